@@ -0,0 +1,76 @@
+//! Node expansions and wall time of the A* search against the same search
+//! without its heuristic, on random constant-product pool graphs.
+//!
+//! Run with `cargo bench --bench pathfinding`.
+
+use std::time::{Duration, Instant};
+
+use solana_program::pubkey::Pubkey;
+use wayfinder::{
+    pathfinding::AStarPathfinder,
+    state::{PoolInfo, CURVE_CONSTANT_PRODUCT, POOL_STATUS_ACTIVE},
+};
+
+const QUERIES: usize = 20;
+
+/// Deterministic pseudo-random generator so runs are comparable
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+}
+
+fn random_pools(rng: &mut Lcg, tokens: &[Pubkey], count: usize) -> Vec<PoolInfo> {
+    (0..count)
+        .map(|_| {
+            let a = rng.next(tokens.len() as u64) as usize;
+            let b = (a + 1 + rng.next(tokens.len() as u64 - 1) as usize) % tokens.len();
+            PoolInfo {
+                address: Pubkey::new_unique(),
+                token_a: tokens[a],
+                token_b: tokens[b],
+                fee_bps: rng.next(100) as u16,
+                reserve_a: 10_000 + rng.next(10_000_000),
+                reserve_b: 10_000 + rng.next(10_000_000),
+                status: POOL_STATUS_ACTIVE,
+                curve_type: CURVE_CONSTANT_PRODUCT,
+                curve_params: [0; 2],
+            }
+        })
+        .collect()
+}
+
+/// Total expansions and time of `QUERIES` searches from the first token
+fn run(pathfinder: &AStarPathfinder, tokens: &[Pubkey]) -> (usize, Duration, Vec<Option<u64>>) {
+    let start = Instant::now();
+    let amounts = (1..=QUERIES)
+        .map(|i| {
+            pathfinder
+                .find_optimal_route(&tokens[0], &tokens[i], 100_000)
+                .ok()
+                .map(|(_, amount_out)| amount_out)
+        })
+        .collect();
+    (pathfinder.expansions(), start.elapsed(), amounts)
+}
+
+fn main() {
+    println!("pools  tokens  hops  expansions (A* / exhaustive)  time (A* / exhaustive)");
+    for (pool_count, token_count, max_hops) in [(500, 100, 3), (1000, 200, 3), (500, 100, 4)] {
+        let mut rng = Lcg(pool_count as u64);
+        let tokens: Vec<Pubkey> = (0..token_count).map(|_| Pubkey::new_unique()).collect();
+        let pools = random_pools(&mut rng, &tokens, pool_count);
+
+        let (expansions, time, amounts) = run(&AStarPathfinder::new(&pools, max_hops), &tokens);
+        let (exhaustive_expansions, exhaustive_time, exhaustive_amounts) =
+            run(&AStarPathfinder::new(&pools, max_hops).exhaustive(), &tokens);
+        assert_eq!(amounts, exhaustive_amounts);
+
+        println!(
+            "{pool_count:>5}  {token_count:>6}  {max_hops:>4}  {expansions:>10} / {exhaustive_expansions:<15}  {time:?} / {exhaustive_time:?}"
+        );
+    }
+}
@@ -0,0 +1,587 @@
+//! Concentrated-liquidity (tick-based) pools and a swap simulator walking
+//! their initialized ticks.
+//!
+//! Prices are square roots in Q64.64 fixed point, of token B per token A,
+//! with tick `i` at a price of 1.0001^i, as in Orca Whirlpools.
+
+use solana_program::pubkey::Pubkey;
+
+/// Fees are in basis points of the input
+const BPS: u128 = 10_000;
+
+/// Lowest tick whose square root price fits Q64.64
+pub const MIN_TICK: i32 = -443_636;
+/// Highest tick whose square root price fits Q64.64
+pub const MAX_TICK: i32 = 443_636;
+
+/// One in Q64.64
+const Q64: u128 = 1 << 64;
+
+/// 1 / sqrt(1.0001)^(2^i) in Q64.64, for each bit `i` of a tick
+const TICK_RATIOS: [u128; 19] = [
+    0xfffcb933bd6fad37,
+    0xfff97272373d4132,
+    0xfff2e50f5f656932,
+    0xffe5caca7e10e4e6,
+    0xffcb9843d60f6159,
+    0xff973b41fa98c081,
+    0xff2ea16466c96a38,
+    0xfe5dee046a99a2a8,
+    0xfcbe86c7900a88ae,
+    0xf987a7253ac41317,
+    0xf3392b0822b70005,
+    0xe7159475a2c29b74,
+    0xd097f3bdfd2022b8,
+    0xa9f746462d870fdf,
+    0x70d869a156d2a1b8,
+    0x31be135f97d08fd9,
+    0x09aa508b5b7a84e1,
+    0x005d6af8dedb8119,
+    0x00002216e584f5fa,
+];
+
+/// Square root price of `tick` in Q64.64, or `None` outside
+/// `MIN_TICK..=MAX_TICK`
+pub fn sqrt_price_at_tick(tick: i32) -> Option<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+
+    // 1 / sqrt(1.0001)^|tick|, from the ratios of the bits set in it. Each
+    // factor is below one, so the products stay within 128 bits.
+    let magnitude = tick.unsigned_abs();
+    let mut ratio = Q64;
+    for (bit, factor) in TICK_RATIOS.iter().enumerate() {
+        if magnitude & (1 << bit) != 0 {
+            ratio = (ratio * factor) >> 64;
+        }
+    }
+
+    Some(if tick > 0 { u128::MAX / ratio } else { ratio })
+}
+
+/// Highest tick whose square root price is at most `sqrt_price_x64`,
+/// clamped to `MIN_TICK..=MAX_TICK`
+pub fn tick_at_sqrt_price(sqrt_price_x64: u128) -> i32 {
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(mid).is_some_and(|price| price <= sqrt_price_x64) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+/// `a * b / denominator` with a 256-bit intermediate product, rounded up or
+/// down, or `None` if the quotient overflows or `denominator` is zero
+fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+
+    // 128x128 -> 256-bit product from 64-bit limbs
+    let (a_high, a_low) = (a >> 64, a & (Q64 - 1));
+    let (b_high, b_low) = (b >> 64, b & (Q64 - 1));
+    let low_low = a_low * b_low;
+    let (middle, middle_carry) = (a_low * b_high).overflowing_add(a_high * b_low);
+    let (low, low_carry) = low_low.overflowing_add(middle << 64);
+    let high = a_high * b_high + (middle >> 64) + ((middle_carry as u128) << 64) + low_carry as u128;
+
+    let (quotient, remainder) = if high == 0 {
+        (low / denominator, low % denominator)
+    } else {
+        if high >= denominator {
+            return None;
+        }
+        // Long division, one bit of the low half at a time
+        let (mut quotient, mut remainder) = (0u128, high);
+        for bit in (0..128).rev() {
+            let carry = remainder >> 127;
+            remainder = (remainder << 1) | ((low >> bit) & 1);
+            quotient <<= 1;
+            if carry == 1 || remainder >= denominator {
+                remainder = remainder.wrapping_sub(denominator);
+                quotient |= 1;
+            }
+        }
+        (quotient, remainder)
+    };
+
+    if round_up && remainder != 0 {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Token A paid in or out moving between two square root prices with
+/// `liquidity`: L * (upper - lower) / (upper * lower)
+fn amount_a_delta(price_0: u128, price_1: u128, liquidity: u128, round_up: bool) -> Option<u128> {
+    let (lower, upper) = (price_0.min(price_1), price_0.max(price_1));
+    if lower == 0 {
+        return None;
+    }
+    let scaled = mul_div(liquidity, upper - lower, upper, round_up)?;
+    mul_div(scaled, Q64, lower, round_up)
+}
+
+/// Token B paid in or out moving between two square root prices with
+/// `liquidity`: L * (upper - lower)
+fn amount_b_delta(price_0: u128, price_1: u128, liquidity: u128, round_up: bool) -> Option<u128> {
+    let (lower, upper) = (price_0.min(price_1), price_0.max(price_1));
+    mul_div(liquidity, upper - lower, Q64, round_up)
+}
+
+/// Price reached by adding `amount` of token A (`a_to_b`) or B, rounded so
+/// the pool never pays for more than it received
+fn price_after_input(price: u128, liquidity: u128, amount: u128, a_to_b: bool) -> Option<u128> {
+    if amount == 0 {
+        return Some(price);
+    }
+    if a_to_b {
+        // L * p / (L + amount * p)
+        let denominator = liquidity.checked_add(mul_div(amount, price, Q64, false)?)?;
+        mul_div(liquidity, price, denominator, true)
+    } else {
+        price.checked_add(mul_div(amount, Q64, liquidity, false)?)
+    }
+}
+
+/// Price reached by taking `amount` of token B (`a_to_b`) or A out, rounded
+/// so the pool is always paid for at least that much
+fn price_after_output(price: u128, liquidity: u128, amount: u128, a_to_b: bool) -> Option<u128> {
+    if amount == 0 {
+        return Some(price);
+    }
+    if a_to_b {
+        price.checked_sub(mul_div(amount, Q64, liquidity, true)?)
+    } else {
+        // L * p / (L - amount * p)
+        let denominator = liquidity.checked_sub(mul_div(amount, price, Q64, true)?)?;
+        if denominator == 0 {
+            return None;
+        }
+        mul_div(liquidity, price, denominator, true)
+    }
+}
+
+/// Fee charged on top of a swap step's `amount_in`, rounded up
+fn fee_on(amount_in: u128, fee_bps: u16) -> Option<u128> {
+    mul_div(amount_in, fee_bps as u128, BPS.checked_sub(fee_bps as u128)?, true)
+}
+
+/// Initialized tick of a concentrated-liquidity pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TickSnapshot {
+    /// Tick index
+    pub index: i32,
+
+    /// Liquidity added when the price crosses the tick upwards, and removed
+    /// when it crosses downwards
+    pub liquidity_net: i128,
+}
+
+/// Price, in-range liquidity and current tick of a concentrated-liquidity
+/// pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClmmState {
+    /// Square root of the price of token A in token B, Q64.64
+    pub sqrt_price_x64: u128,
+
+    /// Liquidity of the positions in range at the current price
+    pub liquidity: u128,
+
+    /// Tick the current price falls in
+    pub tick_current: i32,
+}
+
+/// Concentrated-liquidity pool with a snapshot of its initialized ticks
+/// around the current price
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClmmPool {
+    /// Pool address
+    pub address: Pubkey,
+
+    /// Token A mint
+    pub token_a: Pubkey,
+
+    /// Token B mint
+    pub token_b: Pubkey,
+
+    /// Fee in basis points of the input
+    pub fee_bps: u16,
+
+    /// Current price and liquidity
+    pub state: ClmmState,
+
+    /// Initialized ticks in ascending order. Swaps are only simulated as
+    /// far as the last tick in their direction: liquidity past it is
+    /// unknown.
+    pub ticks: Vec<TickSnapshot>,
+}
+
+/// Simulated swap through a concentrated-liquidity pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClmmSwap {
+    /// Input including fees
+    pub amount_in: u64,
+
+    /// Output
+    pub amount_out: u64,
+
+    /// Initialized ticks crossed, each costing the swap extra compute
+    pub ticks_crossed: u32,
+
+    /// Pool state the swap leaves behind
+    pub state: ClmmState,
+}
+
+impl ClmmPool {
+    /// Whether swapping from `input_mint` sells token A, or `None` if the
+    /// pool does not trade it
+    fn direction(&self, input_mint: &Pubkey) -> Option<bool> {
+        if *input_mint == self.token_a {
+            Some(true)
+        } else if *input_mint == self.token_b {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_other_token(&self, token: &Pubkey) -> Option<Pubkey> {
+        match self.direction(token)? {
+            true => Some(self.token_b),
+            false => Some(self.token_a),
+        }
+    }
+
+    /// Next initialized tick the price reaches moving down (`a_to_b`) or up
+    /// from `state`
+    fn next_tick(&self, state: &ClmmState, a_to_b: bool) -> Option<&TickSnapshot> {
+        if a_to_b {
+            self.ticks
+                .iter()
+                .rev()
+                .find(|tick| tick.index <= state.tick_current)
+        } else {
+            self.ticks.iter().find(|tick| tick.index > state.tick_current)
+        }
+    }
+
+    /// Swap exactly `amount_in` of `input_mint` at the current state
+    pub fn swap_exact_in(&self, input_mint: &Pubkey, amount_in: u64) -> Option<ClmmSwap> {
+        self.swap_exact_in_from(&self.state, input_mint, amount_in)
+    }
+
+    /// Buy exactly `amount_out` of the other token with `input_mint` at the
+    /// current state
+    pub fn swap_exact_out(&self, input_mint: &Pubkey, amount_out: u64) -> Option<ClmmSwap> {
+        self.swap_exact_out_from(&self.state, input_mint, amount_out)
+    }
+
+    /// Swap exactly `amount_in` of `input_mint` starting from `state`,
+    /// walking the price tick by tick and charging the fee on each step's
+    /// input. `None` if the swap would run past the snapshot's ticks.
+    pub fn swap_exact_in_from(
+        &self,
+        state: &ClmmState,
+        input_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<ClmmSwap> {
+        self.simulate(state, input_mint, amount_in, true)
+    }
+
+    /// Buy exactly `amount_out` of the other token with `input_mint`
+    /// starting from `state`. `None` if the snapshot's ticks do not hold
+    /// that much.
+    pub fn swap_exact_out_from(
+        &self,
+        state: &ClmmState,
+        input_mint: &Pubkey,
+        amount_out: u64,
+    ) -> Option<ClmmSwap> {
+        self.simulate(state, input_mint, amount_out, false)
+    }
+
+    /// Walk the swap step by step, each ending at the next initialized tick
+    /// or where the amount runs out. `amount` is the input for `exact_in`,
+    /// otherwise the output.
+    fn simulate(
+        &self,
+        state: &ClmmState,
+        input_mint: &Pubkey,
+        amount: u64,
+        exact_in: bool,
+    ) -> Option<ClmmSwap> {
+        let a_to_b = self.direction(input_mint)?;
+        let fee_factor = BPS.checked_sub(self.fee_bps as u128)?;
+
+        let mut state = *state;
+        let mut remaining = amount as u128;
+        let (mut total_in, mut total_out) = (0u128, 0u128);
+        let mut ticks_crossed = 0u32;
+
+        while remaining > 0 {
+            let tick = *self.next_tick(&state, a_to_b)?;
+            let target = sqrt_price_at_tick(tick.index)?;
+            let price = state.sqrt_price_x64;
+
+            // Input (before fees) and output moving from `price` to `next`
+            let amount_in_to = |next: u128| {
+                if a_to_b {
+                    amount_a_delta(price, next, state.liquidity, true)
+                } else {
+                    amount_b_delta(price, next, state.liquidity, true)
+                }
+            };
+            let amount_out_to = |next: u128| {
+                if a_to_b {
+                    amount_b_delta(price, next, state.liquidity, false)
+                } else {
+                    amount_a_delta(price, next, state.liquidity, false)
+                }
+            };
+
+            let (next, step_in, step_out, fee);
+            if exact_in {
+                let available = remaining * fee_factor / BPS;
+                let to_target = amount_in_to(target)?;
+                if available >= to_target {
+                    next = target;
+                    step_in = to_target;
+                    fee = fee_on(step_in, self.fee_bps)?;
+                } else {
+                    next = price_after_input(price, state.liquidity, available, a_to_b)?;
+                    step_in = amount_in_to(next)?;
+                    // The rest of the input goes to fees
+                    fee = remaining.checked_sub(step_in)?;
+                }
+                step_out = amount_out_to(next)?;
+                remaining -= step_in + fee;
+            } else {
+                let to_target = amount_out_to(target)?;
+                if remaining >= to_target {
+                    next = target;
+                    step_out = to_target;
+                } else {
+                    next = price_after_output(price, state.liquidity, remaining, a_to_b)?;
+                    step_out = amount_out_to(next)?.min(remaining);
+                }
+                step_in = amount_in_to(next)?;
+                fee = fee_on(step_in, self.fee_bps)?;
+                remaining -= step_out;
+            }
+
+            total_in = total_in.checked_add(step_in + fee)?;
+            total_out = total_out.checked_add(step_out)?;
+            state.sqrt_price_x64 = next;
+
+            if next == target {
+                // Cross the tick, bringing positions into or out of range
+                let liquidity_net = if a_to_b {
+                    tick.liquidity_net.checked_neg()?
+                } else {
+                    tick.liquidity_net
+                };
+                state.liquidity = state.liquidity.checked_add_signed(liquidity_net)?;
+                state.tick_current = if a_to_b { tick.index - 1 } else { tick.index };
+                ticks_crossed += 1;
+            } else {
+                state.tick_current = tick_at_sqrt_price(next);
+            }
+        }
+
+        Some(ClmmSwap {
+            amount_in: u64::try_from(total_in).ok()?,
+            amount_out: u64::try_from(total_out).ok()?,
+            ticks_crossed,
+            state,
+        })
+    }
+
+    /// Output per unit of input for an infinitesimal trade from `state`,
+    /// after fees. Prices only move against a swap, so `amount_in` times
+    /// this rate bounds its output from above.
+    pub fn spot_rate_from(&self, state: &ClmmState, input_mint: &Pubkey) -> Option<f64> {
+        let a_to_b = self.direction(input_mint)?;
+        let price = (state.sqrt_price_x64 as f64 / Q64 as f64).powi(2);
+        let fee_factor = (BPS as f64 - self.fee_bps as f64) / BPS as f64;
+        if fee_factor < 0.0 {
+            return None;
+        }
+        Some(if a_to_b { price } else { 1.0 / price } * fee_factor)
+    }
+
+    /// `spot_rate_from` at the current state
+    pub fn spot_rate(&self, input_mint: &Pubkey) -> Option<f64> {
+        self.spot_rate_from(&self.state, input_mint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{ConstantProduct, SwapCurve};
+
+    /// Pool at tick 0 with `positions`, each a tick range and its liquidity
+    fn pool(fee_bps: u16, tick_current: i32, positions: &[(i32, i32, u128)]) -> ClmmPool {
+        let mut ticks: Vec<TickSnapshot> = Vec::new();
+        let mut liquidity = 0u128;
+        for &(lower, upper, amount) in positions {
+            for (index, net) in [(lower, amount as i128), (upper, -(amount as i128))] {
+                match ticks.iter_mut().find(|tick| tick.index == index) {
+                    Some(tick) => tick.liquidity_net += net,
+                    None => ticks.push(TickSnapshot {
+                        index,
+                        liquidity_net: net,
+                    }),
+                }
+            }
+            if (lower..upper).contains(&tick_current) {
+                liquidity += amount;
+            }
+        }
+        ticks.sort_by_key(|tick| tick.index);
+
+        ClmmPool {
+            address: Pubkey::new_unique(),
+            token_a: Pubkey::new_unique(),
+            token_b: Pubkey::new_unique(),
+            fee_bps,
+            state: ClmmState {
+                sqrt_price_x64: sqrt_price_at_tick(tick_current).unwrap(),
+                liquidity,
+                tick_current,
+            },
+            ticks,
+        }
+    }
+
+    #[test]
+    fn test_sqrt_price_at_tick() {
+        assert_eq!(sqrt_price_at_tick(0), Some(Q64));
+        assert_eq!(sqrt_price_at_tick(MAX_TICK + 1), None);
+        assert_eq!(sqrt_price_at_tick(MIN_TICK - 1), None);
+
+        let mut previous = 0;
+        for tick in (MIN_TICK..=MAX_TICK).step_by(7_919) {
+            let price = sqrt_price_at_tick(tick).unwrap();
+            assert!(price > previous);
+            previous = price;
+
+            let expected = 1.0001f64.powf(tick as f64 / 2.0) * Q64 as f64;
+            assert!((price as f64 / expected - 1.0).abs() < 1e-9, "tick {tick}");
+            assert_eq!(tick_at_sqrt_price(price), tick);
+            if tick > MIN_TICK {
+                assert_eq!(tick_at_sqrt_price(price - 1), tick - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div(6, 7, 4, false), Some(10));
+        assert_eq!(mul_div(6, 7, 4, true), Some(11));
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, false), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 3, 4, false), Some(u128::MAX / 4 * 3 + 2));
+        assert_eq!(mul_div(1 << 100, 1 << 100, 1 << 80, false), Some(1 << 120));
+        assert_eq!(mul_div(u128::MAX, 2, 1, false), None);
+        assert_eq!(mul_div(1, 1, 0, false), None);
+    }
+
+    #[test]
+    fn test_full_range_position_is_constant_product() {
+        // Virtual reserves of L = 10^9 at tick 0: 10^9 of each token
+        let pool = pool(30, 0, &[(MIN_TICK, MAX_TICK, 1_000_000_000)]);
+        for input_mint in [pool.token_a, pool.token_b] {
+            for amount in [1_000, 1_000_000, 250_000_000] {
+                let expected = ConstantProduct::default()
+                    .output_amount(1_000_000_000, 1_000_000_000, 30, amount)
+                    .unwrap();
+                let swap = pool.swap_exact_in(&input_mint, amount).unwrap();
+                assert_eq!(swap.amount_in, amount);
+                assert!(expected.abs_diff(swap.amount_out) <= 2, "{expected} {swap:?}");
+                assert_eq!(swap.ticks_crossed, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_swap_crosses_initialized_ticks() {
+        let wide = 1_000_000_000;
+        let narrow = 50_000_000_000;
+        let pool = pool(30, 0, &[(MIN_TICK, MAX_TICK, wide), (-60, 60, narrow)]);
+        assert_eq!(pool.state.liquidity, wide + narrow);
+
+        // A small swap stays in range
+        let small = pool.swap_exact_in(&pool.token_a, 1_000_000).unwrap();
+        assert_eq!(small.ticks_crossed, 0);
+        assert_eq!(small.state.liquidity, wide + narrow);
+        assert!(small.amount_out > 990_000);
+
+        // A large one leaves the narrow position behind
+        let large = pool.swap_exact_in(&pool.token_a, 500_000_000).unwrap();
+        assert_eq!(large.ticks_crossed, 1);
+        assert_eq!(large.state.liquidity, wide);
+        assert!(large.state.tick_current < -60);
+        assert_eq!(
+            large.state.tick_current,
+            tick_at_sqrt_price(large.state.sqrt_price_x64)
+        );
+
+        // Continuing from the state a swap leaves behind is no better than
+        // swapping the total at once
+        let first = pool.swap_exact_in(&pool.token_a, 200_000_000).unwrap();
+        let second = pool
+            .swap_exact_in_from(&first.state, &pool.token_a, 300_000_000)
+            .unwrap();
+        assert!(first.amount_out + second.amount_out <= large.amount_out + 2);
+        assert!(first.amount_out + second.amount_out + 2 >= large.amount_out);
+
+        // Past the snapshot's last tick the swap cannot be priced
+        assert_eq!(pool.swap_exact_in(&pool.token_a, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_exact_out_inverts_exact_in() {
+        let pool = pool(
+            25,
+            120,
+            &[
+                (MIN_TICK, MAX_TICK, 2_000_000_000),
+                (-600, 600, 30_000_000_000),
+                (0, 1_200, 10_000_000_000),
+            ],
+        );
+        for input_mint in [pool.token_a, pool.token_b] {
+            for amount_out in [1, 77_777, 5_000_000, 900_000_000] {
+                let swap = pool.swap_exact_out(&input_mint, amount_out).unwrap();
+                assert_eq!(swap.amount_out, amount_out);
+
+                let paid = pool.swap_exact_in(&input_mint, swap.amount_in).unwrap();
+                assert!(paid.amount_out >= amount_out, "{swap:?} {paid:?}");
+                let short = pool.swap_exact_in(&input_mint, swap.amount_in - 1).unwrap();
+                assert!(short.amount_out <= amount_out);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spot_rate_bounds_output() {
+        let pool = pool(30, -2_000, &[(-4_000, 4_000, 5_000_000_000)]);
+        for input_mint in [pool.token_a, pool.token_b] {
+            let rate = pool.spot_rate(&input_mint).unwrap();
+            for amount_in in [1_000, 10_000, 10_000_000] {
+                let swap = pool.swap_exact_in(&input_mint, amount_in).unwrap();
+                assert!(swap.amount_out as f64 <= amount_in as f64 * rate);
+                // Close to it while the price barely moves, up to the
+                // rounding of the fee and the output
+                assert!(swap.amount_out as f64 + 2.0 > amount_in as f64 * rate * 0.99);
+            }
+        }
+    }
+}
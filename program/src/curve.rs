@@ -0,0 +1,590 @@
+//! Swap curves pricing trades against a pool's reserves
+
+/// Fees are in basis points of the input
+const BPS: u128 = 10_000;
+
+/// Most Newton iterations the StableSwap invariant is solved with
+const MAX_NEWTON_ITERATIONS: usize = 256;
+
+/// Relative amount shaved off weighted pool outputs so floating-point
+/// rounding never takes them above the exact curve
+const WEIGHTED_ROUNDING: f64 = 1e-12;
+
+/// Pricing of swaps against a pool's reserves in one direction.
+///
+/// Outputs must grow with the input at a falling rate per unit, so that
+/// `spot_rate`, with `fee_rounding`, bounds them from above: the
+/// pathfinder's heuristic and its pruning of dominated paths both rely on it.
+pub trait SwapCurve {
+    /// Output for `amount_in`, after a fee of `fee_bps` on the input
+    fn output_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_in: u64,
+    ) -> Option<u64>;
+
+    /// Smallest input for which `output_amount` is at least `amount_out`, or
+    /// `None` if the pool cannot pay that much out. Searched for over
+    /// `output_amount` unless the curve inverts in closed form.
+    fn input_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_out: u64,
+    ) -> Option<u64> {
+        if amount_out == 0 {
+            return Some(0);
+        }
+        if amount_out >= reserve_out {
+            return None;
+        }
+
+        let reaches = |amount_in: u64| {
+            self.output_amount(reserve_in, reserve_out, fee_bps, amount_in)
+                .map(|output| output >= amount_out)
+        };
+
+        // Double until the output is reached, then bisect
+        let mut high = 1u64;
+        while !reaches(high)? {
+            high = high.checked_mul(2)?;
+        }
+        let mut low = high / 2;
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if reaches(mid)? {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+
+        Some(high)
+    }
+
+    /// Output per unit of input for an infinitesimal trade, after fees
+    fn spot_rate(&self, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Option<f64>;
+
+    /// Most units of input the rounding of the fees can leave a trade beyond
+    /// its exact share after them. Outputs are at most the input times
+    /// `spot_rate`, plus this many units at the spot rate before fees.
+    fn fee_rounding(&self, _fee_bps: u16) -> u64 {
+        0
+    }
+}
+
+/// Input left after a fee of `fee_bps`, rounded down
+fn amount_after_fee(amount_in: u64, fee_bps: u16) -> Option<u128> {
+    Some((amount_in as u128).checked_mul(BPS.checked_sub(fee_bps as u128)?)? / BPS)
+}
+
+fn fee_factor(fee_bps: u16) -> Option<f64> {
+    Some(10000u16.checked_sub(fee_bps)? as f64 / 10000.0)
+}
+
+/// Token-swap fee of `fee_bps` on `amount`: rounded down, but at least 1
+/// whenever a fee is charged
+fn token_swap_fee(amount: u128, fee_bps: u16) -> u128 {
+    if fee_bps == 0 || amount == 0 {
+        return 0;
+    }
+    (amount * fee_bps as u128 / BPS).max(1)
+}
+
+/// x*y=k: the product of the reserves never falls. Rounds as the token-swap
+/// program does: `owner_fee_bps` of the fee is the pool owner's and charged
+/// apart from the rest, and the new output reserve is rounded up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConstantProduct {
+    pub owner_fee_bps: u16,
+}
+
+impl ConstantProduct {
+    /// Input left after the trade and owner fees, each charged on the whole
+    /// input
+    fn amount_less_fees(&self, amount_in: u64, fee_bps: u16) -> Option<u128> {
+        let trade_fee_bps = fee_bps.checked_sub(self.owner_fee_bps)?;
+        let amount_in = amount_in as u128;
+        amount_in.checked_sub(
+            token_swap_fee(amount_in, trade_fee_bps) + token_swap_fee(amount_in, self.owner_fee_bps),
+        )
+    }
+}
+
+impl SwapCurve for ConstantProduct {
+    fn output_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_in: u64,
+    ) -> Option<u64> {
+        let amount_in = self.amount_less_fees(amount_in, fee_bps)?;
+        let invariant = (reserve_in as u128).checked_mul(reserve_out as u128)?;
+        let new_reserve_in = (reserve_in as u128).checked_add(amount_in)?;
+
+        // New output reserve rounded up. Like token-swap's `checked_ceil_div`
+        // it fails rather than round a zero reserve up to 1, and token-swap
+        // rejects trades paying nothing out.
+        if invariant.checked_div(new_reserve_in)? == 0 {
+            return None;
+        }
+        let new_reserve_out = invariant.div_ceil(new_reserve_in);
+
+        match (reserve_out as u128).checked_sub(new_reserve_out)? {
+            0 => None,
+            amount_out => Some(amount_out as u64),
+        }
+    }
+
+    fn input_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_out: u64,
+    ) -> Option<u64> {
+        if amount_out == 0 {
+            return Some(0);
+        }
+        if amount_out >= reserve_out {
+            return None;
+        }
+
+        // Smallest input after fees that leaves the new output reserve, rounded
+        // up, at most reserve_out - amount_out
+        let invariant = (reserve_in as u128).checked_mul(reserve_out as u128)?;
+        let amount_less_fees = invariant
+            .div_ceil((reserve_out - amount_out) as u128)
+            .checked_sub(reserve_in as u128)?;
+
+        // The fees are each within a unit of their exact share of the input,
+        // which brackets the smallest input leaving that much after them
+        let fee_factor = BPS.checked_sub(fee_bps as u128)?;
+        if fee_factor == 0 {
+            return None;
+        }
+        let mut low = amount_less_fees;
+        let mut high = amount_less_fees
+            .checked_add(2)?
+            .checked_mul(BPS)?
+            .div_ceil(fee_factor);
+        let reaches = |amount_in: u128| {
+            u64::try_from(amount_in)
+                .ok()
+                .and_then(|amount_in| self.amount_less_fees(amount_in, fee_bps))
+                .is_some_and(|amount| amount >= amount_less_fees)
+        };
+        if reaches(low) {
+            return u64::try_from(low).ok();
+        }
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if reaches(mid) {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+
+        u64::try_from(high).ok()
+    }
+
+    fn spot_rate(&self, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Option<f64> {
+        if reserve_in == 0 {
+            return Some(if reserve_out == 0 { 0.0 } else { f64::INFINITY });
+        }
+        Some(reserve_out as f64 / reserve_in as f64 * fee_factor(fee_bps)?)
+    }
+
+    /// Each fee charged is rounded down, so is less than a unit short of its
+    /// exact share of the input
+    fn fee_rounding(&self, fee_bps: u16) -> u64 {
+        u64::from(fee_bps > self.owner_fee_bps) + u64::from(self.owner_fee_bps > 0)
+    }
+}
+
+/// StableSwap invariant of two tokens with amplification coefficient `amp`:
+/// Ann*(x + y) + D = Ann*D + D^3 / (4*x*y) with Ann = 2*amp. Close to a
+/// constant sum near balanced reserves, the more so the higher `amp`, and
+/// to a constant product away from them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StableSwap {
+    pub amp: u64,
+}
+
+impl StableSwap {
+    fn ann(&self) -> Option<u128> {
+        match (self.amp as u128).checked_mul(2)? {
+            0 => None,
+            ann => Some(ann),
+        }
+    }
+
+    /// Invariant D of reserves `x` and `y`, by Newton's method from D = x + y
+    fn invariant(&self, x: u128, y: u128) -> Option<u128> {
+        if x == 0 || y == 0 {
+            return None;
+        }
+        let ann = self.ann()?;
+        let sum = x.checked_add(y)?;
+
+        let mut d = sum;
+        for _ in 0..MAX_NEWTON_ITERATIONS {
+            // D^3 / (4*x*y)
+            let d_p = d.checked_mul(d)? / x.checked_mul(2)?;
+            let d_p = d_p.checked_mul(d)? / y.checked_mul(2)?;
+
+            let previous = d;
+            let numerator = ann
+                .checked_mul(sum)?
+                .checked_add(d_p.checked_mul(2)?)?
+                .checked_mul(d)?;
+            let denominator = (ann - 1).checked_mul(d)?.checked_add(d_p.checked_mul(3)?)?;
+            d = numerator.checked_div(denominator)?;
+
+            if d.abs_diff(previous) <= 1 {
+                return Some(d);
+            }
+        }
+        None
+    }
+
+    /// Reserve y that keeps invariant `d` with reserve `x`, solving
+    /// y^2 + (x + D/Ann - D)*y = D^3 / (4*x*Ann) by Newton's method
+    fn other_reserve(&self, x: u128, d: u128) -> Option<u128> {
+        let ann = self.ann()?;
+        let c = d.checked_mul(d)? / x.checked_mul(2)?;
+        let c = c.checked_mul(d)? / ann.checked_mul(2)?;
+        let b = x.checked_add(d / ann)?;
+
+        let mut y = d;
+        for _ in 0..MAX_NEWTON_ITERATIONS {
+            let previous = y;
+            let numerator = y.checked_mul(y)?.checked_add(c)?;
+            let denominator = y.checked_mul(2)?.checked_add(b)?.checked_sub(d)?;
+            y = numerator.checked_div(denominator)?;
+
+            if y.abs_diff(previous) <= 1 {
+                return Some(y);
+            }
+        }
+        None
+    }
+}
+
+impl SwapCurve for StableSwap {
+    fn output_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_in: u64,
+    ) -> Option<u64> {
+        let d = self.invariant(reserve_in as u128, reserve_out as u128)?;
+        let amount_in = amount_after_fee(amount_in, fee_bps)?;
+        if amount_in == 0 {
+            return Some(0);
+        }
+
+        let new_reserve_out =
+            self.other_reserve((reserve_in as u128).checked_add(amount_in)?, d)?;
+        // One less than the difference, so the solver's rounding never pays
+        // out more than the curve
+        Some(
+            (reserve_out as u128)
+                .saturating_sub(new_reserve_out)
+                .saturating_sub(1) as u64,
+        )
+    }
+
+    fn spot_rate(&self, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Option<f64> {
+        let d = self.invariant(reserve_in as u128, reserve_out as u128)? as f64;
+        let ann = self.ann()? as f64;
+        let (x, y) = (reserve_in as f64, reserve_out as f64);
+
+        // Ratio of the invariant's partial derivatives in x and y
+        let d_p = d / (2.0 * x) * d / (2.0 * y) * d;
+        Some((ann + d_p / x) / (ann + d_p / y) * fee_factor(fee_bps)?)
+    }
+}
+
+/// Weighted (Balancer-style) invariant x^weight_in * y^weight_out = k: a
+/// constant product when the weights are equal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weighted {
+    pub weight_in: u64,
+    pub weight_out: u64,
+}
+
+impl SwapCurve for Weighted {
+    fn output_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_in: u64,
+    ) -> Option<u64> {
+        if reserve_in == 0 || self.weight_in == 0 || self.weight_out == 0 {
+            return None;
+        }
+
+        // reserve_out * (1 - (reserve_in / (reserve_in + amount_in))^(weight_in / weight_out))
+        let amount_in = amount_in as f64 * fee_factor(fee_bps)?;
+        let exponent = self.weight_in as f64 / self.weight_out as f64;
+        let share = -(-exponent * (amount_in / reserve_in as f64).ln_1p()).exp_m1();
+        let amount_out = reserve_out as f64 * share * (1.0 - WEIGHTED_ROUNDING);
+
+        Some((amount_out.floor() as u64).min(reserve_out.saturating_sub(1)))
+    }
+
+    fn spot_rate(&self, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Option<f64> {
+        if reserve_in == 0 || self.weight_in == 0 || self.weight_out == 0 {
+            return None;
+        }
+        let rate = (reserve_out as f64 / self.weight_out as f64)
+            / (reserve_in as f64 / self.weight_in as f64);
+        Some(rate * fee_factor(fee_bps)?)
+    }
+}
+
+/// Curve of a registered pool, in one swap direction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolCurve {
+    ConstantProduct(ConstantProduct),
+    StableSwap(StableSwap),
+    Weighted(Weighted),
+}
+
+impl SwapCurve for PoolCurve {
+    fn output_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_in: u64,
+    ) -> Option<u64> {
+        match self {
+            Self::ConstantProduct(curve) => {
+                curve.output_amount(reserve_in, reserve_out, fee_bps, amount_in)
+            }
+            Self::StableSwap(curve) => {
+                curve.output_amount(reserve_in, reserve_out, fee_bps, amount_in)
+            }
+            Self::Weighted(curve) => {
+                curve.output_amount(reserve_in, reserve_out, fee_bps, amount_in)
+            }
+        }
+    }
+
+    fn input_amount(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+        amount_out: u64,
+    ) -> Option<u64> {
+        match self {
+            Self::ConstantProduct(curve) => {
+                curve.input_amount(reserve_in, reserve_out, fee_bps, amount_out)
+            }
+            Self::StableSwap(curve) => {
+                curve.input_amount(reserve_in, reserve_out, fee_bps, amount_out)
+            }
+            Self::Weighted(curve) => {
+                curve.input_amount(reserve_in, reserve_out, fee_bps, amount_out)
+            }
+        }
+    }
+
+    fn spot_rate(&self, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Option<f64> {
+        match self {
+            Self::ConstantProduct(curve) => curve.spot_rate(reserve_in, reserve_out, fee_bps),
+            Self::StableSwap(curve) => curve.spot_rate(reserve_in, reserve_out, fee_bps),
+            Self::Weighted(curve) => curve.spot_rate(reserve_in, reserve_out, fee_bps),
+        }
+    }
+
+    fn fee_rounding(&self, fee_bps: u16) -> u64 {
+        match self {
+            Self::ConstantProduct(curve) => curve.fee_rounding(fee_bps),
+            Self::StableSwap(curve) => curve.fee_rounding(fee_bps),
+            Self::Weighted(curve) => curve.fee_rounding(fee_bps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curves() -> Vec<PoolCurve> {
+        vec![
+            PoolCurve::ConstantProduct(ConstantProduct::default()),
+            PoolCurve::StableSwap(StableSwap { amp: 1 }),
+            PoolCurve::StableSwap(StableSwap { amp: 100 }),
+            PoolCurve::Weighted(Weighted {
+                weight_in: 80,
+                weight_out: 20,
+            }),
+            PoolCurve::Weighted(Weighted {
+                weight_in: 20,
+                weight_out: 80,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_outputs_rise_at_falling_rate_below_spot_rate() {
+        for curve in curves() {
+            for (reserve_in, reserve_out) in [
+                (1_000_000, 1_000_000),
+                (1_000_000, 3_000_000),
+                (5_000_000, 200_000),
+            ] {
+                let spot_rate = curve.spot_rate(reserve_in, reserve_out, 30).unwrap();
+                let (mut previous, mut previous_step) = (0u64, u64::MAX);
+                for amount_in in (1..=20).map(|i| i * 100_000) {
+                    let amount_out = curve
+                        .output_amount(reserve_in, reserve_out, 30, amount_in)
+                        .unwrap();
+                    assert!(amount_out < reserve_out, "{curve:?}");
+                    assert!(
+                        amount_out as f64 <= amount_in as f64 * spot_rate,
+                        "{curve:?}"
+                    );
+
+                    // Each further 100_000 buys no more than the last, up
+                    // to rounding
+                    let step = amount_out - previous;
+                    assert!(step <= previous_step.saturating_add(2), "{curve:?}");
+                    (previous, previous_step) = (amount_out, step);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_input_amount_inverts_output_amount() {
+        for curve in curves() {
+            for amount_out in [1, 999, 250_000, 900_000] {
+                let amount_in = curve
+                    .input_amount(2_000_000, 1_000_000, 30, amount_out)
+                    .unwrap();
+                assert!(
+                    curve
+                        .output_amount(2_000_000, 1_000_000, 30, amount_in)
+                        .unwrap()
+                        >= amount_out
+                );
+                // Token-swap refuses trades paying nothing out
+                assert!(curve
+                    .output_amount(2_000_000, 1_000_000, 30, amount_in - 1)
+                    .map_or(true, |output| output < amount_out));
+            }
+            assert_eq!(
+                curve.input_amount(2_000_000, 1_000_000, 30, 1_000_000),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_constant_product_matches_token_swap() {
+        use spl_token_swap::curve::{
+            base::{CurveType, SwapCurve as TokenSwapCurve},
+            calculator::TradeDirection,
+            constant_product::ConstantProductCurve,
+            fees::Fees,
+        };
+        use std::sync::Arc;
+
+        let token_swap = TokenSwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Arc::new(ConstantProductCurve),
+        };
+        for (trade_fee_bps, owner_fee_bps) in [(0u16, 0u16), (25, 0), (25, 5), (0, 30), (9_000, 500)] {
+            let fees = Fees {
+                trade_fee_numerator: trade_fee_bps as u64,
+                trade_fee_denominator: 10_000,
+                owner_trade_fee_numerator: owner_fee_bps as u64,
+                owner_trade_fee_denominator: 10_000,
+                ..Fees::default()
+            };
+            let curve = ConstantProduct { owner_fee_bps };
+            let fee_bps = trade_fee_bps + owner_fee_bps;
+
+            for (reserve_in, reserve_out) in
+                [(1_000_000, 2_000_000), (7, 5_000_000), (3_000_000, 11), (u32::MAX as u64, 999)]
+            {
+                for amount_in in [1, 2, 3, 99, 400, 12_345, 1_000_000, u32::MAX as u64] {
+                    let expected = token_swap
+                        .swap(
+                            amount_in as u128,
+                            reserve_in as u128,
+                            reserve_out as u128,
+                            TradeDirection::AtoB,
+                            &fees,
+                        )
+                        .map(|result| result.destination_amount_swapped as u64);
+                    assert_eq!(
+                        curve.output_amount(reserve_in, reserve_out, fee_bps, amount_in),
+                        expected,
+                        "{curve:?} {fee_bps} {reserve_in} {reserve_out} {amount_in}"
+                    );
+                }
+
+                for amount_out in [1, reserve_out / 3, reserve_out - 1] {
+                    let amount_in = curve
+                        .input_amount(reserve_in, reserve_out, fee_bps, amount_out)
+                        .unwrap();
+                    assert!(curve
+                        .output_amount(reserve_in, reserve_out, fee_bps, amount_in)
+                        .is_some_and(|output| output >= amount_out));
+                    assert!(curve
+                        .output_amount(reserve_in, reserve_out, fee_bps, amount_in - 1)
+                        .map_or(true, |output| output < amount_out));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stable_swap_trades_near_parity_when_balanced() {
+        let stable = StableSwap { amp: 100 };
+        let amount_out = stable
+            .output_amount(1_000_000, 1_000_000, 0, 100_000)
+            .unwrap();
+        let constant_product = ConstantProduct::default()
+            .output_amount(1_000_000, 1_000_000, 0, 100_000)
+            .unwrap();
+        assert!(amount_out > 99_000);
+        assert!(amount_out > constant_product);
+
+        // A high amplification is flatter still
+        let flatter = StableSwap { amp: 1_000 }
+            .output_amount(1_000_000, 1_000_000, 0, 100_000)
+            .unwrap();
+        assert!(flatter > amount_out);
+    }
+
+    #[test]
+    fn test_weighted_with_equal_weights_is_constant_product() {
+        let weighted = Weighted {
+            weight_in: 50,
+            weight_out: 50,
+        };
+        for amount_in in [1_000, 50_000, 700_000] {
+            let expected = ConstantProduct::default()
+                .output_amount(1_000_000, 2_000_000, 30, amount_in)
+                .unwrap();
+            let amount_out = weighted
+                .output_amount(1_000_000, 2_000_000, 30, amount_in)
+                .unwrap();
+            assert!(expected - amount_out <= 1);
+        }
+    }
+}
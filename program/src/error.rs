@@ -1,7 +1,12 @@
-use solana_program::program_error::ProgramError;
+use num_derive::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
 use thiserror::Error;
 
-#[derive(Error, Debug, Copy, Clone)]
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
 pub enum WayfinderError {
     #[error("Invalid Instruction")]
     InvalidInstruction,
@@ -38,6 +43,27 @@ pub enum WayfinderError {
 
     #[error("Calculation Overflow")]
     CalculationOverflow,
+
+    #[error("Pool Already Registered")]
+    PoolAlreadyRegistered,
+
+    #[error("Pool Not Registered")]
+    PoolNotRegistered,
+
+    #[error("Pool Registry Full")]
+    RegistryFull,
+
+    #[error("Account Layout Outdated")]
+    AccountVersionMismatch,
+
+    #[error("Maximum Paths Exceeded")]
+    MaximumPathsExceeded,
+
+    #[error("Pool Registry Mismatch")]
+    RegistryMismatch,
+
+    #[error("Account Too Large To Migrate")]
+    MigrationTooLarge,
 }
 
 impl From<WayfinderError> for ProgramError {
@@ -52,3 +78,14 @@ impl<T> From<WayfinderError> for Result<T, ProgramError> {
     }
 }
 
+impl<T> DecodeError<T> for WayfinderError {
+    fn type_of() -> &'static str {
+        "WayfinderError"
+    }
+}
+
+impl PrintProgramError for WayfinderError {
+    fn print<E>(&self) {
+        msg!("Error: {}", self);
+    }
+}
@@ -5,12 +5,14 @@ use crate::error::WayfinderError;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub enum WayfinderInstruction {
-    /// Initialize a route optimizer state
+    /// Create and initialize a route state account at the PDA returned by
+    /// `find_route_state_address(program_id, authority, nonce)`
     ///
     /// Accounts expected:
-    /// 0. `[writable]` Route state account
-    /// 1. `[signer]` Authority account
+    /// 0. `[writable]` Route state account (PDA)
+    /// 1. `[signer, writable]` Authority account, funds the route state rent
     /// 2. `[]` System program
+    /// 3. `[]` Pool registry account the route is searched in
     InitializeRoute {
         /// Input token mint
         input_mint: [u8; 32],
@@ -20,37 +22,155 @@ pub enum WayfinderInstruction {
         amount_in: u64,
         /// Minimum output amount (slippage protection)
         min_amount_out: u64,
-        /// Maximum number of hops
+        /// Maximum number of hops, between 1 and `MAX_ROUTE_HOPS`
         max_hops: u8,
+        /// Seed distinguishing route state accounts of the same authority
+        nonce: u64,
     },
 
-    /// Find optimal swap route using A* pathfinding
+    /// Find optimal swap route using A* pathfinding over the active pools of
+    /// a pool registry
     ///
     /// Accounts expected:
     /// 0. `[writable]` Route state account
-    /// 1. `[]` Pool accounts (multiple, dynamic)
+    /// 1. `[signer]` Route authority
+    /// 2. `[]` Pool registry account the route was initialized with
+    ///
+    /// Optionally followed by `[]` Token-2022 mints of the route's tokens.
+    /// Token-swap cannot move mints with the transfer fee extension, so no
+    /// route trades through them.
     FindOptimalRoute,
 
-    /// Execute the found route
+    /// Execute the found route by swapping through each pool via CPI
     ///
     /// Accounts expected:
     /// 0. `[writable]` Route state account
-    /// 1. `[signer]` User authority
+    /// 1. `[signer]` User authority, owner of every user token account below
     /// 2. `[writable]` User input token account
     /// 3. `[writable]` User output token account
-    /// 4-N. `[]` Pool and token accounts for the route
+    /// 4. `[]` SPL Token program
+    /// 5. `[]` SPL Token-2022 program
+    ///
+    /// Followed by one group of accounts per hop, path by path in route order.
+    /// Both mints of a hop belong to the token program of its pool:
+    /// 0. `[]` Token-swap program
+    /// 1. `[]` Pool (token-swap) account
+    /// 2. `[]` Pool swap authority
+    /// 3. `[writable]` Pool vault for the hop's source token
+    /// 4. `[writable]` Pool vault for the hop's destination token
+    /// 5. `[writable]` Pool token mint
+    /// 6. `[writable]` Pool fee account
+    /// 7. `[]` Source token mint
+    /// 8. `[]` Destination token mint
+    /// 9. `[writable]` User destination token account (intermediate, or the
+    ///    output account for the last hop of each path)
     ExecuteRoute,
 
-    /// Register a liquidity pool for pathfinding
+    /// Register a liquidity pool for pathfinding, snapshotting its fee, curve
+    /// and reserves
     ///
     /// Accounts expected:
     /// 0. `[writable]` Pool registry account
-    /// 1. `[signer]` Authority
-    /// 2. `[]` Pool account
+    /// 1. `[signer]` Registry authority
+    /// 2. `[]` Pool (token-swap) account
+    /// 3. `[]` Pool token A vault
+    /// 4. `[]` Pool token B vault
     RegisterPool {
         token_a_mint: [u8; 32],
         token_b_mint: [u8; 32],
-        fee_bps: u16,
+    },
+
+    /// Initialize an empty pool registry in a zeroed, program-owned account
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` Pool registry account
+    /// 1. `[signer]` Registry authority
+    InitializeRegistry,
+
+    /// Update the status of a registered pool and refresh its fee, curve and
+    /// reserves from the pool account and vaults
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` Pool registry account
+    /// 1. `[signer]` Registry authority
+    /// 2. `[]` Pool (token-swap) account
+    /// 3. `[]` Pool token A vault
+    /// 4. `[]` Pool token B vault
+    UpdatePool {
+        /// 1 = active, 2 = paused
+        status: u8,
+    },
+
+    /// Remove a pool from the registry
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` Pool registry account
+    /// 1. `[signer]` Registry authority
+    /// 2. `[]` Pool account
+    DeregisterPool,
+
+    /// Close a route state account in any status, returning its lamports
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` Route state account
+    /// 1. `[signer]` Route authority
+    /// 2. `[writable]` Destination for the reclaimed lamports
+    CloseRoute,
+
+    /// Upgrade a route state or pool registry account written with an older
+    /// layout to the current version in place, reallocating it if the layout
+    /// grew. The authority tops up rent for any added bytes. Registries that
+    /// would grow by more than `MAX_PERMITTED_DATA_INCREASE` bytes fail with
+    /// `MigrationTooLarge`.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` Route state or pool registry account
+    /// 1. `[signer, writable]` Route or registry authority
+    /// 2. `[]` System program
+    /// 3. `[]` Pool registry account the route is searched in, for route
+    ///    state accounts only
+    MigrateAccount,
+
+    /// Like `FindOptimalRoute`, but may split the input across up to
+    /// `max_paths` paths when that yields more output. Paths can share pools;
+    /// they are executed in the stored order.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` Route state account
+    /// 1. `[signer]` Route authority
+    /// 2. `[]` Pool registry account the route was initialized with
+    ///
+    /// Optionally followed by `[]` Token-2022 mints of the route's tokens.
+    /// Token-swap cannot move mints with the transfer fee extension, so no
+    /// route trades through them.
+    FindSplitRoute {
+        /// At most `MAX_SPLIT_PATHS`
+        max_paths: u8,
+    },
+
+    /// Like `InitializeRoute`, but for an exact-output route: the route
+    /// found is the one buying `amount_out` for the least input, which
+    /// execution spends in full and must stay within `max_amount_in`.
+    /// Exact-output routes take a single path.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` Route state account (PDA)
+    /// 1. `[signer, writable]` Authority account, funds the route state rent
+    /// 2. `[]` System program
+    /// 3. `[]` Pool registry account the route is searched in
+    InitializeExactOutRoute {
+        /// Input token mint
+        input_mint: [u8; 32],
+        /// Output token mint
+        output_mint: [u8; 32],
+        /// Output amount to buy
+        amount_out: u64,
+        /// Maximum input amount (slippage protection)
+        max_amount_in: u64,
+        /// Maximum number of hops, between 1 and `MAX_ROUTE_HOPS`
+        max_hops: u8,
+        /// Seed distinguishing route state accounts of the same authority
+        nonce: u64,
     },
 }
 
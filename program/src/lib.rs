@@ -4,10 +4,28 @@ pub mod instruction;
 pub mod processor;
 pub mod state;
 pub mod pathfinding;
+pub mod curve;
+pub mod clmm;
+pub mod orderbook;
+pub mod swap;
+pub mod token;
+
+use solana_program::pubkey::Pubkey;
 
 #[cfg(not(feature = "no-entrypoint"))]
-use solana_program::entrypoint;
+use crate::entrypoint::process_instruction;
 
 #[cfg(not(feature = "no-entrypoint"))]
-entrypoint!(entrypoint::process_instruction);
+solana_program::entrypoint!(process_instruction);
+
+/// Seed prefix of route state accounts
+pub const ROUTE_STATE_SEED: &[u8] = b"route";
 
+/// Derive the route state account created by `InitializeRoute` for
+/// `authority` and `nonce`
+pub fn find_route_state_address(program_id: &Pubkey, authority: &Pubkey, nonce: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ROUTE_STATE_SEED, authority.as_ref(), &nonce.to_le_bytes()],
+        program_id,
+    )
+}
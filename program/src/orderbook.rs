@@ -0,0 +1,394 @@
+//! Central-limit order book markets as routing edges, filled against a
+//! snapshot of their price levels.
+//!
+//! Prices and sizes are in lots, as on OpenBook: a level's price is quote
+//! lots per base lot, its size base lots. The taker fee is charged on the
+//! quote side of every fill.
+
+use solana_program::pubkey::Pubkey;
+
+/// Fees are in basis points of the quote amount
+const BPS: u128 = 10_000;
+
+/// Price level of one side of an order book
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OrderLevel {
+    /// Quote lots per base lot
+    pub price_lots: u64,
+
+    /// Base lots resting at the price
+    pub size_lots: u64,
+}
+
+/// Lots already taken from the top of each side of a book, by earlier fills
+/// against the same snapshot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BookFills {
+    pub bid_lots: u64,
+    pub ask_lots: u64,
+}
+
+/// Order book market with a snapshot of its levels
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderBookMarket {
+    /// Market address
+    pub address: Pubkey,
+
+    /// Base token mint
+    pub base_mint: Pubkey,
+
+    /// Quote token mint
+    pub quote_mint: Pubkey,
+
+    /// Taker fee in basis points
+    pub fee_bps: u16,
+
+    /// Base token amount of one base lot
+    pub base_lot_size: u64,
+
+    /// Quote token amount of one quote lot
+    pub quote_lot_size: u64,
+
+    /// Bids, best (highest) first
+    pub bids: Vec<OrderLevel>,
+
+    /// Asks, best (lowest) first
+    pub asks: Vec<OrderLevel>,
+}
+
+/// Simulated taker fill against an order book
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fill {
+    /// Input used, including fees. Input smaller than a lot, or than the
+    /// cost of one, is left over.
+    pub amount_in: u64,
+
+    /// Output, after fees
+    pub amount_out: u64,
+
+    /// Levels the fill took from, wholly or in part
+    pub levels_consumed: u32,
+
+    /// Lots taken from the book, this fill's included
+    pub fills: BookFills,
+}
+
+/// Levels of `levels` left once `taken` lots are gone from the top, as
+/// (price, size) in lots
+fn remaining_levels(levels: &[OrderLevel], taken: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+    let mut skip = taken;
+    levels.iter().filter_map(move |level| {
+        if skip >= level.size_lots {
+            skip -= level.size_lots;
+            return None;
+        }
+        let size = level.size_lots - skip;
+        skip = 0;
+        Some((level.price_lots, size))
+    })
+}
+
+impl OrderBookMarket {
+    /// Whether swapping from `input_mint` sells the base token, or `None` if
+    /// the market does not trade it
+    fn direction(&self, input_mint: &Pubkey) -> Option<bool> {
+        if *input_mint == self.base_mint {
+            Some(true)
+        } else if *input_mint == self.quote_mint {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_other_token(&self, token: &Pubkey) -> Option<Pubkey> {
+        match self.direction(token)? {
+            true => Some(self.quote_mint),
+            false => Some(self.base_mint),
+        }
+    }
+
+    /// Quote amount of one base lot at `price_lots`
+    fn lot_value(&self, price_lots: u64) -> u128 {
+        price_lots as u128 * self.quote_lot_size as u128
+    }
+
+    /// Fill exactly `amount_in` of `input_mint` against the snapshot
+    pub fn fill_exact_in(&self, input_mint: &Pubkey, amount_in: u64) -> Option<Fill> {
+        self.fill_exact_in_from(&BookFills::default(), input_mint, amount_in)
+    }
+
+    /// Buy exactly `amount_out` of the other token with `input_mint`
+    /// against the snapshot
+    pub fn fill_exact_out(&self, input_mint: &Pubkey, amount_out: u64) -> Option<Fill> {
+        self.fill_exact_out_from(&BookFills::default(), input_mint, amount_out)
+    }
+
+    /// Fill exactly `amount_in` of `input_mint` against what `fills` left of
+    /// the book, best levels first, in whole lots. `None` if the snapshot
+    /// runs out of levels first.
+    pub fn fill_exact_in_from(
+        &self,
+        fills: &BookFills,
+        input_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<Fill> {
+        let sell = self.direction(input_mint)?;
+        let fee_factor = BPS.checked_sub(self.fee_bps as u128)?;
+        let mut fills = *fills;
+        let mut levels_consumed = 0u32;
+        let (mut lots, mut quote) = (0u64, 0u128);
+
+        if sell {
+            // Sell whole base lots into the bids
+            let mut lots_left = amount_in.checked_div(self.base_lot_size)?;
+            for (price, size) in remaining_levels(&self.bids, fills.bid_lots) {
+                if lots_left == 0 {
+                    break;
+                }
+                let take = size.min(lots_left);
+                quote += take as u128 * self.lot_value(price);
+                lots_left -= take;
+                lots += take;
+                levels_consumed += 1;
+            }
+            if lots_left > 0 {
+                return None;
+            }
+            fills.bid_lots = fills.bid_lots.checked_add(lots)?;
+
+            let fee = (quote * self.fee_bps as u128).div_ceil(BPS);
+            Some(Fill {
+                amount_in: lots.checked_mul(self.base_lot_size)?,
+                amount_out: u64::try_from(quote - fee).ok()?,
+                levels_consumed,
+                fills,
+            })
+        } else {
+            // Buy whole base lots from the asks with the input left after
+            // the fee
+            let mut budget = amount_in as u128 * fee_factor / BPS;
+            let mut book_exhausted = true;
+            let mut last_lot_value = 0;
+            for (price, size) in remaining_levels(&self.asks, fills.ask_lots) {
+                let lot_value = self.lot_value(price);
+                if lot_value == 0 {
+                    return None;
+                }
+                let take = (size as u128).min(budget / lot_value) as u64;
+                if take > 0 {
+                    quote += take as u128 * lot_value;
+                    budget -= take as u128 * lot_value;
+                    lots += take;
+                    levels_consumed += 1;
+                }
+                last_lot_value = lot_value;
+                if take < size {
+                    book_exhausted = false;
+                    break;
+                }
+            }
+            // The input could have bought more past the snapshot
+            if book_exhausted && (last_lot_value == 0 || budget >= last_lot_value) {
+                return None;
+            }
+            fills.ask_lots = fills.ask_lots.checked_add(lots)?;
+
+            Some(Fill {
+                amount_in: u64::try_from((quote * BPS).div_ceil(fee_factor)).ok()?,
+                amount_out: lots.checked_mul(self.base_lot_size)?,
+                levels_consumed,
+                fills,
+            })
+        }
+    }
+
+    /// Buy at least `amount_out` of the other token with `input_mint`
+    /// against what `fills` left of the book, for the least input. Outputs
+    /// come in whole lots, so the fill may buy a little more. `None` if the
+    /// snapshot does not hold that much.
+    pub fn fill_exact_out_from(
+        &self,
+        fills: &BookFills,
+        input_mint: &Pubkey,
+        amount_out: u64,
+    ) -> Option<Fill> {
+        let sell = self.direction(input_mint)?;
+        let fee_factor = BPS.checked_sub(self.fee_bps as u128)?;
+        let mut fills = *fills;
+        let mut levels_consumed = 0u32;
+        let (mut lots, mut quote) = (0u64, 0u128);
+
+        if sell {
+            // Quote the bids must pay before the fee for `amount_out` to
+            // remain after it
+            let needed = (amount_out as u128 * BPS).div_ceil(fee_factor);
+            for (price, size) in remaining_levels(&self.bids, fills.bid_lots) {
+                if quote >= needed {
+                    break;
+                }
+                let lot_value = self.lot_value(price);
+                if lot_value == 0 {
+                    return None;
+                }
+                let take = (size as u128).min((needed - quote).div_ceil(lot_value)) as u64;
+                quote += take as u128 * lot_value;
+                lots += take;
+                levels_consumed += 1;
+            }
+            if quote < needed {
+                return None;
+            }
+            fills.bid_lots = fills.bid_lots.checked_add(lots)?;
+
+            let fee = (quote * self.fee_bps as u128).div_ceil(BPS);
+            Some(Fill {
+                amount_in: lots.checked_mul(self.base_lot_size)?,
+                amount_out: u64::try_from(quote - fee).ok()?,
+                levels_consumed,
+                fills,
+            })
+        } else {
+            let mut lots_left = (amount_out as u128).div_ceil(self.base_lot_size as u128) as u64;
+            for (price, size) in remaining_levels(&self.asks, fills.ask_lots) {
+                if lots_left == 0 {
+                    break;
+                }
+                let take = size.min(lots_left);
+                quote += take as u128 * self.lot_value(price);
+                lots_left -= take;
+                lots += take;
+                levels_consumed += 1;
+            }
+            if lots_left > 0 {
+                return None;
+            }
+            fills.ask_lots = fills.ask_lots.checked_add(lots)?;
+
+            Some(Fill {
+                amount_in: u64::try_from((quote * BPS).div_ceil(fee_factor)).ok()?,
+                amount_out: lots.checked_mul(self.base_lot_size)?,
+                levels_consumed,
+                fills,
+            })
+        }
+    }
+
+    /// Output per unit of input at the best level `fills` left, after fees.
+    /// Worse levels follow and lots round down, so `amount_in` times this
+    /// rate bounds a fill's output from above.
+    pub fn spot_rate_from(&self, fills: &BookFills, input_mint: &Pubkey) -> Option<f64> {
+        let sell = self.direction(input_mint)?;
+        let fee_factor = 10000u16.checked_sub(self.fee_bps)? as f64 / 10000.0;
+        let (levels, taken) = if sell {
+            (&self.bids, fills.bid_lots)
+        } else {
+            (&self.asks, fills.ask_lots)
+        };
+        let (price, _) = remaining_levels(levels, taken).next()?;
+
+        // Quote per unit of base at the best price
+        let price = self.lot_value(price) as f64 / self.base_lot_size as f64;
+        Some(if sell { price } else { 1.0 / price } * fee_factor)
+    }
+
+    /// `spot_rate_from` against the whole snapshot
+    pub fn spot_rate(&self, input_mint: &Pubkey) -> Option<f64> {
+        self.spot_rate_from(&BookFills::default(), input_mint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Market with 100-unit base lots and 10-unit quote lots: a price of
+    /// 20 lots is 2 quote per base
+    fn market(fee_bps: u16) -> OrderBookMarket {
+        let level = |price_lots, size_lots| OrderLevel {
+            price_lots,
+            size_lots,
+        };
+        OrderBookMarket {
+            address: Pubkey::new_unique(),
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            fee_bps,
+            base_lot_size: 100,
+            quote_lot_size: 10,
+            bids: vec![level(20, 10), level(19, 20), level(15, 100)],
+            asks: vec![level(21, 10), level(22, 20), level(30, 100)],
+        }
+    }
+
+    #[test]
+    fn test_fill_sells_into_bids_in_whole_lots() {
+        let market = market(0);
+
+        // 12.5 lots: 10 at 20, 2 at 19, the half lot left over
+        let fill = market.fill_exact_in(&market.base_mint, 1_250).unwrap();
+        assert_eq!(fill.amount_in, 1_200);
+        assert_eq!(fill.amount_out, 10 * 200 + 2 * 190);
+        assert_eq!(fill.levels_consumed, 2);
+        assert_eq!(fill.fills.bid_lots, 12);
+
+        // Continuing takes the rest of the second level first
+        let next = market
+            .fill_exact_in_from(&fill.fills, &market.base_mint, 2_000)
+            .unwrap();
+        assert_eq!(next.amount_out, 18 * 190 + 2 * 150);
+        assert_eq!(next.levels_consumed, 2);
+
+        // Beyond the snapshot's depth
+        assert_eq!(market.fill_exact_in(&market.base_mint, 13_100), None);
+        assert_eq!(market.fill_exact_in(&Pubkey::new_unique(), 100), None);
+    }
+
+    #[test]
+    fn test_fill_buys_from_asks_after_fee() {
+        let market = market(50);
+
+        // 3_000 quote less 0.5% buys 10 lots at 210 and 4 at 220
+        let fill = market.fill_exact_in(&market.quote_mint, 3_000).unwrap();
+        assert_eq!(fill.amount_out, 1_400);
+        assert_eq!(fill.levels_consumed, 2);
+        assert_eq!(fill.fills.ask_lots, 14);
+        assert_eq!(fill.amount_in, (2_100u64 + 880) * 10_000 / 9_950 + 1);
+
+        // Selling pays the fee out of the quote received
+        let fill = market.fill_exact_in(&market.base_mint, 1_000).unwrap();
+        assert_eq!(fill.amount_out, 2_000 - 10);
+
+        // Too little to afford a lot
+        let fill = market.fill_exact_in(&market.quote_mint, 100).unwrap();
+        assert_eq!((fill.amount_in, fill.amount_out, fill.levels_consumed), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_fill_exact_out_inverts_fill_exact_in() {
+        let market = market(30);
+        for input_mint in [market.base_mint, market.quote_mint] {
+            for amount_out in [1, 150, 2_345, 9_999] {
+                let fill = market.fill_exact_out(&input_mint, amount_out).unwrap();
+                assert!(fill.amount_out >= amount_out);
+
+                let paid = market.fill_exact_in(&input_mint, fill.amount_in).unwrap();
+                assert_eq!(paid.amount_out, fill.amount_out);
+                let short = market.fill_exact_in(&input_mint, fill.amount_in - 1).unwrap();
+                assert!(short.amount_out < amount_out);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spot_rate_bounds_fill() {
+        let market = market(30);
+        for input_mint in [market.base_mint, market.quote_mint] {
+            let rate = market.spot_rate(&input_mint).unwrap();
+            for amount_in in [100, 1_000, 5_000] {
+                let fill = market.fill_exact_in(&input_mint, amount_in).unwrap();
+                assert!(fill.amount_out as f64 <= amount_in as f64 * rate);
+            }
+        }
+    }
+}
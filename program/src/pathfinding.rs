@@ -1,17 +1,147 @@
-use std::collections::{BinaryHeap, HashMap, HashSet};
-use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::cell::Cell;
+use std::cmp::{Ordering, Reverse};
 use solana_program::pubkey::Pubkey;
 
-use crate::state::{PoolInfo, MAX_ROUTE_HOPS};
+use crate::clmm::{ClmmPool, ClmmState};
+use crate::orderbook::{BookFills, OrderBookMarket};
+use crate::state::{PoolInfo, MAX_ROUTE_HOPS, MAX_SPLIT_PATHS};
+use crate::token::{TransferFee, TransferFeeConfig};
 use crate::error::WayfinderError;
 
 #[derive(Clone, Debug)]
 struct PathNode {
     token: Pubkey,
-    cost: u64, // Negative of amount out (to maximize output)
+    cost: u64, // Negative of the estimated final output (to maximize it)
     hops: u8,
     path: Vec<Pubkey>, // Pool addresses
-    amount_out: u64,
+    visited: Vec<Pubkey>, // Tokens on this path, to keep it simple
+    amount: u64, // Amount of token held, or for exact-output searches needed
+    penalty: u64, // Costs the scoring charges for the hops so far
+    deltas: ReserveDeltas, // Reserves of the pools swapped through so far
+}
+
+/// Copy-on-write reserves: the reserves of pools a path (or earlier paths of
+/// a split) has swapped through, by index into the pathfinder's pools. Pools
+/// not listed still have their registry reserves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReserveDeltas {
+    reserves: Vec<(usize, u64, u64)>,
+    /// States of the concentrated-liquidity pools swapped through
+    concentrated: Vec<(usize, ClmmState)>,
+    ticks_crossed: u32,
+    /// Lots taken from the order books filled against
+    order_books: Vec<(usize, BookFills)>,
+    levels_consumed: u32,
+}
+
+impl ReserveDeltas {
+    /// `pools[index]` with its reserves after the recorded swaps
+    pub fn pool(&self, pools: &[PoolInfo], index: usize) -> PoolInfo {
+        let mut pool = pools[index];
+        if let Some(&(_, reserve_a, reserve_b)) =
+            self.reserves.iter().find(|(i, _, _)| *i == index)
+        {
+            pool.reserve_a = reserve_a;
+            pool.reserve_b = reserve_b;
+        }
+        pool
+    }
+
+    /// Swap through `pools[index]` at its current reserves and record the
+    /// reserves it leaves behind
+    pub fn swap(
+        &mut self,
+        pools: &[PoolInfo],
+        index: usize,
+        input_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<u64> {
+        let mut pool = self.pool(pools, index);
+        let amount_out = pool.apply_swap(input_mint, amount_in)?;
+
+        match self.reserves.iter_mut().find(|(i, _, _)| *i == index) {
+            Some(entry) => *entry = (index, pool.reserve_a, pool.reserve_b),
+            None => self.reserves.push((index, pool.reserve_a, pool.reserve_b)),
+        }
+        Some(amount_out)
+    }
+
+    /// State of the concentrated-liquidity pool at `index` after the
+    /// recorded swaps
+    pub fn concentrated_state(&self, pool: &ClmmPool, index: usize) -> ClmmState {
+        self.concentrated
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map_or(pool.state, |(_, state)| *state)
+    }
+
+    /// Swap through the concentrated-liquidity pool at `index` from its
+    /// current state and record the state and ticks crossed
+    pub fn swap_concentrated(
+        &mut self,
+        pool: &ClmmPool,
+        index: usize,
+        input_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<u64> {
+        let state = self.concentrated_state(pool, index);
+        let swap = pool.swap_exact_in_from(&state, input_mint, amount_in)?;
+
+        match self.concentrated.iter_mut().find(|(i, _)| *i == index) {
+            Some(entry) => entry.1 = swap.state,
+            None => self.concentrated.push((index, swap.state)),
+        }
+        self.ticks_crossed = self.ticks_crossed.saturating_add(swap.ticks_crossed);
+        Some(swap.amount_out)
+    }
+
+    /// Initialized ticks crossed by the recorded swaps
+    pub fn ticks_crossed(&self) -> u32 {
+        self.ticks_crossed
+    }
+
+    /// Lots taken from the order book at `index` by the recorded fills
+    pub fn book_fills(&self, index: usize) -> BookFills {
+        self.order_books
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map_or(BookFills::default(), |(_, fills)| *fills)
+    }
+
+    /// Fill against what earlier fills left of the order book at `index`
+    /// and record the lots and levels taken
+    pub fn fill_order_book(
+        &mut self,
+        market: &OrderBookMarket,
+        index: usize,
+        input_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<u64> {
+        let fill = market.fill_exact_in_from(&self.book_fills(index), input_mint, amount_in)?;
+
+        match self.order_books.iter_mut().find(|(i, _)| *i == index) {
+            Some(entry) => entry.1 = fill.fills,
+            None => self.order_books.push((index, fill.fills)),
+        }
+        self.levels_consumed = self.levels_consumed.saturating_add(fill.levels_consumed);
+        Some(fill.amount_out)
+    }
+
+    /// Order book levels taken from by the recorded fills
+    pub fn levels_consumed(&self) -> u32 {
+        self.levels_consumed
+    }
+}
+
+/// Venue the pathfinder can swap through: a registry pool, a
+/// concentrated-liquidity pool walked tick by tick, or an order book filled
+/// level by level
+#[derive(Clone, Copy)]
+enum Edge<'a> {
+    Pool(&'a PoolInfo),
+    Concentrated(&'a ClmmPool),
+    OrderBook(&'a OrderBookMarket),
 }
 
 impl PartialEq for PathNode {
@@ -36,20 +166,413 @@ impl Ord for PathNode {
     }
 }
 
+/// Number of equal parts `find_split_route` divides the input into when
+/// allocating it across paths
+pub const SPLIT_PARTS: u64 = 10;
+
+/// Expansion budget of each route search run on-chain, keeping
+/// `FindOptimalRoute` and `FindSplitRoute` within the compute limit of a
+/// transaction however dense the registry
+pub const ROUTE_EXPANSION_BUDGET: usize = 64;
+
+/// Input split across one or more paths
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitRoute {
+    /// Pools of each path in swap order, with the input amount routed
+    /// through it. Paths are executed in this order.
+    pub paths: Vec<(Vec<Pubkey>, u64)>,
+
+    /// Total output of executing the paths in order
+    pub amount_out: u64,
+}
+
+/// Output of a route, with the initialized ticks of concentrated-liquidity
+/// pools it crosses and the order book levels it fills against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteQuote {
+    pub amount_out: u64,
+    pub ticks_crossed: u32,
+    pub levels_consumed: u32,
+}
+
+/// How searches rank routes: by output net of a cost for every hop and of
+/// risk penalties on individual venues. Costs are in units of the token a
+/// search optimizes, the output token or, for exact-output searches, the
+/// input token. The default charges nothing, ranking routes by output alone.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouteScoring {
+    /// Charged for every hop, for the compute, accounts and chance of
+    /// failure it adds
+    pub hop_cost: u64,
+
+    /// Charged on top of `hop_cost` for each hop through the listed venues
+    pub pool_penalties: HashMap<Pubkey, u64>,
+}
+
+impl RouteScoring {
+    /// Scoring charging `hop_cost` for every hop
+    pub fn with_hop_cost(hop_cost: u64) -> Self {
+        Self {
+            hop_cost,
+            pool_penalties: HashMap::new(),
+        }
+    }
+
+    /// Also charge `penalty` for every hop through the venue at `pool`
+    pub fn with_pool_penalty(mut self, pool: Pubkey, penalty: u64) -> Self {
+        self.pool_penalties.insert(pool, penalty);
+        self
+    }
+
+    /// Cost of a hop through the venue at `pool`
+    pub fn hop_penalty(&self, pool: &Pubkey) -> u64 {
+        let risk = self.pool_penalties.get(pool).copied().unwrap_or(0);
+        self.hop_cost.saturating_add(risk)
+    }
+
+    /// Total cost of the hops of `route`
+    pub fn route_penalty(&self, route: &[Pubkey]) -> u64 {
+        route
+            .iter()
+            .fold(0, |penalty, pool| penalty.saturating_add(self.hop_penalty(pool)))
+    }
+
+    /// Score of `route` yielding `amount_out`: its output net of its costs
+    pub fn score(&self, route: &[Pubkey], amount_out: u64) -> u64 {
+        amount_out.saturating_sub(self.route_penalty(route))
+    }
+}
+
+/// Cycle of swaps out of and back into one mint that returns more than it
+/// takes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArbitrageCycle {
+    /// Pools of the cycle in swap order
+    pub route: Vec<Pubkey>,
+
+    /// Input that maximizes the profit
+    pub amount_in: u64,
+
+    /// Output minus input at `amount_in`
+    pub profit: u64,
+}
+
 pub struct AStarPathfinder<'a> {
     pools: &'a [PoolInfo],
+    /// Concentrated-liquidity pools, indexed after `pools`
+    concentrated: &'a [ClmmPool],
+    /// Order book markets, indexed after `concentrated`
+    order_books: &'a [OrderBookMarket],
+    /// Indexes of the active pools trading each mint
+    adjacency: HashMap<Pubkey, Vec<usize>>,
+    /// Index of every venue by address
+    edge_indexes: HashMap<Pubkey, usize>,
+    /// Token-2022 transfer fees of the mints that charge one
+    transfer_fees: HashMap<Pubkey, TransferFee>,
+    /// Mints no route may trade through
+    excluded_mints: Vec<Pubkey>,
+    scoring: RouteScoring,
     max_hops: u8,
+    heuristic: bool,
+    expansion_budget: Option<usize>,
+    expansions: Cell<usize>,
+    /// Nodes expanded by the search running, counted against the budget
+    search_expansions: Cell<usize>,
+    budget_exhausted: Cell<bool>,
+}
+
+/// Relative slack added to heuristic estimates so floating-point rounding
+/// never takes them below the true output
+const ESTIMATE_SLACK: f64 = 1e-9;
+
+/// Affine bound `rate * amount + offset` on what an amount can be swapped
+/// into
+type RateBound = (f64, f64);
+
+/// Output amount (negated input amount for exact-output searches, so more
+/// is better either way), hop count, costs charged and tokens of a path
+/// reaching some token
+type Label = (u64, u8, u64, Vec<Pubkey>);
+
+/// Whether every continuation of path `b` is also open to path `a` and
+/// scores at least as well from it: outputs grow with inputs, continuations
+/// cost both paths the same, and a path only ever leaves through pools
+/// between tokens it has not visited yet, whose reserves no earlier hop of
+/// it has touched.
+fn dominates(a: &Label, b: &Label) -> bool {
+    a.0 >= b.0 && a.1 <= b.1 && a.2 <= b.2 && a.3.iter().all(|token| b.3.contains(token))
 }
 
 impl<'a> AStarPathfinder<'a> {
     pub fn new(pools: &'a [PoolInfo], max_hops: u8) -> Self {
-        Self {
+        let mut pathfinder = Self {
             pools,
+            concentrated: &[],
+            order_books: &[],
+            adjacency: HashMap::new(),
+            edge_indexes: HashMap::new(),
+            transfer_fees: HashMap::new(),
+            excluded_mints: Vec::new(),
+            scoring: RouteScoring::default(),
             max_hops: max_hops.min(MAX_ROUTE_HOPS as u8),
+            heuristic: true,
+            expansion_budget: None,
+            expansions: Cell::new(0),
+            search_expansions: Cell::new(0),
+            budget_exhausted: Cell::new(false),
+        };
+        pathfinder.index_edges();
+        pathfinder
+    }
+
+    /// Also route through `pools`, pricing swaps by walking their tick
+    /// snapshots
+    pub fn with_concentrated_pools(mut self, pools: &'a [ClmmPool]) -> Self {
+        self.concentrated = pools;
+        self.index_edges();
+        self
+    }
+
+    /// Also route through the order books `markets`, pricing swaps by
+    /// filling against their level snapshots in whole lots
+    pub fn with_order_books(mut self, markets: &'a [OrderBookMarket]) -> Self {
+        self.order_books = markets;
+        self.index_edges();
+        self
+    }
+
+    /// Deduct the Token-2022 transfer fees `configs` charge during `epoch`
+    /// from every transfer of their mints: into each venue and out of it.
+    /// Spot rates stay fee-free, so they still bound outputs from above.
+    pub fn with_transfer_fees(
+        mut self,
+        configs: &[(Pubkey, TransferFeeConfig)],
+        epoch: u64,
+    ) -> Self {
+        self.transfer_fees = configs
+            .iter()
+            .map(|(mint, config)| (*mint, *config.get_epoch_fee(epoch)))
+            .filter(|(_, fee)| fee.transfer_fee_basis_points > 0)
+            .collect();
+        self
+    }
+
+    /// Leave every venue trading one of `mints` out of the search, for mints
+    /// the venues cannot move
+    pub fn without_mints(mut self, mints: &[Pubkey]) -> Self {
+        self.excluded_mints = mints.to_vec();
+        self.index_edges();
+        self
+    }
+
+    /// Rank routes by `scoring` instead of by output alone, so a longer or
+    /// riskier route must beat the alternatives by its extra costs
+    pub fn with_scoring(mut self, scoring: RouteScoring) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// Rebuild the indexes of the venues trading each mint and of the
+    /// venues by address
+    fn index_edges(&mut self) {
+        let mut adjacency: HashMap<Pubkey, Vec<usize>> = HashMap::new();
+        let mut edge_indexes = HashMap::with_capacity(self.edge_count());
+        for index in 0..self.edge_count() {
+            edge_indexes.entry(self.edge_address(index)).or_insert(index);
+            let Some((token_a, token_b)) = self.active_edge_tokens(index) else {
+                continue;
+            };
+            adjacency.entry(token_a).or_default().push(index);
+            if token_b != token_a {
+                adjacency.entry(token_b).or_default().push(index);
+            }
+        }
+        self.adjacency = adjacency;
+        self.edge_indexes = edge_indexes;
+    }
+
+    /// Search without the heuristic, expanding every path no other path
+    /// dominates. Finds the same output, for comparison.
+    pub fn exhaustive(mut self) -> Self {
+        self.heuristic = false;
+        self
+    }
+
+    /// Stop each search once it has expanded `budget` nodes, returning the
+    /// best complete route it has found by then. Routes built from several
+    /// searches, like split and k-best routes, give each its own budget.
+    /// Bounds the compute a search can use on dense pool sets.
+    pub fn with_expansion_budget(mut self, budget: usize) -> Self {
+        self.expansion_budget = Some(budget);
+        self
+    }
+
+    /// Number of nodes expanded by the searches run so far
+    pub fn expansions(&self) -> usize {
+        self.expansions.get()
+    }
+
+    /// Whether a search run so far stopped at the expansion budget, so the
+    /// routes it returned may not be the best
+    pub fn is_possibly_suboptimal(&self) -> bool {
+        self.budget_exhausted.get()
+    }
+
+    /// Indexes of the active pools trading `mint`, in registry order
+    /// followed by the concentrated-liquidity pools and order books
+    fn connected_pools(&self, mint: &Pubkey) -> &[usize] {
+        self.adjacency.get(mint).map_or(&[], Vec::as_slice)
+    }
+
+    fn edge(&self, index: usize) -> Edge<'a> {
+        if index < self.pools.len() {
+            return Edge::Pool(&self.pools[index]);
+        }
+        let index = index - self.pools.len();
+        match self.concentrated.get(index) {
+            Some(pool) => Edge::Concentrated(pool),
+            None => Edge::OrderBook(&self.order_books[index - self.concentrated.len()]),
+        }
+    }
+
+    fn edge_count(&self) -> usize {
+        self.pools.len() + self.concentrated.len() + self.order_books.len()
+    }
+
+    /// Mints traded by the venue at `index`, or `None` if it is a paused
+    /// registry pool or trades an excluded mint
+    fn active_edge_tokens(&self, index: usize) -> Option<(Pubkey, Pubkey)> {
+        let (token_a, token_b) = match self.edge(index) {
+            Edge::Pool(pool) => pool.is_active().then_some((pool.token_a, pool.token_b))?,
+            Edge::Concentrated(pool) => (pool.token_a, pool.token_b),
+            Edge::OrderBook(market) => (market.base_mint, market.quote_mint),
+        };
+        if self.excluded_mints.contains(&token_a) || self.excluded_mints.contains(&token_b) {
+            return None;
+        }
+        Some((token_a, token_b))
+    }
+
+    /// Index of the venue at `address`
+    fn edge_index(&self, address: &Pubkey) -> Option<usize> {
+        self.edge_indexes.get(address).copied()
+    }
+
+    fn edge_address(&self, index: usize) -> Pubkey {
+        match self.edge(index) {
+            Edge::Pool(pool) => pool.address,
+            Edge::Concentrated(pool) => pool.address,
+            Edge::OrderBook(market) => market.address,
+        }
+    }
+
+    fn other_token(&self, index: usize, token: &Pubkey) -> Option<Pubkey> {
+        match self.edge(index) {
+            Edge::Pool(pool) => pool.get_other_token(token),
+            Edge::Concentrated(pool) => pool.get_other_token(token),
+            Edge::OrderBook(market) => market.get_other_token(token),
+        }
+    }
+
+    /// Amount that arrives when `amount` of `mint` is transferred
+    fn amount_after_fee(&self, mint: &Pubkey, amount: u64) -> Option<u64> {
+        match self.transfer_fees.get(mint) {
+            Some(fee) => fee.amount_after_fee(amount),
+            None => Some(amount),
+        }
+    }
+
+    /// Amount of `mint` to transfer for `amount` to arrive
+    fn amount_before_fee(&self, mint: &Pubkey, amount: u64) -> Option<u64> {
+        match self.transfer_fees.get(mint) {
+            Some(fee) => fee.calculate_pre_fee_amount(amount),
+            None => Some(amount),
+        }
+    }
+
+    /// Swap through the pool at `index` at the state `deltas` left it in,
+    /// net of the transfer fees on the way in and out
+    fn swap(
+        &self,
+        deltas: &mut ReserveDeltas,
+        index: usize,
+        input_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<u64> {
+        let output_mint = self.other_token(index, input_mint)?;
+        let amount_in = self.amount_after_fee(input_mint, amount_in)?;
+        let amount_out = match self.edge(index) {
+            Edge::Pool(_) => deltas.swap(self.pools, index, input_mint, amount_in),
+            Edge::Concentrated(pool) => deltas.swap_concentrated(pool, index, input_mint, amount_in),
+            Edge::OrderBook(market) => deltas.fill_order_book(market, index, input_mint, amount_in),
+        }?;
+        self.amount_after_fee(&output_mint, amount_out)
+    }
+
+    /// Input the pool at `index` needs to pay out `amount_out`, including
+    /// the transfer fees on the way in and out
+    fn input_amount(&self, index: usize, input_mint: &Pubkey, amount_out: u64) -> Option<u64> {
+        let output_mint = self.other_token(index, input_mint)?;
+        let amount_out = self.amount_before_fee(&output_mint, amount_out)?;
+        let amount_in = match self.edge(index) {
+            Edge::Pool(pool) => pool.get_input_amount(input_mint, amount_out),
+            Edge::Concentrated(pool) => pool
+                .swap_exact_out(input_mint, amount_out)
+                .map(|swap| swap.amount_in),
+            Edge::OrderBook(market) => market
+                .fill_exact_out(input_mint, amount_out)
+                .map(|fill| fill.amount_in),
+        }?;
+        self.amount_before_fee(input_mint, amount_in)
+    }
+
+    /// Spot rate of the pool at `index` at the state `deltas` left it in
+    fn spot_rate(&self, deltas: &ReserveDeltas, index: usize, input_mint: &Pubkey) -> Option<f64> {
+        self.rate_bound(deltas, index, input_mint).map(|(rate, _)| rate)
+    }
+
+    /// Bound on the output of the pool at `index` at the state `deltas` left
+    /// it in: its spot rate, and for registry pools what fee rounding adds
+    fn rate_bound(
+        &self,
+        deltas: &ReserveDeltas,
+        index: usize,
+        input_mint: &Pubkey,
+    ) -> Option<RateBound> {
+        match self.edge(index) {
+            Edge::Pool(_) => deltas.pool(self.pools, index).rate_bound(input_mint),
+            Edge::Concentrated(pool) => pool
+                .spot_rate_from(&deltas.concentrated_state(pool, index), input_mint)
+                .map(|rate| (rate, 0.0)),
+            Edge::OrderBook(market) => market
+                .spot_rate_from(&deltas.book_fills(index), input_mint)
+                .map(|rate| (rate, 0.0)),
         }
     }
 
-    /// Find optimal route using A* algorithm
+    /// Give a new search the whole expansion budget
+    fn start_search(&self) {
+        self.search_expansions.set(0);
+    }
+
+    /// Count an expansion, or flag the search as cut short if the budget
+    /// allows no more
+    fn expand(&self) -> bool {
+        if self
+            .expansion_budget
+            .is_some_and(|budget| self.search_expansions.get() >= budget)
+        {
+            self.budget_exhausted.set(true);
+            return false;
+        }
+        self.search_expansions.set(self.search_expansions.get() + 1);
+        self.expansions.set(self.expansions.get() + 1);
+        true
+    }
+
+    /// Find optimal route using A* algorithm, ordering paths by an upper
+    /// bound on the score they can still reach and stopping once no queued
+    /// path can beat the best route found, or the expansion budget runs out
     /// Returns (route_pools, final_amount_out)
     pub fn find_optimal_route(
         &self,
@@ -57,45 +580,108 @@ impl<'a> AStarPathfinder<'a> {
         output_mint: &Pubkey,
         amount_in: u64,
     ) -> Result<(Vec<Pubkey>, u64), WayfinderError> {
+        self.search(input_mint, output_mint, amount_in, &ReserveDeltas::default())
+            .map(|(route, amount_out, _)| (route, amount_out))
+    }
+
+    /// Output of swapping `amount_in` through `route`, pricing every hop at
+    /// the reserves left by the hops before it, so a pool entered twice is
+    /// priced at its post-swap state
+    pub fn quote_route(
+        &self,
+        input_mint: &Pubkey,
+        route: &[Pubkey],
+        amount_in: u64,
+    ) -> Option<u64> {
+        self.swap_along(&mut ReserveDeltas::default(), route, input_mint, amount_in)
+    }
+
+    /// `quote_route`, along with the initialized ticks the route crosses and
+    /// the order book levels it fills against
+    pub fn quote_route_detailed(
+        &self,
+        input_mint: &Pubkey,
+        route: &[Pubkey],
+        amount_in: u64,
+    ) -> Option<RouteQuote> {
+        let mut deltas = ReserveDeltas::default();
+        let amount_out = self.swap_along(&mut deltas, route, input_mint, amount_in)?;
+        Some(RouteQuote {
+            amount_out,
+            ticks_crossed: deltas.ticks_crossed(),
+            levels_consumed: deltas.levels_consumed(),
+        })
+    }
+
+    /// Best route at the reserves left by `base`, with the reserves it
+    /// leaves behind
+    fn search(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount_in: u64,
+        base: &ReserveDeltas,
+    ) -> Result<(Vec<Pubkey>, u64, ReserveDeltas), WayfinderError> {
         if input_mint == output_mint {
             return Err(WayfinderError::InvalidRoute);
         }
 
-        let mut open_set = BinaryHeap::new();
-        let mut visited: HashSet<Pubkey> = HashSet::new();
-        let mut best_routes: HashMap<Pubkey, (u64, u8)> = HashMap::new();
-
         // Initialize with starting token
-        open_set.push(PathNode {
+        let start = PathNode {
             token: *input_mint,
             cost: 0,
             hops: 0,
             path: Vec::new(),
-            amount_out: amount_in,
-        });
-        best_routes.insert(*input_mint, (amount_in, 0));
+            visited: vec![*input_mint],
+            amount: amount_in,
+            penalty: 0,
+            deltas: base.clone(),
+        };
+        self.search_from(start, output_mint, &[])
+    }
+
+    /// Best way to extend `start` to `output_mint` without swapping through
+    /// `excluded_pools`
+    fn search_from(
+        &self,
+        start: PathNode,
+        output_mint: &Pubkey,
+        excluded_pools: &[Pubkey],
+    ) -> Result<(Vec<Pubkey>, u64, ReserveDeltas), WayfinderError> {
+        self.start_search();
+        let bounds = self.rate_bounds(output_mint, true, &start.deltas);
+        let mut open_set = BinaryHeap::new();
+        // Non-dominated (amount, hops, penalty, visited) labels per token
+        let mut best_routes: HashMap<Pubkey, Vec<Label>> = HashMap::new();
 
-        let mut best_solution: Option<(Vec<Pubkey>, u64)> = None;
+        best_routes.insert(
+            start.token,
+            vec![(start.amount, start.hops, start.penalty, start.visited.clone())],
+        );
+        open_set.push(start);
+
+        // Best route with its output, the reserves it leaves and its score
+        let mut best_solution: Option<(Vec<Pubkey>, u64, ReserveDeltas, u64)> = None;
 
         while let Some(current) = open_set.pop() {
-            // Skip if we've found a better path to this token
-            if let Some(&(best_amount, best_hops)) = best_routes.get(&current.token) {
-                if current.amount_out < best_amount || 
-                   (current.amount_out == best_amount && current.hops > best_hops) {
-                    continue;
-                }
+            // With an admissible heuristic, nothing left can beat the best
+            // route once its estimate is no higher
+            if self.heuristic
+                && best_solution
+                    .as_ref()
+                    .is_some_and(|(_, _, _, best_score)| *best_score >= u64::MAX - current.cost)
+            {
+                break;
             }
 
-            // Check if we reached the destination
-            if current.token == *output_mint {
-                match &best_solution {
-                    None => best_solution = Some((current.path.clone(), current.amount_out)),
-                    Some((_, best_amount)) => {
-                        if current.amount_out > *best_amount {
-                            best_solution = Some((current.path.clone(), current.amount_out));
-                        }
-                    }
-                }
+            // Skip if a path found since this one was queued dominates it
+            let is_current = |(amount, hops, penalty, visited): &Label| {
+                *amount == current.amount
+                    && *hops == current.hops
+                    && *penalty == current.penalty
+                    && *visited == current.visited
+            };
+            if !best_routes[&current.token].iter().any(is_current) {
                 continue;
             }
 
@@ -103,149 +689,2049 @@ impl<'a> AStarPathfinder<'a> {
             if current.hops >= self.max_hops {
                 continue;
             }
-
-            // Mark as visited
-            visited.insert(current.token);
+            if !self.expand() {
+                break;
+            }
 
             // Explore neighbors (pools connected to current token)
-            for pool in self.pools.iter() {
-                // Check if pool involves current token
-                let next_token = match pool.get_other_token(&current.token) {
+            for &index in self.connected_pools(&current.token) {
+                let address = self.edge_address(index);
+                if excluded_pools.contains(&address) {
+                    continue;
+                }
+
+                // Token on the other side of the pool
+                let next_token = match self.other_token(index, &current.token) {
                     Some(t) => t,
                     None => continue,
                 };
 
-                // Skip if we've already visited this token (prevent cycles)
-                if visited.contains(&next_token) {
+                // Skip tokens already on this path (prevent cycles)
+                if current.visited.contains(&next_token) {
                     continue;
                 }
 
-                // Calculate output amount through this pool
-                let amount_out = match pool.get_output_amount(&current.token, current.amount_out) {
-                    Some(amt) => amt,
-                    None => continue,
-                };
+                // Calculate output amount through this pool, at the reserves
+                // this path has left it with
+                let mut deltas = current.deltas.clone();
+                let amount_out =
+                    match self.swap(&mut deltas, index, &current.token, current.amount) {
+                        Some(amt) => amt,
+                        None => continue,
+                    };
 
                 if amount_out == 0 {
                     continue;
                 }
 
-                // Check if this is a better route to next_token
-                let should_explore = match best_routes.get(&next_token) {
-                    None => true,
-                    Some(&(best_amount, best_hops)) => {
-                        amount_out > best_amount || 
-                        (amount_out == best_amount && current.hops + 1 < best_hops)
-                    }
+                // Skip paths that can no longer reach the output or beat
+                // the best route found. Paths short of the output still
+                // have at least one hop to pay for.
+                let hops = current.hops + 1;
+                let penalty = current.penalty.saturating_add(self.scoring.hop_penalty(&address));
+                let Some(estimate) =
+                    self.estimate(&bounds, &next_token, output_mint, amount_out, hops)
+                else {
+                    continue;
+                };
+                let remaining_cost = if next_token == *output_mint {
+                    0
+                } else {
+                    self.scoring.hop_cost
                 };
+                let estimate = estimate.saturating_sub(penalty.saturating_add(remaining_cost));
+                if self.heuristic
+                    && best_solution
+                        .as_ref()
+                        .is_some_and(|(_, _, _, best_score)| *best_score >= estimate)
+                {
+                    continue;
+                }
 
-                if should_explore {
-                    best_routes.insert(next_token, (amount_out, current.hops + 1));
-                    
-                    let mut new_path = current.path.clone();
-                    new_path.push(pool.address);
+                // Explore unless another path to next_token gets there with
+                // at least as much, in no more hops, for no more costs,
+                // through no other tokens
+                let mut visited = current.visited.clone();
+                visited.push(next_token);
+                let label = (amount_out, hops, penalty, visited);
+                let labels = best_routes.entry(next_token).or_default();
+                if labels.iter().any(|other| dominates(other, &label)) {
+                    continue;
+                }
+                labels.retain(|other| !dominates(&label, other));
+                labels.push(label.clone());
 
-                    // Cost is negated amount_out to maximize output in min-heap
-                    let cost = u64::MAX - amount_out;
+                let mut new_path = current.path.clone();
+                new_path.push(address);
 
-                    open_set.push(PathNode {
-                        token: next_token,
-                        cost,
-                        hops: current.hops + 1,
-                        path: new_path,
-                        amount_out,
-                    });
+                // Complete routes are kept as soon as they are found, so a
+                // search cut short by its budget still returns the best
+                if next_token == *output_mint {
+                    if best_solution
+                        .as_ref()
+                        .map_or(true, |(_, _, _, best_score)| estimate > *best_score)
+                    {
+                        best_solution = Some((new_path, amount_out, deltas, estimate));
+                    }
+                    continue;
                 }
+
+                // Cost is the negated estimate to maximize score in min-heap
+                let cost = u64::MAX - estimate;
+
+                open_set.push(PathNode {
+                    token: next_token,
+                    cost,
+                    hops,
+                    path: new_path,
+                    visited: label.3,
+                    amount: amount_out,
+                    penalty,
+                    deltas,
+                });
             }
         }
 
-        best_solution.ok_or(WayfinderError::NoValidPath)
+        best_solution
+            .map(|(route, amount_out, deltas, _)| (route, amount_out, deltas))
+            .ok_or(WayfinderError::NoValidPath)
     }
 
-    /// Heuristic function for A* (estimates remaining cost)
-    /// In swap routing, we estimate the best possible rate
-    fn _heuristic(&self, from_token: &Pubkey, to_token: &Pubkey, amount: u64) -> u64 {
-        // Find best direct rate if exists
-        let mut best_rate = 0u64;
-        
-        for pool in self.pools.iter() {
-            if (pool.token_a == *from_token && pool.token_b == *to_token) ||
-               (pool.token_b == *from_token && pool.token_a == *to_token) {
-                if let Some(out) = pool.get_output_amount(from_token, amount) {
-                    best_rate = best_rate.max(out);
+    /// Find up to `k` routes in order of decreasing score, the first being
+    /// `find_optimal_route`'s, using Yen's algorithm: each next route is the
+    /// best deviation from a route already found, branching off at one of
+    /// its tokens through a pool no found route with the same prefix took.
+    /// Routes are simple, like those of `find_optimal_route`.
+    pub fn find_k_best_routes(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount_in: u64,
+        k: usize,
+    ) -> Result<Vec<(Vec<Pubkey>, u64)>, WayfinderError> {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut found = vec![self.find_optimal_route(input_mint, output_mint, amount_in)?];
+        let mut candidates: Vec<(Vec<Pubkey>, u64)> = Vec::new();
+
+        while found.len() < k {
+            let (previous, _) = found.last().unwrap().clone();
+
+            // Branch off `previous` after each of its prefixes
+            let mut root = PathNode {
+                token: *input_mint,
+                cost: 0,
+                hops: 0,
+                path: Vec::new(),
+                visited: vec![*input_mint],
+                amount: amount_in,
+                penalty: 0,
+                deltas: ReserveDeltas::default(),
+            };
+            for (i, pool_address) in previous.iter().enumerate() {
+                let excluded_pools: Vec<Pubkey> = found
+                    .iter()
+                    .filter(|(route, _)| route.len() > i && route[..i] == previous[..i])
+                    .map(|(route, _)| route[i])
+                    .collect();
+
+                if let Ok((route, amount_out, _)) =
+                    self.search_from(root.clone(), output_mint, &excluded_pools)
+                {
+                    if !candidates.iter().any(|(candidate, _)| *candidate == route) {
+                        candidates.push((route, amount_out));
+                    }
                 }
+
+                // Extend the root by the next hop of `previous`
+                let Some(index) = self.edge_index(pool_address) else {
+                    break;
+                };
+                let Some(next_token) = self.other_token(index, &root.token) else {
+                    break;
+                };
+                let Some(amount) = self.swap(&mut root.deltas, index, &root.token, root.amount)
+                else {
+                    break;
+                };
+                root.token = next_token;
+                root.hops += 1;
+                root.path.push(*pool_address);
+                root.visited.push(next_token);
+                root.amount = amount;
+                root.penalty = root.penalty.saturating_add(self.scoring.hop_penalty(pool_address));
             }
+
+            // The best candidate is the next route; ties go to the earliest
+            let Some(best) = candidates
+                .iter()
+                .enumerate()
+                .max_by_key(|(i, (route, amount_out))| {
+                    (self.scoring.score(route, *amount_out), Reverse(*i))
+                })
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            found.push(candidates.remove(best));
         }
-        
-        // Return negated for min-heap
-        u64::MAX - best_rate
+
+        Ok(found)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Find the route that buys `amount_out` of `output_mint` for the least
+    /// input plus costs, searching back from the output with each hop's
+    /// required input from `PoolInfo::get_input_amount`
+    /// Returns (route_pools, required_amount_in)
+    pub fn find_optimal_route_exact_out(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount_out: u64,
+    ) -> Result<(Vec<Pubkey>, u64), WayfinderError> {
+        if input_mint == output_mint {
+            return Err(WayfinderError::InvalidRoute);
+        }
 
-    #[test]
-    fn test_pathfinding_direct_route() {
-        let token_a = Pubkey::new_unique();
-        let token_b = Pubkey::new_unique();
-        let pool_addr = Pubkey::new_unique();
+        self.start_search();
+        let bounds = self.rate_bounds(input_mint, false, &ReserveDeltas::default());
+        let mut open_set = BinaryHeap::new();
+        let mut best_routes: HashMap<Pubkey, Vec<Label>> = HashMap::new();
 
-        let pools = vec![
-            PoolInfo {
-                address: pool_addr,
-                token_a,
-                token_b,
-                fee_bps: 30,
-                reserve_a: 1_000_000,
-                reserve_b: 2_000_000,
-            }
-        ];
+        // Initialize with the output token and the amount to buy
+        open_set.push(PathNode {
+            token: *output_mint,
+            cost: 0,
+            hops: 0,
+            path: Vec::new(),
+            visited: vec![*output_mint],
+            amount: amount_out,
+            penalty: 0,
+            deltas: ReserveDeltas::default(),
+        });
+        best_routes.insert(*output_mint, vec![(u64::MAX - amount_out, 0, 0, vec![*output_mint])]);
 
-        let pathfinder = AStarPathfinder::new(&pools, 3);
-        let result = pathfinder.find_optimal_route(&token_a, &token_b, 1000);
-        
-        assert!(result.is_ok());
-        let (route, amount_out) = result.unwrap();
-        assert_eq!(route.len(), 1);
-        assert_eq!(route[0], pool_addr);
-        assert!(amount_out > 0);
-    }
+        // Best route with its input and its input plus costs
+        let mut best_solution: Option<(Vec<Pubkey>, u64, u64)> = None;
 
-    #[test]
-    fn test_pathfinding_multi_hop() {
-        let token_a = Pubkey::new_unique();
-        let token_b = Pubkey::new_unique();
-        let token_c = Pubkey::new_unique();
-        let pool1 = Pubkey::new_unique();
-        let pool2 = Pubkey::new_unique();
+        while let Some(current) = open_set.pop() {
+            // Cost is a lower bound on the path's total input plus costs
+            if self.heuristic
+                && best_solution
+                    .as_ref()
+                    .is_some_and(|(_, _, best_score)| *best_score <= current.cost)
+            {
+                break;
+            }
 
-        let pools = vec![
-            PoolInfo {
-                address: pool1,
-                token_a,
-                token_b,
-                fee_bps: 30,
-                reserve_a: 1_000_000,
-                reserve_b: 1_000_000,
-            },
-            PoolInfo {
-                address: pool2,
-                token_a: token_b,
-                token_b: token_c,
-                fee_bps: 30,
-                reserve_a: 1_000_000,
-                reserve_b: 1_000_000,
+            // Skip if a path found since this one was queued dominates it
+            let is_current = |(amount, hops, penalty, visited): &Label| {
+                *amount == u64::MAX - current.amount
+                    && *hops == current.hops
+                    && *penalty == current.penalty
+                    && *visited == current.visited
+            };
+            if !best_routes[&current.token].iter().any(is_current) {
+                continue;
             }
-        ];
 
-        let pathfinder = AStarPathfinder::new(&pools, 3);
-        let result = pathfinder.find_optimal_route(&token_a, &token_c, 1000);
-        
-        assert!(result.is_ok());
-        let (route, _) = result.unwrap();
-        assert_eq!(route.len(), 2);
-    }
-}
+            if current.hops >= self.max_hops {
+                continue;
+            }
+            if !self.expand() {
+                break;
+            }
 
+            // Explore pools that pay out the current token
+            for &index in self.connected_pools(&current.token) {
+                let previous_token = match self.other_token(index, &current.token) {
+                    Some(t) => t,
+                    None => continue,
+                };
+
+                if current.visited.contains(&previous_token) {
+                    continue;
+                }
+
+                let amount_in = match self.input_amount(index, &previous_token, current.amount) {
+                    Some(amt) => amt,
+                    None => continue,
+                };
+
+                let address = self.edge_address(index);
+                let hops = current.hops + 1;
+                let penalty = current.penalty.saturating_add(self.scoring.hop_penalty(&address));
+                let Some(estimate) =
+                    self.estimate_input(&bounds, &previous_token, input_mint, amount_in, hops)
+                else {
+                    continue;
+                };
+                let remaining_cost = if previous_token == *input_mint {
+                    0
+                } else {
+                    self.scoring.hop_cost
+                };
+                let estimate = estimate.saturating_add(penalty.saturating_add(remaining_cost));
+                if self.heuristic
+                    && best_solution
+                        .as_ref()
+                        .is_some_and(|(_, _, best_score)| *best_score <= estimate)
+                {
+                    continue;
+                }
+
+                let mut visited = current.visited.clone();
+                visited.push(previous_token);
+                let label = (u64::MAX - amount_in, hops, penalty, visited);
+                let labels = best_routes.entry(previous_token).or_default();
+                if labels.iter().any(|other| dominates(other, &label)) {
+                    continue;
+                }
+                labels.retain(|other| !dominates(&label, other));
+                labels.push(label.clone());
+
+                // Built from the output back, reversed once found
+                let mut new_path = current.path.clone();
+                new_path.push(address);
+
+                // Complete routes are kept as soon as they are found, so a
+                // search cut short by its budget still returns the best
+                if previous_token == *input_mint {
+                    if best_solution
+                        .as_ref()
+                        .map_or(true, |(_, _, best_score)| estimate < *best_score)
+                    {
+                        best_solution = Some((new_path, amount_in, estimate));
+                    }
+                    continue;
+                }
+
+                open_set.push(PathNode {
+                    token: previous_token,
+                    cost: estimate,
+                    hops,
+                    path: new_path,
+                    visited: label.3,
+                    amount: amount_in,
+                    penalty,
+                    deltas: ReserveDeltas::default(),
+                });
+            }
+        }
+
+        let (mut route, amount_in, _) = best_solution.ok_or(WayfinderError::NoValidPath)?;
+        route.reverse();
+        Ok((route, amount_in))
+    }
+
+    /// Find every cycle of 2 to `max_hops` hops from `mint` back to itself
+    /// that turns a profit at its best input size, most profitable first.
+    ///
+    /// A cycle can only profit if the product of its spot rates exceeds 1,
+    /// since larger trades get worse rates, so walks whose rate so far times
+    /// the best rate back to `mint` cannot exceed 1 are never extended.
+    pub fn find_arbitrage_cycles(&self, mint: &Pubkey) -> Vec<ArbitrageCycle> {
+        let bounds = self.rate_bounds(mint, true, &ReserveDeltas::default());
+        let mut routes = Vec::new();
+        self.collect_cycles(mint, &bounds, *mint, 1.0, &mut vec![*mint], &mut Vec::new(), &mut routes);
+
+        let mut cycles: Vec<ArbitrageCycle> = routes
+            .into_iter()
+            .filter_map(|route| {
+                let (amount_in, profit) = self.size_cycle(mint, &route)?;
+                Some(ArbitrageCycle {
+                    route,
+                    amount_in,
+                    profit,
+                })
+            })
+            .collect();
+        cycles.sort_by_key(|cycle| Reverse(cycle.profit));
+        cycles
+    }
+
+    /// Depth-first search for cycles back to `mint` whose spot rates
+    /// multiply to more than 1, through distinct pools and tokens
+    #[allow(clippy::too_many_arguments)]
+    fn collect_cycles(
+        &self,
+        mint: &Pubkey,
+        bounds: &[HashMap<Pubkey, RateBound>],
+        token: Pubkey,
+        rate: f64,
+        visited: &mut Vec<Pubkey>,
+        path: &mut Vec<Pubkey>,
+        routes: &mut Vec<Vec<Pubkey>>,
+    ) {
+        let hops_left = self.max_hops as usize - path.len();
+        if hops_left == 0 {
+            return;
+        }
+
+        for &index in self.connected_pools(&token) {
+            let address = self.edge_address(index);
+            if path.contains(&address) {
+                continue;
+            }
+            let (Some(next_token), Some(pool_rate)) = (
+                self.other_token(index, &token),
+                self.spot_rate(&ReserveDeltas::default(), index, &token),
+            ) else {
+                continue;
+            };
+            let next_rate = rate * pool_rate;
+
+            if next_token == *mint {
+                if !path.is_empty() && next_rate > 1.0 {
+                    let mut route = path.clone();
+                    route.push(address);
+                    routes.push(route);
+                }
+                continue;
+            }
+
+            // Prune walks that cannot get back to `mint` at a profit
+            let back = bounds[hops_left - 1].get(&next_token).map_or(0.0, |(rate, _)| *rate);
+            if visited.contains(&next_token) || next_rate * back <= 1.0 {
+                continue;
+            }
+
+            visited.push(next_token);
+            path.push(address);
+            self.collect_cycles(mint, bounds, next_token, next_rate, visited, path, routes);
+            path.pop();
+            visited.pop();
+        }
+    }
+
+    /// Input into the cycle `route` from `mint` that maximizes output minus
+    /// input, with that profit, or `None` if no input profits
+    fn size_cycle(&self, mint: &Pubkey, route: &[Pubkey]) -> Option<(u64, u64)> {
+        let size_numerically = || {
+            PoolInfo::maximize_profit(1, u64::MAX, |amount_in| {
+                self.quote_route(mint, route, amount_in)
+            })
+        };
+
+        let mut token = *mint;
+        let mut hops = Vec::with_capacity(route.len());
+        for pool_address in route {
+            // Walking ticks and deducting transfer fees have no closed form
+            // to compose
+            let Edge::Pool(pool) = self.edge(self.edge_index(pool_address)?) else {
+                return size_numerically();
+            };
+            if self.transfer_fees.contains_key(&token) {
+                return size_numerically();
+            }
+            hops.push((*pool, token));
+            token = pool.get_other_token(&token)?;
+        }
+        PoolInfo::optimal_cycle_input(&hops)
+    }
+
+    /// Find the best way to divide `amount_in` across up to `max_paths`
+    /// paths, which may share pools.
+    ///
+    /// The input is allocated greedily in `SPLIT_PARTS` equal parts: each
+    /// part goes to whichever path, new or already chosen, yields the most
+    /// for it at the reserves left by the parts before it, a new path only
+    /// net of the scoring's costs for its hops. Never scores less than the
+    /// single best path.
+    pub fn find_split_route(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount_in: u64,
+        max_paths: usize,
+    ) -> Result<SplitRoute, WayfinderError> {
+        let (single_route, single_amount_out) =
+            self.find_optimal_route(input_mint, output_mint, amount_in)?;
+        let single = SplitRoute {
+            paths: vec![(single_route, amount_in)],
+            amount_out: single_amount_out,
+        };
+        let max_paths = max_paths.clamp(1, MAX_SPLIT_PATHS);
+
+        // Reserves after the parts allocated so far
+        let mut committed = ReserveDeltas::default();
+        let mut paths: Vec<(Vec<Pubkey>, u64)> = Vec::new();
+        let part = amount_in / SPLIT_PARTS;
+
+        for i in 0..SPLIT_PARTS {
+            let amount = if i == SPLIT_PARTS - 1 {
+                amount_in - part * (SPLIT_PARTS - 1)
+            } else {
+                part
+            };
+            if amount == 0 {
+                continue;
+            }
+
+            // Best of the paths already chosen, at the current reserves
+            let mut best: Option<(usize, u64, ReserveDeltas)> = None;
+            for (index, (route, _)) in paths.iter().enumerate() {
+                let mut deltas = committed.clone();
+                if let Some(amount_out) = self.swap_along(&mut deltas, route, input_mint, amount) {
+                    if best.as_ref().map_or(true, |(_, best_out, _)| amount_out > *best_out) {
+                        best = Some((index, amount_out, deltas));
+                    }
+                }
+            }
+
+            // Open a new path if one is still allowed and beats them net
+            // of the costs of its hops, which paths already chosen have paid
+            if paths.len() < max_paths {
+                if let Ok((route, amount_out, deltas)) =
+                    self.search(input_mint, output_mint, amount, &committed)
+                {
+                    let existing = paths.iter().position(|(r, _)| *r == route);
+                    let amount_out = match existing {
+                        Some(_) => amount_out,
+                        None => self.scoring.score(&route, amount_out),
+                    };
+                    if best.as_ref().map_or(true, |(_, best_out, _)| amount_out > *best_out) {
+                        let index = existing.unwrap_or_else(|| {
+                            paths.push((route, 0));
+                            paths.len() - 1
+                        });
+                        best = Some((index, amount_out, deltas));
+                    }
+                }
+            }
+
+            // Parts too small to yield any output are better left unsplit
+            let Some((index, _, deltas)) = best else {
+                return Ok(single);
+            };
+            committed = deltas;
+            paths[index].1 += amount;
+        }
+
+        // Re-simulate whole paths in execution order: swapping a path's total
+        // differs slightly from swapping its parts one by one
+        let mut deltas = ReserveDeltas::default();
+        let mut amount_out = 0u64;
+        for (route, path_amount_in) in &paths {
+            let path_out = self
+                .swap_along(&mut deltas, route, input_mint, *path_amount_in)
+                .ok_or(WayfinderError::InsufficientLiquidity)?;
+            amount_out = amount_out
+                .checked_add(path_out)
+                .ok_or(WayfinderError::CalculationOverflow)?;
+        }
+
+        // Each path pays the costs of its hops
+        let penalty = paths.iter().fold(0u64, |penalty, (route, _)| {
+            penalty.saturating_add(self.scoring.route_penalty(route))
+        });
+        let single_score = self.scoring.score(&single.paths[0].0, single.amount_out);
+        if amount_out.saturating_sub(penalty) <= single_score {
+            return Ok(single);
+        }
+
+        Ok(SplitRoute { paths, amount_out })
+    }
+
+    /// Swap `amount_in` through `route`, recording each hop in `deltas`, and
+    /// return the final output
+    fn swap_along(
+        &self,
+        deltas: &mut ReserveDeltas,
+        route: &[Pubkey],
+        input_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<u64> {
+        let mut token = *input_mint;
+        let mut amount = amount_in;
+
+        for pool_address in route {
+            let index = self.edge_index(pool_address)?;
+            let next_token = self.other_token(index, &token)?;
+            amount = self.swap(deltas, index, &token, amount)?;
+            token = next_token;
+        }
+
+        Some(amount)
+    }
+
+    /// Upper bounds on the rate between each token and `mint`, by number of
+    /// hops: how much of `mint` one unit of the token can be swapped into
+    /// (`toward_mint`), or how much of the token one unit of `mint` can be
+    /// swapped into. Each is the best product of spot rates along any walk,
+    /// found with a pass over the pools per hop, with an offset covering
+    /// what fee rounding adds at every hop. Walks may revisit tokens, so the
+    /// bounds only relax the search.
+    fn rate_bounds(
+        &self,
+        mint: &Pubkey,
+        toward_mint: bool,
+        base: &ReserveDeltas,
+    ) -> Vec<HashMap<Pubkey, RateBound>> {
+        let mut bounds = vec![HashMap::from([(*mint, (1.0, 0.0))])];
+
+        for _ in 0..self.max_hops {
+            let previous = bounds.last().unwrap();
+            let mut next = previous.clone();
+            for index in 0..self.edge_count() {
+                let Some((token_a, token_b)) = self.active_edge_tokens(index) else {
+                    continue;
+                };
+                for (from, to) in [(token_a, token_b), (token_b, token_a)] {
+                    // Extend walks from `known` by this swap to bound `token`
+                    let (token, known) = if toward_mint { (from, to) } else { (to, from) };
+                    if token == *mint {
+                        continue;
+                    }
+                    let (Some((rate, offset)), Some(&(known_rate, known_offset))) =
+                        (self.rate_bound(base, index, &from), previous.get(&known))
+                    else {
+                        continue;
+                    };
+                    // The swap goes before the known walk toward `mint`, and
+                    // after it away from `mint`
+                    let bound_offset = if toward_mint {
+                        offset * known_rate + known_offset
+                    } else {
+                        known_offset * rate + offset
+                    };
+                    let bound: &mut RateBound = next.entry(token).or_insert((0.0, 0.0));
+                    *bound = (bound.0.max(rate * known_rate), bound.1.max(bound_offset));
+                }
+            }
+            bounds.push(next);
+        }
+
+        bounds
+    }
+
+    /// Estimated final output of a path holding `amount` of `token` after
+    /// `hops` hops, or `None` if it cannot reach `output_mint` at all.
+    /// Never below the output the path can actually reach.
+    fn estimate(
+        &self,
+        bounds: &[HashMap<Pubkey, RateBound>],
+        token: &Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+        hops: u8,
+    ) -> Option<u64> {
+        if token == output_mint || !self.heuristic {
+            return Some(amount);
+        }
+
+        let (rate, offset) = *bounds[(self.max_hops - hops) as usize].get(token)?;
+        if rate == 0.0 {
+            return None;
+        }
+        // Saturates at u64::MAX
+        Some(((amount as f64 * rate + offset) * (1.0 + ESTIMATE_SLACK)).ceil() as u64)
+    }
+
+    /// Estimated input an exact-output path needing `amount` of `token`
+    /// after `hops` hops requires in total, or `None` if `input_mint` cannot
+    /// reach `token` at all. Never above the input the path actually needs.
+    fn estimate_input(
+        &self,
+        bounds: &[HashMap<Pubkey, RateBound>],
+        token: &Pubkey,
+        input_mint: &Pubkey,
+        amount: u64,
+        hops: u8,
+    ) -> Option<u64> {
+        if token == input_mint || !self.heuristic {
+            return Some(amount);
+        }
+
+        let (rate, offset) = *bounds[(self.max_hops - hops) as usize].get(token)?;
+        if rate == 0.0 {
+            return None;
+        }
+        let amount = (amount as f64 - offset * (1.0 + ESTIMATE_SLACK)).max(0.0);
+        Some((amount / (rate * (1.0 + ESTIMATE_SLACK))).floor() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::RangeInclusive;
+    use crate::clmm::{sqrt_price_at_tick, TickSnapshot, MAX_TICK, MIN_TICK};
+    use crate::orderbook::OrderLevel;
+    use crate::state::{
+        CURVE_CONSTANT_PRODUCT, CURVE_STABLE_SWAP, CURVE_WEIGHTED, POOL_STATUS_ACTIVE,
+        POOL_STATUS_PAUSED,
+    };
+
+    /// Active constant-product pool at a fresh address
+    fn pool(token_a: Pubkey, token_b: Pubkey, fee_bps: u16, reserve_a: u64, reserve_b: u64) -> PoolInfo {
+        PoolInfo {
+            address: Pubkey::new_unique(),
+            token_a,
+            token_b,
+            fee_bps,
+            reserve_a,
+            reserve_b,
+            status: POOL_STATUS_ACTIVE,
+            curve_type: CURVE_CONSTANT_PRODUCT,
+            curve_params: [0; 2],
+        }
+    }
+
+    #[test]
+    fn test_pathfinding_direct_route() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+
+        let pools = vec![pool(token_a, token_b, 30, 1_000_000, 2_000_000)];
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let result = pathfinder.find_optimal_route(&token_a, &token_b, 1000);
+        
+        assert!(result.is_ok());
+        let (route, amount_out) = result.unwrap();
+        assert_eq!(route.len(), 1);
+        assert_eq!(route[0], pools[0].address);
+        assert!(amount_out > 0);
+    }
+
+    #[test]
+    fn test_pathfinding_multi_hop() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let token_c = Pubkey::new_unique();
+
+        let pools = vec![
+            pool(token_a, token_b, 30, 1_000_000, 1_000_000),
+            pool(token_b, token_c, 30, 1_000_000, 1_000_000),
+        ];
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let result = pathfinder.find_optimal_route(&token_a, &token_c, 1000);
+        
+        assert!(result.is_ok());
+        let (route, _) = result.unwrap();
+        assert_eq!(route.len(), 2);
+    }
+
+    #[test]
+    fn test_pathfinding_respects_max_hops() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let token_c = Pubkey::new_unique();
+
+        let pools = vec![
+            pool(token_a, token_b, 30, 1_000_000, 1_000_000),
+            pool(token_b, token_c, 30, 1_000_000, 1_000_000),
+        ];
+
+        let pathfinder = AStarPathfinder::new(&pools, 1);
+        let result = pathfinder.find_optimal_route(&token_a, &token_c, 1000);
+        assert!(matches!(result, Err(WayfinderError::NoValidPath)));
+
+        let pathfinder = AStarPathfinder::new(&pools, 2);
+        let (route, _) = pathfinder.find_optimal_route(&token_a, &token_c, 1000).unwrap();
+        assert_eq!(route.len(), 2);
+    }
+
+    fn parallel_pools(token_a: Pubkey, token_b: Pubkey, count: usize) -> Vec<PoolInfo> {
+        (0..count)
+            .map(|_| pool(token_a, token_b, 30, 1_000_000, 1_000_000))
+            .collect()
+    }
+
+    #[test]
+    fn test_split_route_across_parallel_pools() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let pools = parallel_pools(token_a, token_b, 3);
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let (_, single_amount_out) = pathfinder
+            .find_optimal_route(&token_a, &token_b, 600_000)
+            .unwrap();
+        let split = pathfinder
+            .find_split_route(&token_a, &token_b, 600_000, 2)
+            .unwrap();
+
+        assert_eq!(split.paths.len(), 2);
+        assert_eq!(split.paths.iter().map(|(_, amount)| amount).sum::<u64>(), 600_000);
+        for (route, amount) in &split.paths {
+            assert_eq!(route.len(), 1);
+            assert_eq!(*amount, 300_000);
+        }
+        assert!(split.amount_out > single_amount_out);
+
+        let split = pathfinder
+            .find_split_route(&token_a, &token_b, 600_000, 3)
+            .unwrap();
+        assert_eq!(split.paths.len(), 3);
+    }
+
+    #[test]
+    fn test_split_route_keeps_small_trade_on_one_path() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let pools = parallel_pools(token_a, token_b, 2);
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let (route, amount_out) = pathfinder.find_optimal_route(&token_a, &token_b, 10).unwrap();
+        let split = pathfinder.find_split_route(&token_a, &token_b, 10, 3).unwrap();
+
+        assert_eq!(
+            split,
+            SplitRoute {
+                paths: vec![(route, 10)],
+                amount_out,
+            }
+        );
+    }
+
+    #[test]
+    fn test_quote_route_prices_pool_reentry() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let pools = parallel_pools(token_a, token_b, 1);
+        let pool = pools[0].address;
+
+        // A -> B -> A through the same pool: the way back sees the reserves
+        // left by the way out
+        let mut executed = pools[0];
+        let amount_b = executed.apply_swap(&token_a, 100_000).unwrap();
+        let amount_a = executed.apply_swap(&token_b, amount_b).unwrap();
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        assert_eq!(
+            pathfinder.quote_route(&token_a, &[pool, pool], 100_000),
+            Some(amount_a)
+        );
+        // Pricing both hops at the registry reserves would overstate the
+        // slippage of the way back
+        let naive = pools[0]
+            .get_output_amount(&token_b, pools[0].get_output_amount(&token_a, 100_000).unwrap())
+            .unwrap();
+        assert!(amount_a > naive);
+    }
+
+    #[test]
+    fn test_split_route_prices_shared_pool() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let token_c = Pubkey::new_unique();
+
+        // Two A/B pools feeding a single B/C pool that every path shares
+        let mut pools = parallel_pools(token_a, token_b, 2);
+        pools.extend(parallel_pools(token_b, token_c, 1));
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let split = pathfinder
+            .find_split_route(&token_a, &token_c, 400_000, 2)
+            .unwrap();
+        assert_eq!(split.paths.len(), 2);
+
+        // Executing the paths in order against a copy of the pools
+        let mut executed = pools.clone();
+        let mut amount_out = 0;
+        for (route, amount_in) in &split.paths {
+            let mut token = token_a;
+            let mut amount = *amount_in;
+            for address in route {
+                let pool = executed.iter_mut().find(|p| p.address == *address).unwrap();
+                let next_token = pool.get_other_token(&token).unwrap();
+                amount = pool.apply_swap(&token, amount).unwrap();
+                token = next_token;
+            }
+            amount_out += amount;
+        }
+        assert_eq!(split.amount_out, amount_out);
+    }
+
+    /// Venue the brute-force searches swap through. A simple path trades
+    /// through each venue at most once, so every hop is priced at the
+    /// venue's own state.
+    trait Venue {
+        fn other_token(&self, token: &Pubkey) -> Option<Pubkey>;
+        fn output_amount(&self, input_mint: &Pubkey, amount_in: u64) -> Option<u64>;
+        fn input_amount(&self, input_mint: &Pubkey, amount_out: u64) -> Option<u64>;
+    }
+
+    impl Venue for PoolInfo {
+        fn other_token(&self, token: &Pubkey) -> Option<Pubkey> {
+            self.get_other_token(token).filter(|_| self.is_active())
+        }
+
+        fn output_amount(&self, input_mint: &Pubkey, amount_in: u64) -> Option<u64> {
+            self.get_output_amount(input_mint, amount_in)
+        }
+
+        fn input_amount(&self, input_mint: &Pubkey, amount_out: u64) -> Option<u64> {
+            self.get_input_amount(input_mint, amount_out)
+        }
+    }
+
+    impl Venue for ClmmPool {
+        fn other_token(&self, token: &Pubkey) -> Option<Pubkey> {
+            self.get_other_token(token)
+        }
+
+        fn output_amount(&self, input_mint: &Pubkey, amount_in: u64) -> Option<u64> {
+            self.swap_exact_in(input_mint, amount_in).map(|swap| swap.amount_out)
+        }
+
+        fn input_amount(&self, input_mint: &Pubkey, amount_out: u64) -> Option<u64> {
+            self.swap_exact_out(input_mint, amount_out).map(|swap| swap.amount_in)
+        }
+    }
+
+    impl Venue for OrderBookMarket {
+        fn other_token(&self, token: &Pubkey) -> Option<Pubkey> {
+            self.get_other_token(token)
+        }
+
+        fn output_amount(&self, input_mint: &Pubkey, amount_in: u64) -> Option<u64> {
+            self.fill_exact_in(input_mint, amount_in).map(|fill| fill.amount_out)
+        }
+
+        fn input_amount(&self, input_mint: &Pubkey, amount_out: u64) -> Option<u64> {
+            self.fill_exact_out(input_mint, amount_out).map(|fill| fill.amount_in)
+        }
+    }
+
+    fn venues<'a>(
+        pools: &'a [PoolInfo],
+        concentrated: &'a [ClmmPool],
+        markets: &'a [OrderBookMarket],
+    ) -> Vec<&'a dyn Venue> {
+        pools
+            .iter()
+            .map(|pool| pool as &dyn Venue)
+            .chain(concentrated.iter().map(|pool| pool as &dyn Venue))
+            .chain(markets.iter().map(|market| market as &dyn Venue))
+            .collect()
+    }
+
+    /// Best output over every simple path of at most `max_hops` hops,
+    /// enumerated exhaustively
+    fn brute_force_best(
+        venues: &[&dyn Venue],
+        token: Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+        max_hops: u8,
+        visited: &mut Vec<Pubkey>,
+    ) -> Option<u64> {
+        if token == *output_mint {
+            return Some(amount);
+        }
+        if visited.len() > max_hops as usize {
+            return None;
+        }
+
+        let mut best = None;
+        for venue in venues {
+            let Some(next_token) = venue.other_token(&token) else {
+                continue;
+            };
+            if visited.contains(&next_token) {
+                continue;
+            }
+            let amount_out = match venue.output_amount(&token, amount) {
+                Some(amount_out) if amount_out > 0 => amount_out,
+                _ => continue,
+            };
+            visited.push(next_token);
+            let found = brute_force_best(venues, next_token, output_mint, amount_out, max_hops, visited);
+            visited.pop();
+            best = best.max(found);
+        }
+        best
+    }
+
+    /// Deterministic pseudo-random generator so failures reproduce
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) % bound
+        }
+    }
+
+    fn random_pools(rng: &mut Lcg, tokens: &[Pubkey], count: usize) -> Vec<PoolInfo> {
+        (0..count)
+            .map(|_| {
+                let a = rng.next(tokens.len() as u64) as usize;
+                let b = (a + 1 + rng.next(tokens.len() as u64 - 1) as usize) % tokens.len();
+                pool(
+                    tokens[a],
+                    tokens[b],
+                    rng.next(100) as u16,
+                    10_000 + rng.next(10_000_000),
+                    10_000 + rng.next(10_000_000),
+                )
+            })
+            .collect()
+    }
+
+    /// Random routing problem: pools between two to six tokens and a trade
+    /// from the first token to the last
+    struct RandomGraph {
+        tokens: Vec<Pubkey>,
+        pools: Vec<PoolInfo>,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        max_hops: u8,
+    }
+
+    fn random_graph(rng: &mut Lcg, pool_counts: RangeInclusive<u64>) -> RandomGraph {
+        let tokens: Vec<Pubkey> = (0..2 + rng.next(5)).map(|_| Pubkey::new_unique()).collect();
+        let pool_count = pool_counts.start() + rng.next(pool_counts.end() - pool_counts.start() + 1);
+        let pools = random_pools(rng, &tokens, pool_count as usize);
+        RandomGraph {
+            input_mint: tokens[0],
+            output_mint: tokens[tokens.len() - 1],
+            amount: 1 + rng.next(100_000),
+            max_hops: 1 + rng.next(MAX_ROUTE_HOPS as u64) as u8,
+            tokens,
+            pools,
+        }
+    }
+
+    #[test]
+    fn test_pathfinding_revisits_token_expanded_by_another_path() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let token_c = Pubkey::new_unique();
+        let token_d = Pubkey::new_unique();
+
+        // A -> B yields more units than A -> C, so B is expanded first, but
+        // C is worth more B than A is: A -> C -> B -> D is the best route
+        let pools = vec![
+            pool(token_a, token_b, 30, 1_000_000, 1_000_000),
+            pool(token_a, token_c, 30, 1_000_000, 500_000),
+            pool(token_c, token_b, 30, 500_000, 2_000_000),
+            pool(token_b, token_d, 30, 1_000_000, 1_000_000),
+        ];
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let (route, amount_out) = pathfinder.find_optimal_route(&token_a, &token_d, 100_000).unwrap();
+        assert_eq!(route, vec![pools[1].address, pools[2].address, pools[3].address]);
+        assert_eq!(
+            Some(amount_out),
+            brute_force_best(&venues(&pools, &[], &[]), token_a, &token_d, 100_000, 3, &mut vec![token_a])
+        );
+    }
+
+    #[test]
+    fn test_pathfinding_matches_brute_force() {
+        let mut rng = Lcg(7);
+        for _ in 0..200 {
+            let RandomGraph {
+                pools,
+                input_mint,
+                output_mint,
+                amount: amount_in,
+                max_hops,
+                ..
+            } = random_graph(&mut rng, 1..=12);
+
+            let expected = brute_force_best(
+                &venues(&pools, &[], &[]),
+                input_mint,
+                &output_mint,
+                amount_in,
+                max_hops,
+                &mut vec![input_mint],
+            );
+            let pathfinder = AStarPathfinder::new(&pools, max_hops);
+            match pathfinder.find_optimal_route(&input_mint, &output_mint, amount_in) {
+                Ok((route, amount_out)) => {
+                    assert_eq!(Some(amount_out), expected);
+                    assert!(route.len() <= max_hops as usize);
+                    let exhaustive = AStarPathfinder::new(&pools, max_hops).exhaustive();
+                    let (_, exhaustive_amount_out) = exhaustive
+                        .find_optimal_route(&input_mint, &output_mint, amount_in)
+                        .unwrap();
+                    assert_eq!(amount_out, exhaustive_amount_out);
+                    assert_eq!(
+                        pathfinder.quote_route(&input_mint, &route, amount_in),
+                        Some(amount_out)
+                    );
+                }
+                Err(error) => {
+                    assert_eq!(error, WayfinderError::NoValidPath);
+                    assert_eq!(expected, None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_heuristic_covers_fee_rounding() {
+        let (token_a, token_b, token_c) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        // The fee on 799 C is 1.9975 rounded down to 1, which the steep last
+        // pool turns into a thousand more B than its spot rate after fees
+        let pools = vec![
+            pool(token_a, token_b, 0, 1_000_000_000_000, 997_000_000_000_000),
+            pool(token_a, token_c, 0, 1_000_000_000_000_000, 1_000_000_000_000_000),
+            pool(token_c, token_b, 25, 1_000_000_000_000, 1_000_000_000_000_000),
+        ];
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let (route, amount_out) = pathfinder.find_optimal_route(&token_a, &token_b, 800).unwrap();
+        assert_eq!(route, vec![pools[1].address, pools[2].address]);
+        assert_eq!(
+            Some(amount_out),
+            brute_force_best(&venues(&pools, &[], &[]), token_a, &token_b, 800, 3, &mut vec![token_a])
+        );
+    }
+
+    #[test]
+    fn test_skewed_pools_match_brute_force() {
+        let mut rng = Lcg(41);
+        for _ in 0..200 {
+            let RandomGraph {
+                mut pools,
+                input_mint,
+                output_mint,
+                max_hops,
+                ..
+            } = random_graph(&mut rng, 1..=12);
+            // Reserves across six orders of magnitude, with owner fees. No
+            // output reaches the product of a pool's reserves, beyond which
+            // token-swap rejects the trade.
+            for pool in &mut pools {
+                pool.reserve_a = (1_000 + rng.next(9_000)) * 10u64.pow(3 + rng.next(6) as u32);
+                pool.reserve_b = (1_000 + rng.next(9_000)) * 10u64.pow(3 + rng.next(6) as u32);
+                pool.curve_params[0] = rng.next(pool.fee_bps as u64 + 1);
+            }
+            let amount = 1 + rng.next(9_999);
+            let pathfinder = AStarPathfinder::new(&pools, max_hops);
+            let venues = venues(&pools, &[], &[]);
+
+            let expected =
+                brute_force_best(&venues, input_mint, &output_mint, amount, max_hops, &mut vec![input_mint]);
+            let found = pathfinder.find_optimal_route(&input_mint, &output_mint, amount);
+            assert_eq!(found.ok().map(|(_, amount_out)| amount_out), expected);
+
+            let expected = brute_force_min_input(
+                &venues,
+                output_mint,
+                &input_mint,
+                amount,
+                max_hops,
+                &mut vec![output_mint],
+            );
+            let found = pathfinder.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            assert_eq!(found.ok().map(|(_, amount_in)| amount_in), expected);
+        }
+    }
+
+    #[test]
+    fn test_heuristic_expands_fewer_nodes_on_large_graph() {
+        let mut rng = Lcg(11);
+        let tokens: Vec<Pubkey> = (0..100).map(|_| Pubkey::new_unique()).collect();
+        let pools = random_pools(&mut rng, &tokens, 500);
+
+        let mut heuristic_expansions = 0;
+        let mut exhaustive_expansions = 0;
+        for i in 1..=10 {
+            let pathfinder = AStarPathfinder::new(&pools, 3);
+            let exhaustive = AStarPathfinder::new(&pools, 3).exhaustive();
+            let found = pathfinder.find_optimal_route(&tokens[0], &tokens[i], 100_000);
+            let expected = exhaustive.find_optimal_route(&tokens[0], &tokens[i], 100_000);
+            assert_eq!(
+                found.map(|(_, amount_out)| amount_out),
+                expected.map(|(_, amount_out)| amount_out)
+            );
+            heuristic_expansions += pathfinder.expansions();
+            exhaustive_expansions += exhaustive.expansions();
+        }
+        assert!(heuristic_expansions * 2 < exhaustive_expansions);
+    }
+
+    #[test]
+    fn test_expansion_budget_returns_best_route_so_far() {
+        let mut rng = Lcg(17);
+        let tokens: Vec<Pubkey> = (0..100).map(|_| Pubkey::new_unique()).collect();
+        let pools = random_pools(&mut rng, &tokens, 500);
+
+        let mut cut_short = 0;
+        for i in 1..=10 {
+            let unbounded = AStarPathfinder::new(&pools, 3);
+            let Ok((_, best)) = unbounded.find_optimal_route(&tokens[0], &tokens[i], 100_000)
+            else {
+                continue;
+            };
+            assert!(!unbounded.is_possibly_suboptimal());
+
+            // A budget the search fits in changes nothing
+            let exact = AStarPathfinder::new(&pools, 3).with_expansion_budget(unbounded.expansions());
+            let (_, amount_out) = exact.find_optimal_route(&tokens[0], &tokens[i], 100_000).unwrap();
+            assert_eq!(amount_out, best);
+            assert!(!exact.is_possibly_suboptimal());
+
+            // A smaller one stops early with a real route no better than the best
+            let bounded = AStarPathfinder::new(&pools, 3).with_expansion_budget(3);
+            if let Ok((route, amount_out)) =
+                bounded.find_optimal_route(&tokens[0], &tokens[i], 100_000)
+            {
+                assert!(amount_out <= best);
+                assert_eq!(bounded.quote_route(&tokens[0], &route, 100_000), Some(amount_out));
+            }
+            assert!(bounded.expansions() <= 3);
+            if bounded.is_possibly_suboptimal() {
+                cut_short += 1;
+            }
+        }
+        assert!(cut_short > 0);
+
+        // Expanding the input token alone finds the best direct pool, and
+        // every search on the pathfinder gets the whole budget
+        let pathfinder = AStarPathfinder::new(&pools, 3).with_expansion_budget(1);
+        let mut direct_found = 0;
+        for output_mint in &tokens[1..] {
+            let direct = pools
+                .iter()
+                .filter(|pool| pool.get_other_token(&tokens[0]) == Some(*output_mint))
+                .filter_map(|pool| pool.get_output_amount(&tokens[0], 100_000))
+                .filter(|amount_out| *amount_out > 0)
+                .max();
+            let found = pathfinder.find_optimal_route(&tokens[0], output_mint, 100_000);
+            assert_eq!(found.ok().map(|(_, amount_out)| amount_out), direct);
+            direct_found += direct.is_some() as usize;
+        }
+        assert!(direct_found > 0);
+
+        // Without a single expansion nothing is reachable
+        let pathfinder = AStarPathfinder::new(&pools, 3).with_expansion_budget(0);
+        assert_eq!(
+            pathfinder.find_optimal_route(&tokens[0], &tokens[1], 100_000),
+            Err(WayfinderError::NoValidPath)
+        );
+        assert!(pathfinder.is_possibly_suboptimal());
+    }
+
+    #[test]
+    fn test_connected_pools_lists_active_pools_by_mint() {
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut pools = [
+            parallel_pools(a, b, 1),
+            parallel_pools(b, c, 1),
+            parallel_pools(a, c, 1),
+            parallel_pools(a, b, 1),
+        ]
+        .concat();
+        pools[3].status = POOL_STATUS_PAUSED;
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        assert_eq!(pathfinder.connected_pools(&a), &[0, 2]);
+        assert_eq!(pathfinder.connected_pools(&b), &[0, 1]);
+        assert_eq!(pathfinder.connected_pools(&c), &[1, 2]);
+        assert!(pathfinder.connected_pools(&Pubkey::new_unique()).is_empty());
+    }
+
+    /// Least input over every simple path of at most `max_hops` hops that
+    /// buys `amount` of `token`, enumerated exhaustively back from it
+    fn brute_force_min_input(
+        venues: &[&dyn Venue],
+        token: Pubkey,
+        input_mint: &Pubkey,
+        amount: u64,
+        max_hops: u8,
+        visited: &mut Vec<Pubkey>,
+    ) -> Option<u64> {
+        if token == *input_mint {
+            return Some(amount);
+        }
+        if visited.len() > max_hops as usize {
+            return None;
+        }
+
+        let mut best: Option<u64> = None;
+        for venue in venues {
+            let Some(previous_token) = venue.other_token(&token) else {
+                continue;
+            };
+            if visited.contains(&previous_token) {
+                continue;
+            }
+            let Some(amount_in) = venue.input_amount(&previous_token, amount) else {
+                continue;
+            };
+            visited.push(previous_token);
+            let found =
+                brute_force_min_input(venues, previous_token, input_mint, amount_in, max_hops, visited);
+            visited.pop();
+            best = match (best, found) {
+                (Some(best), Some(found)) => Some(best.min(found)),
+                (best, found) => best.or(found),
+            };
+        }
+        best
+    }
+
+    #[test]
+    fn test_exact_out_matches_brute_force() {
+        let mut rng = Lcg(13);
+        for _ in 0..200 {
+            let RandomGraph {
+                pools,
+                input_mint,
+                output_mint,
+                amount: amount_out,
+                max_hops,
+                ..
+            } = random_graph(&mut rng, 1..=12);
+
+            let expected = brute_force_min_input(
+                &venues(&pools, &[], &[]),
+                output_mint,
+                &input_mint,
+                amount_out,
+                max_hops,
+                &mut vec![output_mint],
+            );
+            let pathfinder = AStarPathfinder::new(&pools, max_hops);
+            match pathfinder.find_optimal_route_exact_out(&input_mint, &output_mint, amount_out) {
+                Ok((route, amount_in)) => {
+                    assert_eq!(Some(amount_in), expected);
+                    assert!(route.len() <= max_hops as usize);
+                    // Swapping the required input forward buys at least the output
+                    assert!(pathfinder.quote_route(&input_mint, &route, amount_in).unwrap() >= amount_out);
+                    let exhaustive = AStarPathfinder::new(&pools, max_hops).exhaustive();
+                    let (_, exhaustive_amount_in) = exhaustive
+                        .find_optimal_route_exact_out(&input_mint, &output_mint, amount_out)
+                        .unwrap();
+                    assert_eq!(amount_in, exhaustive_amount_in);
+                }
+                Err(error) => {
+                    assert_eq!(error, WayfinderError::NoValidPath);
+                    assert_eq!(expected, None);
+                }
+            }
+        }
+    }
+
+    /// Give each pool a random curve: constant-product, StableSwap or
+    /// weighted
+    fn mix_curves(rng: &mut Lcg, pools: &mut [PoolInfo]) {
+        for pool in pools {
+            pool.curve_type = rng.next(3) as u8;
+            pool.curve_params = match pool.curve_type {
+                CURVE_STABLE_SWAP => [1 + rng.next(500), 0],
+                CURVE_WEIGHTED => {
+                    let weight_a = 1 + rng.next(99);
+                    [weight_a, 100 - weight_a]
+                }
+                _ => [0; 2],
+            };
+        }
+    }
+
+    #[test]
+    fn test_mixed_curves_match_brute_force() {
+        let mut rng = Lcg(19);
+        for _ in 0..200 {
+            let RandomGraph {
+                mut pools,
+                input_mint,
+                output_mint,
+                amount,
+                max_hops,
+                ..
+            } = random_graph(&mut rng, 1..=12);
+            mix_curves(&mut rng, &mut pools);
+            let pathfinder = AStarPathfinder::new(&pools, max_hops);
+
+            let expected = brute_force_best(
+                &venues(&pools, &[], &[]),
+                input_mint,
+                &output_mint,
+                amount,
+                max_hops,
+                &mut vec![input_mint],
+            );
+            let found = pathfinder.find_optimal_route(&input_mint, &output_mint, amount);
+            assert_eq!(found.ok().map(|(_, amount_out)| amount_out), expected);
+
+            let expected = brute_force_min_input(
+                &venues(&pools, &[], &[]),
+                output_mint,
+                &input_mint,
+                amount,
+                max_hops,
+                &mut vec![output_mint],
+            );
+            let found = pathfinder.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            assert_eq!(found.ok().map(|(_, amount_in)| amount_in), expected);
+        }
+    }
+
+    /// Concentrated-liquidity pool at `tick_current` with a full-range
+    /// position of `wide` and one of `narrow` within `width` ticks of the
+    /// price
+    fn clmm_pool(
+        token_a: Pubkey,
+        token_b: Pubkey,
+        tick_current: i32,
+        wide: u128,
+        narrow: u128,
+        width: i32,
+    ) -> ClmmPool {
+        let tick = |index, liquidity_net| TickSnapshot {
+            index,
+            liquidity_net,
+        };
+        ClmmPool {
+            address: Pubkey::new_unique(),
+            token_a,
+            token_b,
+            fee_bps: 30,
+            state: ClmmState {
+                sqrt_price_x64: sqrt_price_at_tick(tick_current).unwrap(),
+                liquidity: wide + narrow,
+                tick_current,
+            },
+            ticks: vec![
+                tick(MIN_TICK, wide as i128),
+                tick(tick_current - width, narrow as i128),
+                tick(tick_current + width, -(narrow as i128)),
+                tick(MAX_TICK, -(wide as i128)),
+            ],
+        }
+    }
+
+    fn random_clmm_pools(rng: &mut Lcg, tokens: &[Pubkey], count: usize) -> Vec<ClmmPool> {
+        (0..count)
+            .map(|_| {
+                let a = rng.next(tokens.len() as u64) as usize;
+                let b = (a + 1 + rng.next(tokens.len() as u64 - 1) as usize) % tokens.len();
+                let mut pool = clmm_pool(
+                    tokens[a],
+                    tokens[b],
+                    rng.next(4_000) as i32 - 2_000,
+                    1_000_000 + rng.next(10_000_000) as u128,
+                    rng.next(100_000_000) as u128,
+                    1 + rng.next(500) as i32,
+                );
+                pool.fee_bps = rng.next(100) as u16;
+                pool
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_routes_through_concentrated_pool() {
+        let (token_a, token_b, token_c) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let pools = vec![
+            pool(token_a, token_b, 30, 1_000_000, 1_000_000),
+            pool(token_b, token_c, 30, 100_000_000, 100_000_000),
+        ];
+        // Deep liquidity within 100 ticks of the price
+        let concentrated = vec![clmm_pool(token_a, token_b, 0, 1_000_000, 1_000_000_000, 100)];
+        let pathfinder = AStarPathfinder::new(&pools, 3).with_concentrated_pools(&concentrated);
+
+        let (route, amount_out) = pathfinder.find_optimal_route(&token_a, &token_c, 100_000).unwrap();
+        assert_eq!(route, vec![concentrated[0].address, pools[1].address]);
+        assert_eq!(
+            pathfinder.quote_route_detailed(&token_a, &route, 100_000),
+            Some(RouteQuote {
+                amount_out,
+                ticks_crossed: 0,
+                levels_consumed: 0,
+            })
+        );
+
+        // A trade the narrow position cannot absorb crosses out of it
+        let (route, amount_out) =
+            pathfinder.find_optimal_route(&token_a, &token_c, 10_000_000).unwrap();
+        assert_eq!(route, vec![concentrated[0].address, pools[1].address]);
+        let quote = pathfinder.quote_route_detailed(&token_a, &route, 10_000_000).unwrap();
+        assert_eq!(quote.amount_out, amount_out);
+        assert_eq!(quote.ticks_crossed, 1);
+
+        let (route, amount_in) =
+            pathfinder.find_optimal_route_exact_out(&token_a, &token_c, 50_000).unwrap();
+        assert_eq!(route, vec![concentrated[0].address, pools[1].address]);
+        assert!(pathfinder.quote_route(&token_a, &route, amount_in).unwrap() >= 50_000);
+    }
+
+    #[test]
+    fn test_concentrated_pools_match_brute_force() {
+        let mut rng = Lcg(23);
+        for _ in 0..200 {
+            let RandomGraph {
+                tokens,
+                pools,
+                input_mint,
+                output_mint,
+                amount,
+                max_hops,
+            } = random_graph(&mut rng, 0..=7);
+            let concentrated_count = 1 + rng.next(6) as usize;
+            let concentrated = random_clmm_pools(&mut rng, &tokens, concentrated_count);
+            let pathfinder = AStarPathfinder::new(&pools, max_hops).with_concentrated_pools(&concentrated);
+            let venues = venues(&pools, &concentrated, &[]);
+
+            let expected =
+                brute_force_best(&venues, input_mint, &output_mint, amount, max_hops, &mut vec![input_mint]);
+            let found = pathfinder.find_optimal_route(&input_mint, &output_mint, amount);
+            assert_eq!(found.as_ref().ok().map(|(_, amount_out)| *amount_out), expected);
+            if let Ok((route, amount_out)) = found {
+                assert_eq!(pathfinder.quote_route(&input_mint, &route, amount), Some(amount_out));
+            }
+
+            let expected = brute_force_min_input(
+                &venues,
+                output_mint,
+                &input_mint,
+                amount,
+                max_hops,
+                &mut vec![output_mint],
+            );
+            let found = pathfinder.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            assert_eq!(found.ok().map(|(_, amount_in)| amount_in), expected);
+        }
+    }
+
+    /// Order book with five levels a side, spread around a price of one
+    /// quote per base
+    fn random_order_books(rng: &mut Lcg, tokens: &[Pubkey], count: usize) -> Vec<OrderBookMarket> {
+        (0..count)
+            .map(|_| {
+                let a = rng.next(tokens.len() as u64) as usize;
+                let b = (a + 1 + rng.next(tokens.len() as u64 - 1) as usize) % tokens.len();
+                let base_lot_size = 10 + rng.next(100);
+                let quote_lot_size = 1 + rng.next(10);
+                // Quote lots per base lot at a price of one
+                let par = base_lot_size as f64 / quote_lot_size as f64;
+                let mut levels = |side: f64| {
+                    (1..=5)
+                        .map(|i| OrderLevel {
+                            price_lots: (par * (1.0 + side * (i as f64 * 0.01 + rng.next(10) as f64 * 0.002)))
+                                .max(1.0) as u64,
+                            size_lots: 100 + rng.next(10_000),
+                        })
+                        .collect::<Vec<_>>()
+                };
+                let bids = levels(-1.0);
+                let asks = levels(1.0);
+                OrderBookMarket {
+                    address: Pubkey::new_unique(),
+                    base_mint: tokens[a],
+                    quote_mint: tokens[b],
+                    fee_bps: rng.next(50) as u16,
+                    base_lot_size,
+                    quote_lot_size,
+                    bids,
+                    asks,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_routes_mix_pool_and_order_book_hops() {
+        let (token_a, token_b, token_c) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let pools = vec![pool(token_a, token_b, 30, 10_000_000, 10_000_000)];
+        // Sells B for C at 2 C per B, then at 1.9
+        let level = |price_lots, size_lots| OrderLevel {
+            price_lots,
+            size_lots,
+        };
+        let markets = vec![OrderBookMarket {
+            address: Pubkey::new_unique(),
+            base_mint: token_b,
+            quote_mint: token_c,
+            fee_bps: 10,
+            base_lot_size: 100,
+            quote_lot_size: 10,
+            bids: vec![level(20, 50), level(19, 1_000)],
+            asks: vec![level(21, 1_000)],
+        }];
+        let pathfinder = AStarPathfinder::new(&pools, 3).with_order_books(&markets);
+
+        let (route, amount_out) = pathfinder.find_optimal_route(&token_a, &token_c, 10_000).unwrap();
+        assert_eq!(route, vec![pools[0].address, markets[0].address]);
+        let amount_b = pools[0].get_output_amount(&token_a, 10_000).unwrap();
+        assert_eq!(
+            Some(amount_out),
+            markets[0]
+                .fill_exact_in(&token_b, amount_b)
+                .map(|fill| fill.amount_out)
+        );
+        let quote = pathfinder.quote_route_detailed(&token_a, &route, 10_000).unwrap();
+        assert_eq!((quote.amount_out, quote.levels_consumed), (amount_out, 2));
+
+        // Buying C back prices the asks
+        let (route, amount_in) =
+            pathfinder.find_optimal_route_exact_out(&token_c, &token_a, 5_000).unwrap();
+        assert_eq!(route, vec![markets[0].address, pools[0].address]);
+        assert!(pathfinder.quote_route(&token_c, &route, amount_in).unwrap() >= 5_000);
+    }
+
+    #[test]
+    fn test_mixed_venues_match_brute_force() {
+        let mut rng = Lcg(29);
+        for _ in 0..200 {
+            let RandomGraph {
+                tokens,
+                pools,
+                input_mint,
+                output_mint,
+                amount,
+                max_hops,
+            } = random_graph(&mut rng, 0..=5);
+            let concentrated_count = rng.next(4) as usize;
+            let concentrated = random_clmm_pools(&mut rng, &tokens, concentrated_count);
+            let market_count = 1 + rng.next(4) as usize;
+            let markets = random_order_books(&mut rng, &tokens, market_count);
+            let pathfinder = AStarPathfinder::new(&pools, max_hops)
+                .with_concentrated_pools(&concentrated)
+                .with_order_books(&markets);
+            let venues = venues(&pools, &concentrated, &markets);
+
+            let expected =
+                brute_force_best(&venues, input_mint, &output_mint, amount, max_hops, &mut vec![input_mint]);
+            let found = pathfinder.find_optimal_route(&input_mint, &output_mint, amount);
+            assert_eq!(found.as_ref().ok().map(|(_, amount_out)| *amount_out), expected);
+            if let Ok((route, amount_out)) = found {
+                assert_eq!(pathfinder.quote_route(&input_mint, &route, amount), Some(amount_out));
+            }
+
+            let expected = brute_force_min_input(
+                &venues,
+                output_mint,
+                &input_mint,
+                amount,
+                max_hops,
+                &mut vec![output_mint],
+            );
+            let found = pathfinder.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            assert_eq!(found.ok().map(|(_, amount_in)| amount_in), expected);
+        }
+    }
+
+    fn transfer_fee_config(epoch: u64, older_bps: u16, newer_bps: u16) -> TransferFeeConfig {
+        let fee = |epoch, transfer_fee_basis_points| TransferFee {
+            epoch,
+            maximum_fee: u64::MAX,
+            transfer_fee_basis_points,
+        };
+        TransferFeeConfig {
+            older_transfer_fee: fee(0, older_bps),
+            newer_transfer_fee: fee(epoch, newer_bps),
+        }
+    }
+
+    #[test]
+    fn test_transfer_fees_deducted_at_every_hop() {
+        let (token_a, token_b, token_c, token_d) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let pools = vec![
+            pool(token_a, token_b, 30, 1_000_000, 1_000_000),
+            pool(token_b, token_c, 30, 1_000_000, 1_000_000),
+            pool(token_a, token_d, 60, 1_000_000, 1_000_000),
+            pool(token_d, token_c, 60, 1_000_000, 1_000_000),
+        ];
+        // Token B starts charging 1% per transfer at epoch 5
+        let fees = vec![(token_b, transfer_fee_config(5, 0, 100))];
+
+        let before = AStarPathfinder::new(&pools, 3).with_transfer_fees(&fees, 4);
+        let (route, _) = before.find_optimal_route(&token_a, &token_c, 10_000).unwrap();
+        assert_eq!(route, vec![pools[0].address, pools[1].address]);
+
+        // Token B is charged once leaving the first pool and again entering
+        // the second, which makes the pricier pools through D the better route
+        let after = AStarPathfinder::new(&pools, 3).with_transfer_fees(&fees, 5);
+        let fee = fees[0].1.newer_transfer_fee;
+        let amount_b = pools[0].get_output_amount(&token_a, 10_000).unwrap();
+        let amount_b = fee.amount_after_fee(fee.amount_after_fee(amount_b).unwrap()).unwrap();
+        assert_eq!(
+            after.quote_route(&token_a, &[pools[0].address, pools[1].address], 10_000),
+            pools[1].get_output_amount(&token_b, amount_b)
+        );
+        let (route, amount_out) = after.find_optimal_route(&token_a, &token_c, 10_000).unwrap();
+        assert_eq!(route, vec![pools[2].address, pools[3].address]);
+        assert_eq!(after.quote_route(&token_a, &route, 10_000), Some(amount_out));
+
+        // Exact-output searches gross inputs up by the fees
+        let (route, amount_in) =
+            after.find_optimal_route_exact_out(&token_a, &token_c, 10_000).unwrap();
+        assert_eq!(route, vec![pools[2].address, pools[3].address]);
+        assert!(after.quote_route(&token_a, &route, amount_in).unwrap() >= 10_000);
+        let (_, amount_in_before) =
+            before.find_optimal_route_exact_out(&token_a, &token_c, 10_000).unwrap();
+        assert!(amount_in > amount_in_before);
+    }
+
+    #[test]
+    fn test_transfer_fees_match_exhaustive_search() {
+        let mut rng = Lcg(31);
+        for _ in 0..200 {
+            let RandomGraph {
+                tokens,
+                pools,
+                input_mint,
+                output_mint,
+                amount,
+                max_hops,
+            } = random_graph(&mut rng, 1..=8);
+            let market_count = rng.next(3) as usize;
+            let markets = random_order_books(&mut rng, &tokens, market_count);
+            let mut fees = Vec::new();
+            for mint in &tokens {
+                if rng.next(2) == 0 {
+                    let mut config = transfer_fee_config(0, 0, rng.next(500) as u16);
+                    config.newer_transfer_fee.maximum_fee = rng.next(1_000);
+                    fees.push((*mint, config));
+                }
+            }
+            let pathfinder = AStarPathfinder::new(&pools, max_hops)
+                .with_order_books(&markets)
+                .with_transfer_fees(&fees, 0);
+            let exhaustive = AStarPathfinder::new(&pools, max_hops)
+                .with_order_books(&markets)
+                .with_transfer_fees(&fees, 0)
+                .exhaustive();
+
+            let found = pathfinder.find_optimal_route(&input_mint, &output_mint, amount);
+            let expected = exhaustive.find_optimal_route(&input_mint, &output_mint, amount);
+            assert_eq!(
+                found.as_ref().ok().map(|(_, amount_out)| *amount_out),
+                expected.ok().map(|(_, amount_out)| amount_out)
+            );
+            if let Ok((route, amount_out)) = found {
+                assert_eq!(pathfinder.quote_route(&input_mint, &route, amount), Some(amount_out));
+            }
+
+            let found = pathfinder.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            let expected = exhaustive.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            assert_eq!(
+                found.as_ref().ok().map(|(_, amount_in)| *amount_in),
+                expected.ok().map(|(_, amount_in)| amount_in)
+            );
+            if let Ok((route, amount_in)) = found {
+                assert!(pathfinder.quote_route(&input_mint, &route, amount_in).unwrap() >= amount);
+            }
+        }
+    }
+
+    /// Every simple route of at most `max_hops` hops with its output
+    #[allow(clippy::too_many_arguments)]
+    fn all_routes(
+        pools: &[PoolInfo],
+        token: Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+        max_hops: u8,
+        visited: &mut Vec<Pubkey>,
+        path: &mut Vec<Pubkey>,
+        routes: &mut Vec<(Vec<Pubkey>, u64)>,
+    ) {
+        if token == *output_mint {
+            routes.push((path.clone(), amount));
+            return;
+        }
+        if path.len() >= max_hops as usize {
+            return;
+        }
+
+        for pool in pools.iter().filter(|pool| pool.is_active()) {
+            let Some(next_token) = pool.get_other_token(&token) else {
+                continue;
+            };
+            if visited.contains(&next_token) {
+                continue;
+            }
+            let amount_out = match pool.get_output_amount(&token, amount) {
+                Some(amount_out) if amount_out > 0 => amount_out,
+                _ => continue,
+            };
+            visited.push(next_token);
+            path.push(pool.address);
+            all_routes(pools, next_token, output_mint, amount_out, max_hops, visited, path, routes);
+            path.pop();
+            visited.pop();
+        }
+    }
+
+    #[test]
+    fn test_k_best_routes_match_brute_force() {
+        let mut rng = Lcg(17);
+        for _ in 0..100 {
+            let RandomGraph {
+                pools,
+                input_mint,
+                output_mint,
+                amount: amount_in,
+                max_hops,
+                ..
+            } = random_graph(&mut rng, 1..=12);
+            let k = 1 + rng.next(6) as usize;
+
+            let mut expected = Vec::new();
+            all_routes(
+                &pools,
+                input_mint,
+                &output_mint,
+                amount_in,
+                max_hops,
+                &mut vec![input_mint],
+                &mut Vec::new(),
+                &mut expected,
+            );
+            expected.sort_by(|(_, a), (_, b)| b.cmp(a));
+            expected.truncate(k);
+
+            let pathfinder = AStarPathfinder::new(&pools, max_hops);
+            let Ok(routes) = pathfinder.find_k_best_routes(&input_mint, &output_mint, amount_in, k)
+            else {
+                assert!(expected.is_empty());
+                continue;
+            };
+
+            // Same outputs in the same order; routes may differ between ties
+            let amounts = |routes: &[(Vec<Pubkey>, u64)]| {
+                routes.iter().map(|(_, amount_out)| *amount_out).collect::<Vec<_>>()
+            };
+            assert_eq!(amounts(&routes), amounts(&expected));
+            for (i, (route, amount_out)) in routes.iter().enumerate() {
+                assert!(route.len() <= max_hops as usize);
+                assert_eq!(pathfinder.quote_route(&input_mint, route, amount_in), Some(*amount_out));
+                assert!(routes[..i].iter().all(|(other, _)| other != route));
+            }
+        }
+    }
+
+    #[test]
+    fn test_k_best_routes_fall_back_to_parallel_pools() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let mut pools = parallel_pools(token_a, token_b, 3);
+        pools[1].reserve_b = 1_100_000;
+        pools[2].reserve_b = 900_000;
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let routes = pathfinder.find_k_best_routes(&token_a, &token_b, 1_000, 5).unwrap();
+        let route_pools: Vec<Vec<Pubkey>> = routes.iter().map(|(route, _)| route.clone()).collect();
+        assert_eq!(
+            route_pools,
+            vec![vec![pools[1].address], vec![pools[0].address], vec![pools[2].address]]
+        );
+        assert_eq!(routes[0], pathfinder.find_optimal_route(&token_a, &token_b, 1_000).unwrap());
+    }
+
+    #[test]
+    fn test_hop_cost_prefers_shorter_route() {
+        let (token_a, token_b, token_c) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let pools = vec![
+            pool(token_a, token_c, 30, 1_000_000, 1_000_000),
+            pool(token_a, token_b, 1, 100_000_000, 100_000_000),
+            pool(token_b, token_c, 1, 100_000_000, 100_000_000),
+        ];
+        let direct = vec![pools[0].address];
+        let two_hop = vec![pools[1].address, pools[2].address];
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let direct_out = pathfinder.quote_route(&token_a, &direct, 100_000).unwrap();
+        let (route, two_hop_out) =
+            pathfinder.find_optimal_route(&token_a, &token_c, 100_000).unwrap();
+        assert_eq!(route, two_hop);
+        let margin = two_hop_out - direct_out;
+
+        // The second hop has to earn its cost
+        let scoring = RouteScoring::with_hop_cost(margin - 1);
+        let pathfinder = AStarPathfinder::new(&pools, 3).with_scoring(scoring);
+        assert_eq!(
+            pathfinder.find_optimal_route(&token_a, &token_c, 100_000),
+            Ok((two_hop.clone(), two_hop_out))
+        );
+
+        let scoring = RouteScoring::with_hop_cost(margin + 1);
+        let pathfinder = AStarPathfinder::new(&pools, 3).with_scoring(scoring);
+        assert_eq!(
+            pathfinder.find_optimal_route(&token_a, &token_c, 100_000),
+            Ok((direct.clone(), direct_out))
+        );
+        let (route, _) =
+            pathfinder.find_optimal_route_exact_out(&token_a, &token_c, 80_000).unwrap();
+        assert_eq!(route, direct);
+
+        // A risk penalty on one pool steers routes away from it
+        let scoring = RouteScoring::default().with_pool_penalty(pools[2].address, margin + 1);
+        let pathfinder = AStarPathfinder::new(&pools, 3).with_scoring(scoring);
+        assert_eq!(
+            pathfinder.find_optimal_route(&token_a, &token_c, 100_000),
+            Ok((direct, direct_out))
+        );
+    }
+
+    #[test]
+    fn test_scoring_matches_brute_force() {
+        let mut rng = Lcg(37);
+        for _ in 0..200 {
+            let RandomGraph {
+                pools,
+                input_mint,
+                output_mint,
+                amount,
+                max_hops,
+                ..
+            } = random_graph(&mut rng, 1..=12);
+            let mut scoring = RouteScoring::with_hop_cost(rng.next(2_000));
+            for pool in &pools {
+                if rng.next(3) == 0 {
+                    scoring = scoring.with_pool_penalty(pool.address, rng.next(5_000));
+                }
+            }
+            let pathfinder = AStarPathfinder::new(&pools, max_hops).with_scoring(scoring.clone());
+            let exhaustive = AStarPathfinder::new(&pools, max_hops)
+                .with_scoring(scoring.clone())
+                .exhaustive();
+
+            let mut routes = Vec::new();
+            all_routes(
+                &pools,
+                input_mint,
+                &output_mint,
+                amount,
+                max_hops,
+                &mut vec![input_mint],
+                &mut Vec::new(),
+                &mut routes,
+            );
+            let expected = routes
+                .iter()
+                .map(|(route, amount_out)| scoring.score(route, *amount_out))
+                .max();
+
+            // Same score; routes may differ between ties
+            let found = pathfinder.find_optimal_route(&input_mint, &output_mint, amount);
+            assert_eq!(
+                found.as_ref().ok().map(|(route, amount_out)| scoring.score(route, *amount_out)),
+                expected
+            );
+            if let Ok((route, amount_out)) = found {
+                assert_eq!(pathfinder.quote_route(&input_mint, &route, amount), Some(amount_out));
+            }
+
+            let score_in = |(route, amount_in): (Vec<Pubkey>, u64)| {
+                amount_in + scoring.route_penalty(&route)
+            };
+            let found = pathfinder.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            let expected = exhaustive.find_optimal_route_exact_out(&input_mint, &output_mint, amount);
+            assert_eq!(found.ok().map(score_in), expected.ok().map(score_in));
+        }
+    }
+
+    #[test]
+    fn test_find_arbitrage_cycle() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let token_c = Pubkey::new_unique();
+
+        // C is cheap against A in the last pool: A -> B -> C -> A profits
+        let pools = vec![
+            pool(token_a, token_b, 30, 1_000_000, 1_000_000),
+            pool(token_b, token_c, 30, 1_000_000, 1_000_000),
+            pool(token_c, token_a, 30, 1_000_000, 1_200_000),
+        ];
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        let cycles = pathfinder.find_arbitrage_cycles(&token_a);
+        assert_eq!(cycles.len(), 1);
+        let cycle = &cycles[0];
+        assert_eq!(cycle.route, vec![pools[0].address, pools[1].address, pools[2].address]);
+        assert!(cycle.profit > 0);
+
+        let profit = |amount_in: u64| {
+            pathfinder.quote_route(&token_a, &cycle.route, amount_in).unwrap() as i128 - amount_in as i128
+        };
+        assert_eq!(profit(cycle.amount_in), cycle.profit as i128);
+        for amount_in in [cycle.amount_in - 1, cycle.amount_in + 1, cycle.amount_in / 2, cycle.amount_in * 2] {
+            assert!(profit(amount_in) <= cycle.profit as i128);
+        }
+
+        // The reverse direction loses, and two hops are not enough
+        assert!(AStarPathfinder::new(&pools, 2).find_arbitrage_cycles(&token_a).is_empty());
+    }
+
+    #[test]
+    fn test_no_arbitrage_between_consistent_pools() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let pools = parallel_pools(token_a, token_b, 3);
+
+        let pathfinder = AStarPathfinder::new(&pools, 3);
+        assert!(pathfinder.find_arbitrage_cycles(&token_a).is_empty());
+    }
+
+    #[test]
+    fn test_excluded_mints_are_never_traded() {
+        let (token_a, token_b, token_c, token_d) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let pools = vec![
+            pool(token_a, token_b, 30, 1_000_000, 1_000_000),
+            pool(token_b, token_c, 30, 1_000_000, 1_000_000),
+            pool(token_a, token_d, 60, 1_000_000, 1_000_000),
+            pool(token_d, token_c, 60, 1_000_000, 1_000_000),
+        ];
+        let markets = random_order_books(&mut Lcg(43), &[token_a, token_b], 1);
+
+        let pathfinder = AStarPathfinder::new(&pools, 3)
+            .with_order_books(&markets)
+            .without_mints(&[token_b]);
+        let (route, _) = pathfinder.find_optimal_route(&token_a, &token_c, 10_000).unwrap();
+        assert_eq!(route, vec![pools[2].address, pools[3].address]);
+        let (route, _) =
+            pathfinder.find_optimal_route_exact_out(&token_a, &token_c, 5_000).unwrap();
+        assert_eq!(route, vec![pools[2].address, pools[3].address]);
+        assert_eq!(
+            pathfinder.find_optimal_route(&token_a, &token_b, 10_000),
+            Err(WayfinderError::NoValidPath)
+        );
+        // Excluded venues stay addressable, so found routes still quote
+        assert_eq!(pathfinder.edge_index(&pools[0].address), Some(0));
+    }
+
+    #[test]
+    fn test_edge_index_finds_every_venue() {
+        let mut rng = Lcg(29);
+        let tokens: Vec<Pubkey> = (0..10).map(|_| Pubkey::new_unique()).collect();
+        let mut pools = random_pools(&mut rng, &tokens, 20);
+        pools[3].status = POOL_STATUS_PAUSED;
+        let concentrated = random_clmm_pools(&mut rng, &tokens, 5);
+        let markets = random_order_books(&mut rng, &tokens, 5);
+
+        // Venues added later are indexed after the registry pools
+        let pathfinder = AStarPathfinder::new(&pools, 3)
+            .with_concentrated_pools(&concentrated)
+            .with_order_books(&markets);
+        let addresses = pools
+            .iter()
+            .map(|pool| pool.address)
+            .chain(concentrated.iter().map(|pool| pool.address))
+            .chain(markets.iter().map(|market| market.address));
+        for (index, address) in addresses.enumerate() {
+            assert_eq!(pathfinder.edge_index(&address), Some(index));
+        }
+        assert_eq!(pathfinder.edge_index(&Pubkey::new_unique()), None);
+    }
+}
@@ -1,16 +1,31 @@
-use borsh::BorshDeserialize;
+use std::cell::{Ref, RefMut};
+
+use bytemuck::Zeroable;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint::ProgramResult,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
     msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
 };
 
 use crate::{
     error::WayfinderError,
     instruction::WayfinderInstruction,
-    pathfinding::AStarPathfinder,
-    state::{PoolInfo, RouteState},
+    pathfinding::{AStarPathfinder, ROUTE_EXPANSION_BUDGET},
+    state::{
+        AccountType, LegacyPoolRegistry, LegacyRouteState, PoolInfo, PoolRegistry, RoutePath,
+        RouteState, LEGACY_DISCRIMINATOR, MAX_ROUTE_HOPS, MAX_SPLIT_PATHS, POOL_REGISTRY_VERSION,
+        POOL_STATUS_ACTIVE, POOL_STATUS_PAUSED, ROUTE_MODE_EXACT_IN, ROUTE_MODE_EXACT_OUT,
+        ROUTE_STATE_VERSION,
+    },
+    swap::{invoke_swap, spl_token_swap, SwapHopAccounts, TokenSwapState, SWAP_HOP_ACCOUNTS},
+    token::{spl_token, spl_token_2022, TokenAccount, TransferFeeConfig},
+    ROUTE_STATE_SEED,
 };
 
 pub struct Processor;
@@ -30,6 +45,7 @@ impl Processor {
                 amount_in,
                 min_amount_out,
                 max_hops,
+                nonce,
             } => {
                 msg!("Instruction: InitializeRoute");
                 Self::process_initialize_route(
@@ -40,11 +56,13 @@ impl Processor {
                     amount_in,
                     min_amount_out,
                     max_hops,
+                    nonce,
+                    ROUTE_MODE_EXACT_IN,
                 )
             }
             WayfinderInstruction::FindOptimalRoute => {
                 msg!("Instruction: FindOptimalRoute");
-                Self::process_find_optimal_route(program_id, accounts)
+                Self::process_find_optimal_route(program_id, accounts, 1)
             }
             WayfinderInstruction::ExecuteRoute => {
                 msg!("Instruction: ExecuteRoute");
@@ -53,55 +71,147 @@ impl Processor {
             WayfinderInstruction::RegisterPool {
                 token_a_mint,
                 token_b_mint,
-                fee_bps,
             } => {
                 msg!("Instruction: RegisterPool");
-                Self::process_register_pool(
+                Self::process_register_pool(program_id, accounts, token_a_mint, token_b_mint)
+            }
+            WayfinderInstruction::InitializeRegistry => {
+                msg!("Instruction: InitializeRegistry");
+                Self::process_initialize_registry(program_id, accounts)
+            }
+            WayfinderInstruction::UpdatePool { status } => {
+                msg!("Instruction: UpdatePool");
+                Self::process_update_pool(program_id, accounts, status)
+            }
+            WayfinderInstruction::DeregisterPool => {
+                msg!("Instruction: DeregisterPool");
+                Self::process_deregister_pool(program_id, accounts)
+            }
+            WayfinderInstruction::CloseRoute => {
+                msg!("Instruction: CloseRoute");
+                Self::process_close_route(program_id, accounts)
+            }
+            WayfinderInstruction::MigrateAccount => {
+                msg!("Instruction: MigrateAccount");
+                Self::process_migrate_account(program_id, accounts)
+            }
+            WayfinderInstruction::FindSplitRoute { max_paths } => {
+                msg!("Instruction: FindSplitRoute");
+                Self::process_find_optimal_route(program_id, accounts, max_paths)
+            }
+            WayfinderInstruction::InitializeExactOutRoute {
+                input_mint,
+                output_mint,
+                amount_out,
+                max_amount_in,
+                max_hops,
+                nonce,
+            } => {
+                msg!("Instruction: InitializeExactOutRoute");
+                Self::process_initialize_route(
                     program_id,
                     accounts,
-                    token_a_mint,
-                    token_b_mint,
-                    fee_bps,
+                    input_mint,
+                    output_mint,
+                    max_amount_in,
+                    amount_out,
+                    max_hops,
+                    nonce,
+                    ROUTE_MODE_EXACT_OUT,
                 )
             }
         }
     }
 
+    /// Create a route state in `mode`; for exact-output routes `amount_in`
+    /// is the maximum input and `min_amount_out` the output to buy
+    #[allow(clippy::too_many_arguments)]
     fn process_initialize_route(
-        _program_id: &Pubkey,
+        program_id: &Pubkey,
         accounts: &[AccountInfo],
         input_mint: [u8; 32],
         output_mint: [u8; 32],
         amount_in: u64,
         min_amount_out: u64,
         max_hops: u8,
+        nonce: u64,
+        mode: u8,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let route_state_account = next_account_info(account_info_iter)?;
         let authority_account = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let registry_account = next_account_info(account_info_iter)?;
 
         if !authority_account.is_signer {
             return Err(WayfinderError::AccountNotSigner.into());
         }
 
-        if !route_state_account.is_writable {
+        if !route_state_account.is_writable || !authority_account.is_writable {
             return Err(WayfinderError::AccountNotWritable.into());
         }
 
-        let route_state = RouteState {
-            discriminator: 1,
+        if *system_program_account.key != system_program::id() {
+            return Err(WayfinderError::IncorrectProgramId.into());
+        }
+
+        Self::check_account_type(program_id, registry_account, AccountType::PoolRegistry)?;
+
+        let nonce_bytes = nonce.to_le_bytes();
+        let (route_state_address, bump) =
+            crate::find_route_state_address(program_id, authority_account.key, nonce);
+        if *route_state_account.key != route_state_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if route_state_account.owner == program_id {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        // A route needs input to spend and, for exact-output routes, an amount to buy
+        if amount_in == 0 || (mode == ROUTE_MODE_EXACT_OUT && min_amount_out == 0) {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        if max_hops == 0 {
+            return Err(WayfinderError::InvalidRoute.into());
+        }
+
+        if max_hops as usize > MAX_ROUTE_HOPS {
+            return Err(WayfinderError::MaximumHopsExceeded.into());
+        }
+
+        Self::create_pda_account(
+            program_id,
+            authority_account,
+            route_state_account,
+            system_program_account,
+            RouteState::LEN,
+            &[
+                ROUTE_STATE_SEED,
+                authority_account.key.as_ref(),
+                &nonce_bytes,
+                &[bump],
+            ],
+        )?;
+
+        *RouteState::unpack_mut(&mut route_state_account.data.borrow_mut())? = RouteState {
+            account_type: AccountType::RouteState as u8,
+            version: ROUTE_STATE_VERSION,
             input_mint: Pubkey::new_from_array(input_mint),
             output_mint: Pubkey::new_from_array(output_mint),
             amount_in,
             min_amount_out,
-            hops: 0,
-            route: Vec::new(),
+            max_hops,
+            path_count: 0,
+            paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
             status: 1, // initialized
             authority: *authority_account.key,
+            registry: *registry_account.key,
+            mode,
+            possibly_suboptimal: 0,
         };
 
-        route_state.serialize(&mut &mut route_state_account.data.borrow_mut()[..])?;
-
         msg!("Route initialized: {} -> {}", 
              Pubkey::new_from_array(input_mint),
              Pubkey::new_from_array(output_mint));
@@ -109,60 +219,143 @@ impl Processor {
         Ok(())
     }
 
-    fn process_find_optimal_route(_program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    /// Find the best route for the route state's input, split across up to
+    /// `max_paths` paths
+    fn process_find_optimal_route(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        max_paths: u8,
+    ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let route_state_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let registry_account = next_account_info(account_info_iter)?;
+        let transfer_fee_mints = Self::unpack_transfer_fee_mints(account_info_iter.as_slice())?;
 
-        if !route_state_account.is_writable {
-            return Err(WayfinderError::AccountNotWritable.into());
+        if !authority_account.is_signer {
+            return Err(WayfinderError::AccountNotSigner.into());
+        }
+
+        let mut route_state = Self::unpack_route_state(program_id, route_state_account)?;
+
+        if route_state.authority != *authority_account.key {
+            return Err(WayfinderError::AccountNotSigner.into());
         }
 
-        let mut route_state =
-            RouteState::try_from_slice(&route_state_account.data.borrow())?;
+        if route_state.registry != *registry_account.key {
+            return Err(WayfinderError::RegistryMismatch.into());
+        }
+
+        // Pools are searched straight out of the registry account data
+        let pools = Self::unpack_registry(program_id, registry_account)?;
+
+        if route_state.status != 1 && route_state.status != 2 {
+            return Err(WayfinderError::InvalidRoute.into());
+        }
 
-        // Collect pool information from remaining accounts
-        let mut pools = Vec::new();
-        for pool_account in account_info_iter {
-            // In a real implementation, deserialize actual pool data
-            // For now, using mock data structure
-            if let Ok(pool_info) = PoolInfo::try_from_slice(&pool_account.data.borrow()) {
-                pools.push(pool_info);
+        if max_paths == 0 {
+            return Err(WayfinderError::InvalidRoute.into());
+        }
+
+        if max_paths as usize > MAX_SPLIT_PATHS {
+            return Err(WayfinderError::MaximumPathsExceeded.into());
+        }
+
+        // Run A* pathfinding. Token-swap moves tokens with plain `transfer`,
+        // which Token-2022 refuses for transfer fee mints, so no route may
+        // trade them.
+        let pathfinder = AStarPathfinder::new(&pools, route_state.max_hops)
+            .without_mints(&transfer_fee_mints)
+            .with_expansion_budget(ROUTE_EXPANSION_BUDGET);
+
+        let (paths, amount_out) = if route_state.mode == ROUTE_MODE_EXACT_OUT {
+            if max_paths != 1 {
+                msg!("Exact-output routes take a single path");
+                return Err(WayfinderError::InvalidRoute.into());
             }
+
+            let amount_out = route_state.min_amount_out;
+            let (route, amount_in) = pathfinder.find_optimal_route_exact_out(
+                &route_state.input_mint,
+                &route_state.output_mint,
+                amount_out,
+            )?;
+
+            let max_amount_in = route_state.amount_in;
+            if amount_in > max_amount_in {
+                msg!(
+                    "Best route requires {} above maximum {}",
+                    amount_in,
+                    max_amount_in
+                );
+                return Err(WayfinderError::SlippageExceeded.into());
+            }
+            (vec![(route, amount_in)], amount_out)
+        } else if max_paths == 1 {
+            let (route, amount_out) = pathfinder.find_optimal_route(
+                &route_state.input_mint,
+                &route_state.output_mint,
+                route_state.amount_in,
+            )?;
+            (vec![(route, route_state.amount_in)], amount_out)
+        } else {
+            let split = pathfinder.find_split_route(
+                &route_state.input_mint,
+                &route_state.output_mint,
+                route_state.amount_in,
+                max_paths as usize,
+            )?;
+            (split.paths, split.amount_out)
+        };
+
+        let possibly_suboptimal = pathfinder.is_possibly_suboptimal();
+        if possibly_suboptimal {
+            msg!(
+                "Expansion budget of {} exhausted, route may be suboptimal",
+                ROUTE_EXPANSION_BUDGET
+            );
         }
 
-        // Run A* pathfinding
-        let max_hops = route_state.hops.max(3); // Use provided max_hops or default to 3
-        let pathfinder = AStarPathfinder::new(&pools, max_hops);
-        
-        let (optimal_route, _amount_out) = pathfinder.find_optimal_route(
-            &route_state.input_mint,
-            &route_state.output_mint,
-            route_state.amount_in,
-        )?;
+        let min_amount_out = route_state.min_amount_out;
+        if amount_out < min_amount_out {
+            msg!(
+                "Best route yields {} below minimum {}",
+                amount_out,
+                min_amount_out
+            );
+            return Err(WayfinderError::SlippageExceeded.into());
+        }
 
         // Update route state
-        route_state.route = optimal_route;
-        route_state.hops = route_state.route.len() as u8;
+        route_state.set_paths(&paths)?;
         route_state.status = 2; // route_found
+        route_state.possibly_suboptimal = u8::from(possibly_suboptimal);
 
-        route_state.serialize(&mut &mut route_state_account.data.borrow_mut()[..])?;
-
-        msg!("Optimal route found with {} hops", route_state.hops);
+        msg!(
+            "Optimal route found with {} hops across {} paths",
+            route_state.total_hops(),
+            route_state.path_count
+        );
 
         Ok(())
     }
 
-    fn process_execute_route(_program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    fn process_execute_route(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let route_state_account = next_account_info(account_info_iter)?;
         let authority_account = next_account_info(account_info_iter)?;
+        let user_input_account = next_account_info(account_info_iter)?;
+        let user_output_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let token_2022_program = next_account_info(account_info_iter)?;
+        let hop_accounts = account_info_iter.as_slice();
 
         if !authority_account.is_signer {
             return Err(WayfinderError::AccountNotSigner.into());
         }
 
-        let mut route_state =
-            RouteState::try_from_slice(&route_state_account.data.borrow())?;
+        // Copied out so the account is not borrowed across the swap CPIs
+        let route_state = *Self::unpack_route_state(program_id, route_state_account)?;
 
         if route_state.status != 2 {
             return Err(WayfinderError::InvalidRoute.into());
@@ -172,27 +365,599 @@ impl Processor {
             return Err(WayfinderError::AccountNotSigner.into());
         }
 
-        // In a full implementation, execute swaps through each pool in the route
-        // For now, just mark as executed
-        route_state.status = 3; // executed
+        if *token_program.key != spl_token::id() || *token_2022_program.key != spl_token_2022::id()
+        {
+            return Err(WayfinderError::IncorrectProgramId.into());
+        }
 
-        route_state.serialize(&mut &mut route_state_account.data.borrow_mut()[..])?;
+        if !user_input_account.is_writable || !user_output_account.is_writable {
+            return Err(WayfinderError::AccountNotWritable.into());
+        }
+
+        let paths = route_state.paths();
+        if paths.is_empty() || hop_accounts.len() != route_state.total_hops() * SWAP_HOP_ACCOUNTS
+        {
+            return Err(WayfinderError::InvalidRoute.into());
+        }
+
+        let input_token = TokenAccount::unpack(user_input_account)?;
+        let output_token = TokenAccount::unpack(user_output_account)?;
+        if input_token.mint != route_state.input_mint
+            || output_token.mint != route_state.output_mint
+        {
+            return Err(WayfinderError::InvalidRoute.into());
+        }
+
+        let input_balance_before = input_token.amount;
+        let output_balance_before = output_token.amount;
+
+        // Hop accounts are grouped per path, in path order
+        let mut hop_accounts = hop_accounts.chunks_exact(SWAP_HOP_ACCOUNTS);
+        for path in paths {
+            // Walk the path, feeding each hop's realised output into the next one
+            let route = path.route();
+            let mut source = user_input_account;
+            let mut source_mint = input_token.mint;
+            let mut amount = path.amount_in;
+            for (i, pool_key) in route.iter().enumerate() {
+                let hop = SwapHopAccounts::from_slice(
+                    hop_accounts.next().ok_or(WayfinderError::InvalidRoute)?,
+                )?;
 
-        msg!("Route executed successfully");
+                if *hop.swap_program.key != spl_token_swap::id() {
+                    return Err(WayfinderError::IncorrectProgramId.into());
+                }
+
+                if hop.pool.key != pool_key || hop.pool.owner != hop.swap_program.key {
+                    return Err(WayfinderError::InvalidPoolAccount.into());
+                }
+
+                let is_last_hop = i == route.len() - 1;
+                if is_last_hop && hop.destination.key != user_output_account.key {
+                    return Err(WayfinderError::InvalidRoute.into());
+                }
+
+                let destination = TokenAccount::unpack(hop.destination)?;
+                if destination.owner != *authority_account.key {
+                    return Err(WayfinderError::InvalidRoute.into());
+                }
+
+                // The mints pick the token program each transfer goes through
+                if *hop.source_mint.key != source_mint
+                    || *hop.destination_mint.key != destination.mint
+                {
+                    return Err(WayfinderError::InvalidRoute.into());
+                }
+
+                invoke_swap(
+                    &hop,
+                    source,
+                    authority_account,
+                    [token_program, token_2022_program],
+                    amount,
+                    0,
+                )?;
+
+                let balance_after = TokenAccount::unpack(hop.destination)?.amount;
+                amount = balance_after
+                    .checked_sub(destination.amount)
+                    .ok_or(WayfinderError::CalculationOverflow)?;
+
+                source = hop.destination;
+                source_mint = destination.mint;
+            }
+        }
+
+        let amount_out = TokenAccount::unpack(user_output_account)?
+            .amount
+            .checked_sub(output_balance_before)
+            .ok_or(WayfinderError::CalculationOverflow)?;
+
+        // The input spent never exceeds amount_in, which bounds exact-output
+        // routes
+        let amount_in = input_balance_before
+            .checked_sub(TokenAccount::unpack(user_input_account)?.amount)
+            .ok_or(WayfinderError::CalculationOverflow)?;
+        let max_amount_in = route_state.amount_in;
+        if amount_in > max_amount_in {
+            msg!("Spent {} above maximum {}", amount_in, max_amount_in);
+            return Err(WayfinderError::SlippageExceeded.into());
+        }
+
+        let min_amount_out = route_state.min_amount_out;
+        if amount_out < min_amount_out {
+            msg!(
+                "Received {} below minimum {}",
+                amount_out,
+                min_amount_out
+            );
+            return Err(WayfinderError::SlippageExceeded.into());
+        }
+
+        Self::unpack_route_state(program_id, route_state_account)?.status = 3; // executed
+
+        msg!("Route executed successfully, received {}", amount_out);
 
         Ok(())
     }
 
     fn process_register_pool(
-        _program_id: &Pubkey,
-        _accounts: &[AccountInfo],
-        _token_a_mint: [u8; 32],
-        _token_b_mint: [u8; 32],
-        _fee_bps: u16,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        token_a_mint: [u8; 32],
+        token_b_mint: [u8; 32],
     ) -> ProgramResult {
-        // Pool registration logic
-        msg!("Pool registered");
+        let account_info_iter = &mut accounts.iter();
+        let registry_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let pool_account = next_account_info(account_info_iter)?;
+        let vault_a_account = next_account_info(account_info_iter)?;
+        let vault_b_account = next_account_info(account_info_iter)?;
+
+        let mut registry_data =
+            Self::unpack_registry_for_update(program_id, registry_account, authority_account)?;
+        let (registry, entries) = PoolRegistry::unpack_mut(&mut registry_data)?;
+        let count = registry.pool_count as usize;
+
+        if PoolRegistry::find_pool(&entries[..count], pool_account.key).is_some() {
+            return Err(WayfinderError::PoolAlreadyRegistered.into());
+        }
+
+        if count >= entries.len() {
+            return Err(WayfinderError::RegistryFull.into());
+        }
+
+        let swap_state = TokenSwapState::unpack(pool_account)?;
+        let token_a = Pubkey::new_from_array(token_a_mint);
+        let token_b = Pubkey::new_from_array(token_b_mint);
+        if swap_state.token_a_mint != token_a || swap_state.token_b_mint != token_b {
+            return Err(WayfinderError::InvalidPoolAccount.into());
+        }
+
+        let fee_bps = swap_state.fee_bps()?;
+        let (reserve_a, reserve_b) =
+            Self::unpack_pool_reserves(&swap_state, vault_a_account, vault_b_account)?;
+        let (curve_type, curve_params) = swap_state.curve()?;
+
+        entries[count] = PoolInfo {
+            address: *pool_account.key,
+            token_a,
+            token_b,
+            fee_bps,
+            reserve_a,
+            reserve_b,
+            status: POOL_STATUS_ACTIVE,
+            curve_type,
+            curve_params,
+        };
+        registry.pool_count += 1;
+
+        msg!("Pool registered: {}", pool_account.key);
+
         Ok(())
     }
-}
 
+    fn process_initialize_registry(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let registry_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+
+        if !authority_account.is_signer {
+            return Err(WayfinderError::AccountNotSigner.into());
+        }
+
+        if !registry_account.is_writable {
+            return Err(WayfinderError::AccountNotWritable.into());
+        }
+
+        if registry_account.owner != program_id {
+            return Err(WayfinderError::IncorrectProgramId.into());
+        }
+
+        if registry_account.data_len() < PoolRegistry::space(0) {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        if registry_account.data.borrow()[0] != 0 {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let capacity = PoolRegistry::capacity(registry_account.data_len());
+        let mut registry_data = registry_account.data.borrow_mut();
+        let (registry, _) = PoolRegistry::unpack_mut(&mut registry_data)?;
+        *registry = PoolRegistry {
+            account_type: AccountType::PoolRegistry as u8,
+            version: POOL_REGISTRY_VERSION,
+            authority: *authority_account.key,
+            pool_count: 0,
+        };
+
+        msg!("Pool registry initialized with capacity {}", capacity);
+
+        Ok(())
+    }
+
+    fn process_update_pool(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        status: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let registry_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let pool_account = next_account_info(account_info_iter)?;
+        let vault_a_account = next_account_info(account_info_iter)?;
+        let vault_b_account = next_account_info(account_info_iter)?;
+
+        let mut registry_data =
+            Self::unpack_registry_for_update(program_id, registry_account, authority_account)?;
+        let (registry, entries) = PoolRegistry::unpack_mut(&mut registry_data)?;
+        let pools = &mut entries[..registry.pool_count as usize];
+
+        let index = PoolRegistry::find_pool(pools, pool_account.key)
+            .ok_or(WayfinderError::PoolNotRegistered)?;
+
+        if status != POOL_STATUS_ACTIVE && status != POOL_STATUS_PAUSED {
+            return Err(WayfinderError::InvalidPoolState.into());
+        }
+
+        let swap_state = TokenSwapState::unpack(pool_account)?;
+        let fee_bps = swap_state.fee_bps()?;
+        let (reserve_a, reserve_b) =
+            Self::unpack_pool_reserves(&swap_state, vault_a_account, vault_b_account)?;
+        let (curve_type, curve_params) = swap_state.curve()?;
+
+        let pool = &mut pools[index];
+        pool.fee_bps = fee_bps;
+        pool.status = status;
+        pool.reserve_a = reserve_a;
+        pool.reserve_b = reserve_b;
+        pool.curve_type = curve_type;
+        pool.curve_params = curve_params;
+
+        msg!("Pool updated: {}", pool_account.key);
+
+        Ok(())
+    }
+
+    fn process_deregister_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let registry_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let pool_account = next_account_info(account_info_iter)?;
+
+        let mut registry_data =
+            Self::unpack_registry_for_update(program_id, registry_account, authority_account)?;
+        let (registry, entries) = PoolRegistry::unpack_mut(&mut registry_data)?;
+        let count = registry.pool_count as usize;
+
+        let index = PoolRegistry::find_pool(&entries[..count], pool_account.key)
+            .ok_or(WayfinderError::PoolNotRegistered)?;
+
+        // Shift the following entries down and zero the freed slot so it does
+        // not linger in the account
+        entries[index..count].rotate_left(1);
+        entries[count - 1] = PoolInfo::zeroed();
+        registry.pool_count -= 1;
+
+        msg!("Pool deregistered: {}", pool_account.key);
+
+        Ok(())
+    }
+
+    fn process_migrate_account(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+
+        if !authority_account.is_signer {
+            return Err(WayfinderError::AccountNotSigner.into());
+        }
+
+        if !account.is_writable || !authority_account.is_writable {
+            return Err(WayfinderError::AccountNotWritable.into());
+        }
+
+        if *system_program_account.key != system_program::id() {
+            return Err(WayfinderError::IncorrectProgramId.into());
+        }
+
+        if account.owner != program_id {
+            return Err(WayfinderError::IncorrectProgramId.into());
+        }
+
+        let (account_type, legacy) = match account.data.borrow().first().copied() {
+            None | Some(0) => return Err(ProgramError::UninitializedAccount),
+            Some(LEGACY_DISCRIMINATOR) => (AccountType::of_legacy(account.data_len()), true),
+            Some(tag) if tag == AccountType::RouteState as u8 => (AccountType::RouteState, false),
+            Some(tag) if tag == AccountType::PoolRegistry as u8 => {
+                (AccountType::PoolRegistry, false)
+            }
+            Some(_) => return Err(ProgramError::InvalidAccountData),
+        };
+
+        if !legacy {
+            Self::check_account_type(program_id, account, account_type)?;
+            msg!("Account is already at the current version");
+            return Ok(());
+        }
+
+        // Decode the original Borsh layout, then size the account for the
+        // current one and write it back
+        match account_type {
+            AccountType::RouteState => {
+                let legacy = LegacyRouteState::unpack(&account.data.borrow())?;
+                if legacy.authority != *authority_account.key {
+                    return Err(WayfinderError::AccountNotSigner.into());
+                }
+
+                // Routes are pinned to the registry they are searched in
+                let registry_account = next_account_info(account_info_iter)?;
+                Self::check_account_type(program_id, registry_account, AccountType::PoolRegistry)?;
+
+                Self::realloc_account(
+                    authority_account,
+                    account,
+                    system_program_account,
+                    RouteState::LEN,
+                )?;
+                legacy.upgrade(&mut account.data.borrow_mut(), *registry_account.key)?;
+            }
+            AccountType::PoolRegistry => {
+                let legacy = LegacyPoolRegistry::unpack(&account.data.borrow())?;
+                if legacy.authority != *authority_account.key {
+                    return Err(WayfinderError::AccountNotSigner.into());
+                }
+
+                // Keep room for as many pools as the legacy account had
+                let capacity =
+                    LegacyPoolRegistry::capacity(account.data_len()).max(legacy.pools.len());
+                let new_len = PoolRegistry::space(capacity);
+
+                // A single instruction can only grow an account so far
+                let growth = new_len.saturating_sub(account.data_len());
+                if growth > MAX_PERMITTED_DATA_INCREASE {
+                    msg!(
+                        "Registry needs {} more bytes, at most {} can be added",
+                        growth,
+                        MAX_PERMITTED_DATA_INCREASE
+                    );
+                    return Err(WayfinderError::MigrationTooLarge.into());
+                }
+
+                Self::realloc_account(authority_account, account, system_program_account, new_len)?;
+                legacy.upgrade(&mut account.data.borrow_mut())?;
+            }
+        }
+
+        msg!("Account migrated to version {}", account_type.version());
+
+        Ok(())
+    }
+
+    /// Resize a program-owned account, topping up rent from `payer`
+    fn realloc_account<'a>(
+        payer: &AccountInfo<'a>,
+        account: &AccountInfo<'a>,
+        system_program_account: &AccountInfo<'a>,
+        new_len: usize,
+    ) -> ProgramResult {
+        let required_lamports = Rent::get()?.minimum_balance(new_len);
+        let top_up = required_lamports.saturating_sub(account.lamports());
+        if top_up > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, account.key, top_up),
+                &[payer.clone(), account.clone(), system_program_account.clone()],
+            )?;
+        }
+
+        account.realloc(new_len, false)
+    }
+
+    /// Create a rent-exempt account owned by this program at a PDA, tolerating
+    /// lamports already sent to the address
+    fn create_pda_account<'a>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'a>,
+        new_account: &AccountInfo<'a>,
+        system_program_account: &AccountInfo<'a>,
+        space: usize,
+        signer_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        let required_lamports = Rent::get()?.minimum_balance(space);
+        let accounts = [payer.clone(), new_account.clone(), system_program_account.clone()];
+
+        if new_account.lamports() == 0 {
+            return invoke_signed(
+                &system_instruction::create_account(
+                    payer.key,
+                    new_account.key,
+                    required_lamports,
+                    space as u64,
+                    program_id,
+                ),
+                &accounts,
+                &[signer_seeds],
+            );
+        }
+
+        let top_up = required_lamports.saturating_sub(new_account.lamports());
+        if top_up > 0 {
+            invoke_signed(
+                &system_instruction::transfer(payer.key, new_account.key, top_up),
+                &accounts,
+                &[],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(new_account.key, space as u64),
+            &accounts,
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(new_account.key, program_id),
+            &accounts,
+            &[signer_seeds],
+        )
+    }
+
+    /// Load a writable route state account, rejecting accounts not owned by
+    /// this program or holding another account type
+    fn unpack_route_state<'a>(
+        program_id: &Pubkey,
+        route_state_account: &'a AccountInfo,
+    ) -> Result<RefMut<'a, RouteState>, ProgramError> {
+        if !route_state_account.is_writable {
+            return Err(WayfinderError::AccountNotWritable.into());
+        }
+
+        Self::check_account_type(program_id, route_state_account, AccountType::RouteState)?;
+
+        RefMut::filter_map(route_state_account.data.borrow_mut(), |data| {
+            RouteState::unpack_mut(data).ok()
+        })
+        .map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    fn process_close_route(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let route_state_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let destination_account = next_account_info(account_info_iter)?;
+
+        if !authority_account.is_signer {
+            return Err(WayfinderError::AccountNotSigner.into());
+        }
+
+        if !destination_account.is_writable {
+            return Err(WayfinderError::AccountNotWritable.into());
+        }
+
+        if route_state_account.key == destination_account.key {
+            return Err(WayfinderError::InvalidRoute.into());
+        }
+
+        if Self::unpack_route_state(program_id, route_state_account)?.authority
+            != *authority_account.key
+        {
+            return Err(WayfinderError::AccountNotSigner.into());
+        }
+
+        let lamports = route_state_account.lamports();
+        **destination_account.lamports.borrow_mut() = destination_account
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(WayfinderError::CalculationOverflow)?;
+        **route_state_account.lamports.borrow_mut() = 0;
+
+        // Hand the account back to the System program so the PDA can be reused
+        route_state_account.data.borrow_mut().fill(0);
+        route_state_account.realloc(0, false)?;
+        route_state_account.assign(&system_program::id());
+
+        msg!("Route closed, reclaimed {} lamports", lamports);
+
+        Ok(())
+    }
+
+    /// Borrow the registered pools of a registry account in place
+    fn unpack_registry<'a>(
+        program_id: &Pubkey,
+        registry_account: &'a AccountInfo,
+    ) -> Result<Ref<'a, [PoolInfo]>, ProgramError> {
+        Self::check_account_type(program_id, registry_account, AccountType::PoolRegistry)?;
+
+        Ref::filter_map(registry_account.data.borrow(), |data| {
+            PoolRegistry::unpack(data).ok().map(|(_, pools)| pools)
+        })
+        .map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Token-2022 mints passed to a route search that carry the transfer fee
+    /// extension, whatever fee it currently charges
+    fn unpack_transfer_fee_mints(
+        mint_accounts: &[AccountInfo],
+    ) -> Result<Vec<Pubkey>, ProgramError> {
+        let mut mints = Vec::with_capacity(mint_accounts.len());
+        for mint_account in mint_accounts {
+            if mint_account.owner != &spl_token_2022::id() {
+                return Err(WayfinderError::IncorrectProgramId.into());
+            }
+
+            if TransferFeeConfig::unpack_from_mint(&mint_account.data.borrow())?.is_some() {
+                mints.push(*mint_account.key);
+            }
+        }
+
+        Ok(mints)
+    }
+
+    /// Check that `account` is owned by this program and carries the
+    /// expected account type at its current layout version
+    fn check_account_type(
+        program_id: &Pubkey,
+        account: &AccountInfo,
+        account_type: AccountType,
+    ) -> ProgramResult {
+        if account.owner != program_id {
+            return Err(WayfinderError::IncorrectProgramId.into());
+        }
+
+        let data = account.data.borrow();
+        match (data.first().copied(), data.get(1).copied()) {
+            (None | Some(0), _) => Err(ProgramError::UninitializedAccount),
+            (Some(LEGACY_DISCRIMINATOR), _) => Err(WayfinderError::AccountVersionMismatch.into()),
+            (Some(tag), Some(version)) if tag == account_type as u8 => {
+                if version == account_type.version() {
+                    Ok(())
+                } else {
+                    Err(WayfinderError::AccountVersionMismatch.into())
+                }
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Mutably borrow a registry account's data once its authority has signed
+    fn unpack_registry_for_update<'a>(
+        program_id: &Pubkey,
+        registry_account: &'a AccountInfo,
+        authority_account: &AccountInfo,
+    ) -> Result<RefMut<'a, [u8]>, ProgramError> {
+        if !authority_account.is_signer {
+            return Err(WayfinderError::AccountNotSigner.into());
+        }
+
+        if !registry_account.is_writable {
+            return Err(WayfinderError::AccountNotWritable.into());
+        }
+
+        Self::check_account_type(program_id, registry_account, AccountType::PoolRegistry)?;
+
+        let mut data = RefMut::map(registry_account.data.borrow_mut(), |data| &mut **data);
+        let (registry, _) = PoolRegistry::unpack_mut(&mut data)?;
+        if registry.authority != *authority_account.key {
+            return Err(WayfinderError::AccountNotSigner.into());
+        }
+
+        Ok(data)
+    }
+
+    /// Read a token-swap pool's reserves from its vaults
+    fn unpack_pool_reserves(
+        swap_state: &TokenSwapState,
+        vault_a_account: &AccountInfo,
+        vault_b_account: &AccountInfo,
+    ) -> Result<(u64, u64), ProgramError> {
+        if *vault_a_account.key != swap_state.token_a || *vault_b_account.key != swap_state.token_b
+        {
+            return Err(WayfinderError::InvalidPoolAccount.into());
+        }
+
+        let vault_a = TokenAccount::unpack(vault_a_account)?;
+        let vault_b = TokenAccount::unpack(vault_b_account)?;
+
+        Ok((vault_a.amount, vault_b.amount))
+    }
+}
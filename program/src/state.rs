@@ -1,13 +1,71 @@
+use std::cmp::Reverse;
+
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::pubkey::Pubkey;
+use bytemuck::{Pod, Zeroable};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    curve::{ConstantProduct, PoolCurve, StableSwap, SwapCurve, Weighted},
+    error::WayfinderError,
+};
 
 pub const MAX_ROUTE_HOPS: usize = 5;
-pub const ROUTE_STATE_SIZE: usize = 8 + 32 + 32 + 8 + 8 + 1 + (MAX_ROUTE_HOPS * 32) + 1 + 32;
+pub const MAX_SPLIT_PATHS: usize = 3;
+pub const ROUTE_STATE_SIZE: usize = std::mem::size_of::<RouteState>();
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+/// Account type tag stored in the first byte of every program account,
+/// followed by a layout version byte.
+///
+/// Tag 1 was the bare discriminator of both original Borsh layouts, which had
+/// no version, and is only accepted by `MigrateAccount`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountType {
+    RouteState = 3,
+    PoolRegistry = 4,
+}
+
+impl AccountType {
+    /// Current layout version of this account type
+    pub const fn version(self) -> u8 {
+        match self {
+            Self::RouteState => ROUTE_STATE_VERSION,
+            Self::PoolRegistry => POOL_REGISTRY_VERSION,
+        }
+    }
+
+    /// Type of an account of `data_len` bytes in an unversioned layout. Both
+    /// carry `LEGACY_DISCRIMINATOR`, but a legacy route state is always
+    /// exactly `LegacyRouteState::LEN` bytes.
+    pub const fn of_legacy(data_len: usize) -> Self {
+        if data_len == LegacyRouteState::LEN {
+            Self::RouteState
+        } else {
+            Self::PoolRegistry
+        }
+    }
+}
+
+pub const ROUTE_STATE_VERSION: u8 = 1;
+pub const POOL_REGISTRY_VERSION: u8 = 1;
+
+pub const LEGACY_DISCRIMINATOR: u8 = 1;
+
+/// Route mode: spend exactly `amount_in`, receiving at least `min_amount_out`
+pub const ROUTE_MODE_EXACT_IN: u8 = 0;
+/// Route mode: buy at least `min_amount_out`, spending at most `amount_in`
+pub const ROUTE_MODE_EXACT_OUT: u8 = 1;
+
+/// Route state account, read and written in place. Packed so the layout has
+/// no padding and can be cast from account data of any alignment.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
 pub struct RouteState {
-    /// Discriminator for account type
-    pub discriminator: u8,
+    /// Account type tag, `AccountType::RouteState`
+    pub account_type: u8,
+
+    /// Layout version
+    pub version: u8,
     
     /// Input token mint
     pub input_mint: Pubkey,
@@ -15,30 +73,283 @@ pub struct RouteState {
     /// Output token mint
     pub output_mint: Pubkey,
     
-    /// Input amount
+    /// Input amount; for exact-output routes, the most input the route may
+    /// spend
     pub amount_in: u64,
     
-    /// Minimum output amount
+    /// Minimum output amount; for exact-output routes, the amount to buy
     pub min_amount_out: u64,
     
-    /// Number of hops in the route
-    pub hops: u8,
+    /// Maximum number of hops the route may take
+    pub max_hops: u8,
     
-    /// Route path (pool pubkeys)
-    pub route: Vec<Pubkey>,
+    /// Number of paths the input is split across, i.e. the used prefix of `paths`
+    pub path_count: u8,
+    
+    /// Paths of the found route, executed in order. Fixed-size so the account
+    /// size is constant
+    pub paths: [RoutePath; MAX_SPLIT_PATHS],
     
     /// Route status: 0 = uninitialized, 1 = initialized, 2 = route_found, 3 = executed
     pub status: u8,
     
     /// Authority
     pub authority: Pubkey,
+
+    /// Pool registry the route is searched in, fixed at initialization
+    pub registry: Pubkey,
+
+    /// `ROUTE_MODE_EXACT_IN` or `ROUTE_MODE_EXACT_OUT`
+    pub mode: u8,
+
+    /// 1 if the search that found the route ran out of expansion budget, so
+    /// a better route may exist
+    pub possibly_suboptimal: u8,
 }
 
 impl RouteState {
     pub const LEN: usize = ROUTE_STATE_SIZE;
+
+    pub fn unpack(data: &[u8]) -> Result<&Self, ProgramError> {
+        let data = data.get(..Self::LEN).ok_or(ProgramError::AccountDataTooSmall)?;
+        bytemuck::try_from_bytes(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    pub fn unpack_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        let data = data
+            .get_mut(..Self::LEN)
+            .ok_or(ProgramError::AccountDataTooSmall)?;
+        bytemuck::try_from_bytes_mut(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Paths of the found route, in execution order
+    pub fn paths(&self) -> &[RoutePath] {
+        &self.paths[..(self.path_count as usize).min(MAX_SPLIT_PATHS)]
+    }
+
+    /// Total number of swaps across all paths
+    pub fn total_hops(&self) -> usize {
+        self.paths().iter().map(|path| path.route().len()).sum()
+    }
+
+    /// Route the whole input through a single path
+    pub fn set_route(&mut self, route: &[Pubkey]) -> Result<(), WayfinderError> {
+        self.set_paths(&[(route, self.amount_in)])
+    }
+
+    /// Split the input across `paths`, given as pools in swap order and the
+    /// input amount routed through them. The amounts must add up to
+    /// `amount_in`, or for exact-output routes stay within it.
+    pub fn set_paths<R: AsRef<[Pubkey]>>(
+        &mut self,
+        paths: &[(R, u64)],
+    ) -> Result<(), WayfinderError> {
+        if paths.len() > MAX_SPLIT_PATHS {
+            return Err(WayfinderError::MaximumPathsExceeded);
+        }
+
+        let mut total_amount_in = 0u64;
+        for (route, amount_in) in paths {
+            let route = route.as_ref();
+            if route.is_empty() {
+                return Err(WayfinderError::InvalidRoute);
+            }
+            if route.len() > MAX_ROUTE_HOPS {
+                return Err(WayfinderError::MaximumHopsExceeded);
+            }
+            total_amount_in = total_amount_in
+                .checked_add(*amount_in)
+                .ok_or(WayfinderError::CalculationOverflow)?;
+        }
+        let within_amount_in = match self.mode {
+            ROUTE_MODE_EXACT_OUT => total_amount_in <= self.amount_in,
+            _ => total_amount_in == self.amount_in,
+        };
+        if !within_amount_in {
+            return Err(WayfinderError::InvalidRoute);
+        }
+
+        self.paths = [RoutePath::zeroed(); MAX_SPLIT_PATHS];
+        for (path, (route, amount_in)) in self.paths.iter_mut().zip(paths) {
+            let route = route.as_ref();
+            path.amount_in = *amount_in;
+            path.hops = route.len() as u8;
+            path.route[..route.len()].copy_from_slice(route);
+        }
+        self.path_count = paths.len() as u8;
+        Ok(())
+    }
+}
+
+/// One path of a route: the pools swapped through in order and the share of
+/// the route's input sent down it
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct RoutePath {
+    /// Input amount routed through this path
+    pub amount_in: u64,
+
+    /// Number of hops, i.e. the used prefix of `route`
+    pub hops: u8,
+
+    /// Pool pubkeys in swap order
+    pub route: [Pubkey; MAX_ROUTE_HOPS],
+}
+
+impl RoutePath {
+    pub const LEN: usize = std::mem::size_of::<Self>();
+
+    /// Pools of this path, in swap order
+    pub fn route(&self) -> &[Pubkey] {
+        &self.route[..(self.hops as usize).min(MAX_ROUTE_HOPS)]
+    }
+}
+
+/// Route state as originally written with Borsh, before accounts were
+/// versioned. Only read when migrating.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LegacyRouteState {
+    /// `LEGACY_DISCRIMINATOR`
+    pub discriminator: u8,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+    /// Number of hops in `route`
+    pub hops: u8,
+    pub route: Vec<Pubkey>,
+    pub status: u8,
+    pub authority: Pubkey,
+}
+
+impl LegacyRouteState {
+    /// Size the original route state accounts were created with
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 1 + (MAX_ROUTE_HOPS * 32) + 1 + 32;
+
+    /// Hop limit of the original search, which stored none and searched at
+    /// least three hops
+    const MIN_MAX_HOPS: u8 = 3;
+
+    /// Decode a legacy route state from the front of its account data
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let route_state =
+            Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)?;
+        if route_state.discriminator != LEGACY_DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(route_state)
+    }
+
+    /// Write this route state over `data` in the current layout, its route
+    /// becoming the only path of an exact-input route searched in
+    /// `registry`. `data` must already be resized to `RouteState::LEN`.
+    pub fn upgrade(&self, data: &mut [u8], registry: Pubkey) -> Result<(), ProgramError> {
+        let mut route_state = RouteState {
+            account_type: AccountType::RouteState as u8,
+            version: ROUTE_STATE_VERSION,
+            input_mint: self.input_mint,
+            output_mint: self.output_mint,
+            amount_in: self.amount_in,
+            min_amount_out: self.min_amount_out,
+            max_hops: self.hops.clamp(Self::MIN_MAX_HOPS, MAX_ROUTE_HOPS as u8),
+            path_count: 0,
+            paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
+            status: self.status,
+            authority: self.authority,
+            registry,
+            mode: ROUTE_MODE_EXACT_IN,
+            possibly_suboptimal: 0,
+        };
+        if !self.route.is_empty() {
+            route_state.set_route(&self.route)?;
+        }
+
+        *RouteState::unpack_mut(data)? = route_state;
+        Ok(())
+    }
 }
 
+/// Registry entry as originally written with Borsh. Only read when
+/// migrating.
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LegacyPoolInfo {
+    pub address: Pubkey,
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub fee_bps: u16,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+impl LegacyPoolInfo {
+    pub const LEN: usize = 32 + 32 + 32 + 2 + 8 + 8;
+}
+
+/// Pool registry as originally written with Borsh, its pools in a
+/// length-prefixed list. Only read when migrating.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LegacyPoolRegistry {
+    /// `LEGACY_DISCRIMINATOR`
+    pub discriminator: u8,
+    pub authority: Pubkey,
+    pub pools: Vec<LegacyPoolInfo>,
+}
+
+impl LegacyPoolRegistry {
+    /// Discriminator, authority and list length
+    pub const HEADER_LEN: usize = 1 + 32 + 4;
+
+    /// Decode a legacy registry from the front of its account data
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let registry =
+            Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)?;
+        if registry.discriminator != LEGACY_DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(registry)
+    }
+
+    /// Number of pools a legacy registry account of `len` bytes has room for
+    pub const fn capacity(len: usize) -> usize {
+        len.saturating_sub(Self::HEADER_LEN) / LegacyPoolInfo::LEN
+    }
+
+    /// Write this registry over `data` in the current layout. Every earlier
+    /// pool is active and constant-product. `data` must already be resized
+    /// to hold all its pools.
+    pub fn upgrade(&self, data: &mut [u8]) -> Result<(), ProgramError> {
+        data.fill(0);
+        let (registry, entries) = PoolRegistry::unpack_mut(data)?;
+        if entries.len() < self.pools.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        *registry = PoolRegistry {
+            account_type: AccountType::PoolRegistry as u8,
+            version: POOL_REGISTRY_VERSION,
+            authority: self.authority,
+            pool_count: self.pools.len() as u32,
+        };
+        for (entry, pool) in entries.iter_mut().zip(&self.pools) {
+            *entry = PoolInfo {
+                address: pool.address,
+                token_a: pool.token_a,
+                token_b: pool.token_b,
+                fee_bps: pool.fee_bps,
+                reserve_a: pool.reserve_a,
+                reserve_b: pool.reserve_b,
+                status: POOL_STATUS_ACTIVE,
+                curve_type: CURVE_CONSTANT_PRODUCT,
+                curve_params: [0; 2],
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Registry entry for a pool, also the pathfinder's graph edge
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Pod, Zeroable)]
 pub struct PoolInfo {
     /// Pool account address
     pub address: Pubkey,
@@ -57,28 +368,119 @@ pub struct PoolInfo {
     
     /// Token B reserve
     pub reserve_b: u64,
+    
+    /// Pool status: 1 = active, 2 = paused
+    pub status: u8,
+
+    /// `CURVE_CONSTANT_PRODUCT`, `CURVE_STABLE_SWAP` or `CURVE_WEIGHTED`
+    pub curve_type: u8,
+
+    /// Curve parameters: the amplification coefficient of a StableSwap pool
+    /// first, or the token A and B weights of a weighted pool
+    pub curve_params: [u64; 2],
 }
 
+pub const POOL_STATUS_ACTIVE: u8 = 1;
+pub const POOL_STATUS_PAUSED: u8 = 2;
+
+/// Pool curve: x*y=k, `curve_params[0]` of the fee's basis points going to
+/// the pool owner
+pub const CURVE_CONSTANT_PRODUCT: u8 = 0;
+/// Pool curve: StableSwap with amplification coefficient `curve_params[0]`
+pub const CURVE_STABLE_SWAP: u8 = 1;
+/// Pool curve: weighted, token A and B weighing `curve_params[0]` and
+/// `curve_params[1]`
+pub const CURVE_WEIGHTED: u8 = 2;
+
 impl PoolInfo {
-    pub fn get_output_amount(&self, input_mint: &Pubkey, amount_in: u64) -> Option<u64> {
-        let (reserve_in, reserve_out) = if input_mint == &self.token_a {
-            (self.reserve_a, self.reserve_b)
+    pub const LEN: usize = std::mem::size_of::<Self>();
+
+    pub fn is_active(&self) -> bool {
+        self.status == POOL_STATUS_ACTIVE
+    }
+
+    /// Curve for swapping from `input_mint` with the input and output
+    /// reserves, or `None` if the pool does not trade `input_mint` or its
+    /// curve type is unknown
+    pub fn curve(&self, input_mint: &Pubkey) -> Option<(PoolCurve, u64, u64)> {
+        let a_to_b = if input_mint == &self.token_a {
+            true
         } else if input_mint == &self.token_b {
-            (self.reserve_b, self.reserve_a)
+            false
         } else {
             return None;
         };
 
-        // Calculate output with fee: (amount_in * (10000 - fee_bps) * reserve_out) / (reserve_in * 10000 + amount_in * (10000 - fee_bps))
-        let amount_in_with_fee = (amount_in as u128)
-            .checked_mul((10000u128).checked_sub(self.fee_bps as u128)?)?;
-        
-        let numerator = amount_in_with_fee.checked_mul(reserve_out as u128)?;
-        let denominator = (reserve_in as u128)
-            .checked_mul(10000u128)?
-            .checked_add(amount_in_with_fee)?;
-        
-        Some((numerator / denominator) as u64)
+        let [param_a, param_b] = self.curve_params;
+        let curve = match self.curve_type {
+            CURVE_CONSTANT_PRODUCT => PoolCurve::ConstantProduct(ConstantProduct {
+                owner_fee_bps: u16::try_from(param_a).ok()?,
+            }),
+            CURVE_STABLE_SWAP => PoolCurve::StableSwap(StableSwap { amp: param_a }),
+            CURVE_WEIGHTED => {
+                let (weight_in, weight_out) = if a_to_b { (param_a, param_b) } else { (param_b, param_a) };
+                PoolCurve::Weighted(Weighted {
+                    weight_in,
+                    weight_out,
+                })
+            }
+            _ => return None,
+        };
+
+        Some(if a_to_b {
+            (curve, self.reserve_a, self.reserve_b)
+        } else {
+            (curve, self.reserve_b, self.reserve_a)
+        })
+    }
+
+    pub fn get_output_amount(&self, input_mint: &Pubkey, amount_in: u64) -> Option<u64> {
+        let (curve, reserve_in, reserve_out) = self.curve(input_mint)?;
+        curve.output_amount(reserve_in, reserve_out, self.fee_bps, amount_in)
+    }
+
+    /// Smallest input for which `get_output_amount` returns at least
+    /// `amount_out`, or `None` if the pool cannot pay that much out
+    pub fn get_input_amount(&self, input_mint: &Pubkey, amount_out: u64) -> Option<u64> {
+        let (curve, reserve_in, reserve_out) = self.curve(input_mint)?;
+        curve.input_amount(reserve_in, reserve_out, self.fee_bps, amount_out)
+    }
+
+    /// Output per unit of input for an infinitesimal trade, after fees.
+    /// Larger trades only get less per unit, but fee rounding can give small
+    /// ones a little more: see `rate_bound`.
+    pub fn spot_rate(&self, input_mint: &Pubkey) -> Option<f64> {
+        let (curve, reserve_in, reserve_out) = self.curve(input_mint)?;
+        curve.spot_rate(reserve_in, reserve_out, self.fee_bps)
+    }
+
+    /// Spot rate and offset such that `amount_in` times the rate, plus the
+    /// offset, bounds `get_output_amount` from above. The offset is what the
+    /// input the fee rounding can leave over is worth before fees.
+    pub fn rate_bound(&self, input_mint: &Pubkey) -> Option<(f64, f64)> {
+        let (curve, reserve_in, reserve_out) = self.curve(input_mint)?;
+        let rate = curve.spot_rate(reserve_in, reserve_out, self.fee_bps)?;
+        let offset = match curve.fee_rounding(self.fee_bps) {
+            0 => 0.0,
+            units => units as f64 * curve.spot_rate(reserve_in, reserve_out, 0)?,
+        };
+        Some((rate, offset))
+    }
+
+    /// Swap `amount_in` against the cached reserves, moving them as the pool
+    /// would, and return the output
+    pub fn apply_swap(&mut self, input_mint: &Pubkey, amount_in: u64) -> Option<u64> {
+        let amount_out = self.get_output_amount(input_mint, amount_in)?;
+
+        if *input_mint == self.token_a {
+            self.reserve_a = self.reserve_a.checked_add(amount_in)?;
+            self.reserve_b = self.reserve_b.checked_sub(amount_out)?;
+        } else {
+            self.reserve_b = self.reserve_b.checked_add(amount_in)?;
+            self.reserve_a = self.reserve_a.checked_sub(amount_out)?;
+        }
+
+        Some(amount_out)
     }
 
     pub fn get_other_token(&self, token: &Pubkey) -> Option<Pubkey> {
@@ -90,17 +492,696 @@ impl PoolInfo {
             None
         }
     }
+
+    /// Output of swapping `amount_in` through `hops`, each a pool with the
+    /// mint it is swapped from, in order
+    pub fn get_chain_output_amount(hops: &[(PoolInfo, Pubkey)], amount_in: u64) -> Option<u64> {
+        hops.iter()
+            .try_fold(amount_in, |amount, (pool, input_mint)| pool.get_output_amount(input_mint, amount))
+    }
+
+    /// Input into a cycle of swaps that maximizes output minus input, with
+    /// that profit, or `None` if no input turns a profit. `hops` are the
+    /// pools in swap order with the mint each is swapped from.
+    ///
+    /// A chain of constant-product hops composes into a single curve
+    /// x -> a*x / (b + c*x), whose profit peaks at x* = (sqrt(a*b) - b) / c.
+    /// Each hop rounds its fees and output, so the integer optimum may sit a
+    /// little off x*; it is found by scanning as far around x* as that
+    /// rounding can shift it, or searched for numerically within that range
+    /// when it is too wide to scan. Chains through other curves are searched
+    /// numerically below the last hop's output reserve, which no profitable
+    /// input can reach.
+    pub fn optimal_cycle_input(hops: &[(PoolInfo, Pubkey)]) -> Option<(u64, u64)> {
+        let output = |amount_in: u64| Self::get_chain_output_amount(hops, amount_in);
+        if hops.iter().any(|(pool, _)| pool.curve_type != CURVE_CONSTANT_PRODUCT) {
+            let (pool, input_mint) = hops.last()?;
+            let (_, _, reserve_out) = pool.curve(input_mint)?;
+            return Self::maximize_profit(1, reserve_out, output);
+        }
+
+        // Compose the hops, along with the most the rounding of each hop's
+        // fees and output can move the end of the chain: fees are each within
+        // a unit of their exact share of the input, and outputs within a unit
+        let (mut a, mut b, mut c) = (1.0f64, 1.0f64, 0.0f64);
+        let mut rounding_error = 0.0f64;
+        for (pool, input_mint) in hops {
+            let (reserve_in, reserve_out) = if *input_mint == pool.token_a {
+                (pool.reserve_a as f64, pool.reserve_b as f64)
+            } else if *input_mint == pool.token_b {
+                (pool.reserve_b as f64, pool.reserve_a as f64)
+            } else {
+                return None;
+            };
+            let gamma = (10000.0 - pool.fee_bps as f64) / 10000.0;
+            (a, b, c) = (gamma * reserve_out * a, reserve_in * b, reserve_in * c + gamma * a);
+            rounding_error =
+                rounding_error * pool.spot_rate(input_mint)? + 2.0 * reserve_out / reserve_in + 1.0;
+        }
+
+        if !(a.is_finite() && b.is_finite() && c.is_finite()) || c <= 0.0 {
+            return Self::maximize_profit(1, u64::MAX, output);
+        }
+
+        // Profit peaks at x*, falling off with the curvature there. Rounding
+        // moves each profit by at most `rounding_error` either way, so no
+        // input further off than `reach` can beat x* once rounded.
+        let optimum = if a > b { ((a * b).sqrt() - b) / c } else { 0.0 };
+        let curvature = 2.0 * a * b * c / (b + c * optimum).powi(3);
+        let reach = (4.0 * rounding_error / curvature).sqrt().ceil() + 2.0;
+        if !(optimum.is_finite() && reach.is_finite()) {
+            return Self::maximize_profit(1, u64::MAX, output);
+        }
+
+        let low = (optimum - reach).max(1.0) as u64;
+        let high = (optimum + reach).min(u64::MAX as f64) as u64;
+        if high - low > 2 * MAX_CYCLE_INPUT_SCAN {
+            return Self::maximize_profit(low, high, output);
+        }
+
+        let profit = |amount_in: u64| {
+            output(amount_in).map_or(0, |amount_out| amount_out.saturating_sub(amount_in))
+        };
+        let amount_in = (low..=high).max_by_key(|&amount_in| (profit(amount_in), Reverse(amount_in)))?;
+        let best = profit(amount_in);
+        (best > 0).then_some((amount_in, best))
+    }
+
+    /// Numeric search for the input between `min_amount_in` and
+    /// `max_amount_in` that maximizes `output` of it minus the input, with
+    /// that profit, for `optimal_cycle_input` on curves without a closed
+    /// form. The gain must rise then fall with the input.
+    ///
+    /// Gains are compared signed, so losses keep falling past the peak
+    /// rather than flattening out at zero profit, and the search starts from
+    /// the smallest input paying anything out, below which there is no gain
+    /// to compare. The input is doubled from there while the gain grows and
+    /// the peak then narrowed down by ternary search. Rounding makes the gain
+    /// wobble near the peak, so inputs a little to either side of where the
+    /// search ends are checked too.
+    pub fn maximize_profit(
+        min_amount_in: u64,
+        max_amount_in: u64,
+        output: impl Fn(u64) -> Option<u64>,
+    ) -> Option<(u64, u64)> {
+        let gain = |amount_in: u64| {
+            output(amount_in)
+                .filter(|amount_out| *amount_out > 0)
+                .map(|amount_out| amount_out as i128 - amount_in as i128)
+        };
+        let min_amount_in = min_amount_in.max(1);
+        if min_amount_in > max_amount_in {
+            return None;
+        }
+
+        // Smallest input paying out, by doubling then bisecting
+        let mut low = min_amount_in - 1;
+        let mut high = min_amount_in;
+        while gain(high).is_none() {
+            if high == max_amount_in {
+                return None;
+            }
+            low = high;
+            high = high
+                .saturating_add(high - min_amount_in + 1)
+                .min(max_amount_in);
+        }
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if gain(mid).is_some() {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+        let first = high;
+
+        // Double the distance from it while the gain grows
+        let step = |amount_in: u64| {
+            amount_in
+                .saturating_add(amount_in - first + 1)
+                .min(max_amount_in)
+        };
+        let mut peak = first;
+        while step(peak) != peak && gain(step(peak)) >= gain(peak) {
+            peak = step(peak);
+        }
+        let mut low = first + (peak - first) / 2;
+        let mut high = step(peak);
+
+        while high - low > 2 {
+            let third = (high - low) / 3;
+            if gain(low + third) < gain(high - third) {
+                low += third + 1;
+            } else {
+                high -= third;
+            }
+        }
+
+        let window = low.saturating_sub(PROFIT_SEARCH_MARGIN).max(first)
+            ..=high.saturating_add(PROFIT_SEARCH_MARGIN).min(max_amount_in);
+        let mut amount_in = window.max_by_key(|&amount_in| (gain(amount_in), Reverse(amount_in)))?;
+        let best = gain(amount_in)?;
+        if best <= 0 {
+            return None;
+        }
+
+        // The top can be flatter than the window is wide: take the smallest
+        // input reaching the best gain, which rises up to it
+        let mut low = first - 1;
+        while amount_in - low > 1 {
+            let mid = low + (amount_in - low) / 2;
+            if gain(mid) >= Some(best) {
+                amount_in = mid;
+            } else {
+                low = mid;
+            }
+        }
+
+        Some((amount_in, best as u64))
+    }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+/// Most inputs `PoolInfo::optimal_cycle_input` scans on each side of the
+/// closed-form optimum before falling back to a numeric search
+pub const MAX_CYCLE_INPUT_SCAN: u64 = 1 << 16;
+
+/// Inputs `PoolInfo::maximize_profit` checks on each side of the peak its
+/// search narrows down to
+pub const PROFIT_SEARCH_MARGIN: u64 = 64;
+
+/// Pool registry account header, followed in the account by a `PoolInfo`
+/// array with room for `PoolRegistry::capacity` entries
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
 pub struct PoolRegistry {
-    /// Discriminator
-    pub discriminator: u8,
+    /// Account type tag, `AccountType::PoolRegistry`
+    pub account_type: u8,
+
+    /// Layout version
+    pub version: u8,
     
     /// Authority
     pub authority: Pubkey,
     
-    /// Registered pools
-    pub pools: Vec<PoolInfo>,
+    /// Number of registered pools at the start of the entry array
+    pub pool_count: u32,
 }
 
+impl PoolRegistry {
+    /// Size of the fixed header preceding the pool entries
+    pub const HEADER_LEN: usize = std::mem::size_of::<Self>();
+
+    /// Account size needed to hold `max_pools` entries
+    pub const fn space(max_pools: usize) -> usize {
+        Self::HEADER_LEN + max_pools * PoolInfo::LEN
+    }
+
+    /// Number of entries an account of `data_len` bytes can hold
+    pub const fn capacity(data_len: usize) -> usize {
+        data_len.saturating_sub(Self::HEADER_LEN) / PoolInfo::LEN
+    }
+
+    /// Split registry account data into the header and the registered pools
+    pub fn unpack(data: &[u8]) -> Result<(&Self, &[PoolInfo]), ProgramError> {
+        if data.len() < Self::HEADER_LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        let (header, entries) = data.split_at(Self::HEADER_LEN);
+        let header: &Self =
+            bytemuck::try_from_bytes(header).map_err(|_| ProgramError::InvalidAccountData)?;
+        let pools = Self::entries(entries)?
+            .get(..header.pool_count as usize)
+            .ok_or(ProgramError::InvalidAccountData)?;
+
+        Ok((header, pools))
+    }
+
+    /// Split registry account data into the header and every entry slot,
+    /// registered or not
+    pub fn unpack_mut(data: &mut [u8]) -> Result<(&mut Self, &mut [PoolInfo]), ProgramError> {
+        if data.len() < Self::HEADER_LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        let (header, entries) = data.split_at_mut(Self::HEADER_LEN);
+        let header: &mut Self =
+            bytemuck::try_from_bytes_mut(header).map_err(|_| ProgramError::InvalidAccountData)?;
+        let capacity = entries.len() / PoolInfo::LEN;
+        if header.pool_count as usize > capacity {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let entries = bytemuck::try_cast_slice_mut(&mut entries[..capacity * PoolInfo::LEN])
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+
+        Ok((header, entries))
+    }
+
+    fn entries(data: &[u8]) -> Result<&[PoolInfo], ProgramError> {
+        let capacity = data.len() / PoolInfo::LEN;
+        bytemuck::try_cast_slice(&data[..capacity * PoolInfo::LEN])
+            .map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    pub fn find_pool(pools: &[PoolInfo], address: &Pubkey) -> Option<usize> {
+        pools.iter().position(|pool| pool.address == *address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route_state(amount_in: u64) -> RouteState {
+        RouteState {
+            account_type: AccountType::RouteState as u8,
+            version: ROUTE_STATE_VERSION,
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            amount_in,
+            min_amount_out: 0,
+            max_hops: MAX_ROUTE_HOPS as u8,
+            path_count: 0,
+            paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
+            status: 1,
+            authority: Pubkey::new_unique(),
+            registry: Pubkey::new_unique(),
+            mode: ROUTE_MODE_EXACT_IN,
+            possibly_suboptimal: 0,
+        }
+    }
+
+    fn new_route(hops: usize) -> Vec<Pubkey> {
+        (0..hops).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn test_route_state_len_matches_max_size_layout() {
+        let mut route_state = route_state(u64::MAX);
+        let paths: Vec<(Vec<Pubkey>, u64)> = (0..MAX_SPLIT_PATHS)
+            .map(|i| {
+                let amount_in = if i == 0 { u64::MAX - (MAX_SPLIT_PATHS as u64 - 1) } else { 1 };
+                (new_route(MAX_ROUTE_HOPS), amount_in)
+            })
+            .collect();
+        route_state.set_paths(&paths).unwrap();
+
+        let data = bytemuck::bytes_of(&route_state);
+        assert_eq!(data.len(), RouteState::LEN);
+
+        let decoded = RouteState::unpack(data).unwrap();
+        assert_eq!(decoded.paths().len(), MAX_SPLIT_PATHS);
+        for (path, (route, amount_in)) in decoded.paths().iter().zip(&paths) {
+            assert_eq!(path.route(), &route[..]);
+            assert_eq!({ path.amount_in }, *amount_in);
+        }
+        assert_eq!(decoded.total_hops(), MAX_SPLIT_PATHS * MAX_ROUTE_HOPS);
+    }
+
+    #[test]
+    fn test_set_route_rejects_too_many_hops() {
+        let mut route_state = route_state(0);
+
+        assert!(matches!(
+            route_state.set_route(&new_route(MAX_ROUTE_HOPS + 1)),
+            Err(WayfinderError::MaximumHopsExceeded)
+        ));
+        assert!(route_state.paths().is_empty());
+    }
+
+    #[test]
+    fn test_set_paths_rejects_invalid_split() {
+        let mut route_state = route_state(1_000);
+
+        // Amounts must add up to the route's input
+        assert!(matches!(
+            route_state.set_paths(&[(new_route(1), 600), (new_route(2), 300)]),
+            Err(WayfinderError::InvalidRoute)
+        ));
+        assert!(matches!(
+            route_state.set_paths(&vec![(new_route(1), 250); MAX_SPLIT_PATHS + 1]),
+            Err(WayfinderError::MaximumPathsExceeded)
+        ));
+        assert!(matches!(
+            route_state.set_paths(&[(new_route(0), 1_000)]),
+            Err(WayfinderError::InvalidRoute)
+        ));
+        assert!(route_state.paths().is_empty());
+
+        route_state
+            .set_paths(&[(new_route(1), 600), (new_route(2), 400)])
+            .unwrap();
+        assert_eq!(route_state.path_count, 2);
+        assert_eq!(route_state.total_hops(), 3);
+    }
+
+    #[test]
+    fn test_pool_registry_unpack() {
+        let pool = PoolInfo {
+            address: Pubkey::new_unique(),
+            token_a: Pubkey::new_unique(),
+            token_b: Pubkey::new_unique(),
+            fee_bps: 30,
+            reserve_a: 1_000,
+            reserve_b: 2_000,
+            status: POOL_STATUS_ACTIVE,
+            curve_type: CURVE_CONSTANT_PRODUCT,
+            curve_params: [0; 2],
+        };
+        let authority = Pubkey::new_unique();
+
+        let mut data = vec![0u8; PoolRegistry::space(3) + 1];
+        assert_eq!(PoolRegistry::capacity(data.len()), 3);
+        {
+            let (header, entries) = PoolRegistry::unpack_mut(&mut data).unwrap();
+            assert_eq!(entries.len(), 3);
+            header.account_type = AccountType::PoolRegistry as u8;
+            header.version = POOL_REGISTRY_VERSION;
+            header.authority = authority;
+            header.pool_count = 1;
+            entries[0] = pool;
+        }
+
+        let (header, pools) = PoolRegistry::unpack(&data).unwrap();
+        assert_eq!(header.authority, authority);
+        assert_eq!(pools, &[pool]);
+        assert_eq!(PoolRegistry::find_pool(pools, &pool.address), Some(0));
+
+        // A count beyond the account's capacity is corrupt
+        data[PoolRegistry::HEADER_LEN - 4..PoolRegistry::HEADER_LEN]
+            .copy_from_slice(&4u32.to_le_bytes());
+        assert!(PoolRegistry::unpack(&data).is_err());
+        assert!(PoolRegistry::unpack_mut(&mut data).is_err());
+    }
+
+    #[test]
+    fn test_upgrade_legacy_route_state() {
+        let route = new_route(2);
+        let legacy = LegacyRouteState {
+            discriminator: LEGACY_DISCRIMINATOR,
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            amount_in: 1_000,
+            min_amount_out: 900,
+            hops: 2,
+            route: route.clone(),
+            status: 2,
+            authority: Pubkey::new_unique(),
+        };
+
+        // Legacy accounts hold the Borsh encoding followed by unused bytes
+        let mut data = legacy.try_to_vec().unwrap();
+        data.resize(LegacyRouteState::LEN, 0);
+        assert_eq!(AccountType::of_legacy(data.len()), AccountType::RouteState);
+        let decoded = LegacyRouteState::unpack(&data).unwrap();
+        assert_eq!(decoded.route, route);
+
+        // The route becomes the only path of an exact-input route
+        let registry = Pubkey::new_unique();
+        data.resize(RouteState::LEN, u8::MAX);
+        decoded.upgrade(&mut data, registry).unwrap();
+        let route_state = RouteState::unpack(&data).unwrap();
+        assert_eq!(route_state.account_type, AccountType::RouteState as u8);
+        assert_eq!(route_state.version, ROUTE_STATE_VERSION);
+        assert_eq!(route_state.mode, ROUTE_MODE_EXACT_IN);
+        assert_eq!(route_state.input_mint, legacy.input_mint);
+        assert_eq!(route_state.output_mint, legacy.output_mint);
+        assert_eq!({ route_state.amount_in }, 1_000);
+        assert_eq!({ route_state.min_amount_out }, 900);
+        assert_eq!(route_state.max_hops, 3);
+        assert_eq!(route_state.paths().len(), 1);
+        assert_eq!(route_state.paths()[0].route(), &route[..]);
+        assert_eq!({ route_state.paths()[0].amount_in }, 1_000);
+        assert_eq!(route_state.status, 2);
+        assert_eq!(route_state.authority, legacy.authority);
+        assert_eq!(route_state.registry, registry);
+
+        // A route not found yet has no paths
+        let initialized = LegacyRouteState {
+            hops: 0,
+            route: Vec::new(),
+            status: 1,
+            ..legacy
+        };
+        initialized.upgrade(&mut data, registry).unwrap();
+        let route_state = RouteState::unpack(&data).unwrap();
+        assert!(route_state.paths().is_empty());
+        assert_eq!(route_state.status, 1);
+
+        data[0] = AccountType::RouteState as u8;
+        assert!(LegacyRouteState::unpack(&data).is_err());
+    }
+
+    #[test]
+    fn test_get_input_amount_inverts_get_output_amount() {
+        let token_a = Pubkey::new_unique();
+        let token_b = Pubkey::new_unique();
+        let pool = PoolInfo {
+            address: Pubkey::new_unique(),
+            token_a,
+            token_b,
+            fee_bps: 30,
+            reserve_a: 1_000_000,
+            reserve_b: 3_000_000,
+            status: POOL_STATUS_ACTIVE,
+            curve_type: CURVE_CONSTANT_PRODUCT,
+            curve_params: [0; 2],
+        };
+
+        for (input_mint, amount_out) in [(token_a, 1), (token_a, 12_345), (token_b, 999_999), (token_a, 2_999_999)] {
+            let amount_in = pool.get_input_amount(&input_mint, amount_out).unwrap();
+            assert!(pool.get_output_amount(&input_mint, amount_in).unwrap() >= amount_out);
+            assert!(pool
+                .get_output_amount(&input_mint, amount_in - 1)
+                .map_or(true, |output| output < amount_out));
+        }
+
+        assert_eq!(pool.get_input_amount(&token_a, 0), Some(0));
+        assert_eq!(pool.get_input_amount(&token_a, 3_000_000), None);
+        assert_eq!(pool.get_input_amount(&Pubkey::new_unique(), 1), None);
+    }
+
+    #[test]
+    fn test_optimal_cycle_input_matches_brute_force_sweep() {
+        let mut seed = 5u64;
+        let mut next = |bound: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+
+        let mut profitable = 0;
+        for _ in 0..100 {
+            let hop_count = 2 + next(3) as usize;
+            let tokens: Vec<Pubkey> = (0..hop_count).map(|_| Pubkey::new_unique()).collect();
+            let hops: Vec<(PoolInfo, Pubkey)> = (0..hop_count)
+                .map(|i| {
+                    let pool = PoolInfo {
+                        address: Pubkey::new_unique(),
+                        token_a: tokens[i],
+                        token_b: tokens[(i + 1) % hop_count],
+                        fee_bps: next(100) as u16,
+                        reserve_a: 1_000 + next(10_000),
+                        reserve_b: 1_000 + next(10_000),
+                        status: POOL_STATUS_ACTIVE,
+                        curve_type: CURVE_CONSTANT_PRODUCT,
+                        curve_params: [0; 2],
+                    };
+                    (pool, tokens[i])
+                })
+                .collect();
+
+            // No input beyond the last pool's reserves can profit
+            let sweep = (1..=11_000u64)
+                .map(|amount_in| {
+                    PoolInfo::get_chain_output_amount(&hops, amount_in)
+                        .map_or(0, |amount_out| amount_out.saturating_sub(amount_in))
+                })
+                .max()
+                .unwrap();
+
+            match PoolInfo::optimal_cycle_input(&hops) {
+                Some((amount_in, profit)) => {
+                    profitable += 1;
+                    assert_eq!(profit, sweep);
+                    let amount_out = PoolInfo::get_chain_output_amount(&hops, amount_in).unwrap();
+                    assert_eq!(amount_out - amount_in, profit);
+                }
+                None => assert_eq!(sweep, 0),
+            }
+        }
+        assert!(profitable > 10);
+    }
+
+    #[test]
+    fn test_optimal_cycle_input_on_flat_profit_matches_sweep() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool = |reserve_a, reserve_b, fee_bps| PoolInfo {
+            address: Pubkey::new_unique(),
+            token_a: a,
+            token_b: b,
+            fee_bps,
+            reserve_a,
+            reserve_b,
+            status: POOL_STATUS_ACTIVE,
+            curve_type: CURVE_CONSTANT_PRODUCT,
+            curve_params: [0; 2],
+        };
+
+        // Rates multiplying to barely above 1, or exactly 1, so rounding
+        // leaves profit flat at zero or a few units over wide ranges
+        for (first, second) in [
+            (pool(10_000_000, 10_030_000, 0), pool(10_000_000, 10_000_000, 10)),
+            (pool(10_000_000, 10_003_000, 0), pool(10_000_000, 10_000_000, 0)),
+            (pool(10_000_000, 10_000_000, 0), pool(10_000_000, 10_000_000, 0)),
+        ] {
+            let hops = [(first, a), (second, b)];
+            let profit = |amount_in: u64| {
+                PoolInfo::get_chain_output_amount(&hops, amount_in)
+                    .map_or(0, |amount_out| amount_out.saturating_sub(amount_in))
+            };
+            let amount_in = (1..=100_000u64)
+                .max_by_key(|&amount_in| (profit(amount_in), Reverse(amount_in)))
+                .unwrap();
+            let sweep = (profit(amount_in) > 0).then(|| (amount_in, profit(amount_in)));
+            assert_eq!(PoolInfo::optimal_cycle_input(&hops), sweep);
+        }
+    }
+
+    #[test]
+    fn test_optimal_cycle_input_on_mixed_curves_matches_sweep() {
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let pool = |token_a, token_b, reserve_a, reserve_b, curve_type, curve_params| PoolInfo {
+            address: Pubkey::new_unique(),
+            token_a,
+            token_b,
+            fee_bps: 4,
+            reserve_a,
+            reserve_b,
+            status: POOL_STATUS_ACTIVE,
+            curve_type,
+            curve_params,
+        };
+        // Stable A/B priced off a skewed constant-product B/C and weighted C/A
+        let hops = [
+            (pool(a, b, 50_000, 50_000, CURVE_STABLE_SWAP, [100, 0]), a),
+            (pool(b, c, 40_000, 44_000, CURVE_CONSTANT_PRODUCT, [0; 2]), b),
+            (pool(c, a, 30_000, 90_000, CURVE_WEIGHTED, [75, 25]), c),
+        ];
+
+        let sweep = (1..=50_000u64)
+            .map(|amount_in| {
+                // Hops paying nothing out fail
+                PoolInfo::get_chain_output_amount(&hops, amount_in)
+                    .map_or(0, |amount_out| amount_out.saturating_sub(amount_in))
+            })
+            .max()
+            .unwrap();
+        let (amount_in, profit) = PoolInfo::optimal_cycle_input(&hops).unwrap();
+        assert!(sweep > 0);
+        assert_eq!(profit, sweep);
+        let amount_out = PoolInfo::get_chain_output_amount(&hops, amount_in).unwrap();
+        assert_eq!(amount_out - amount_in, profit);
+    }
+
+    #[test]
+    fn test_upgrade_legacy_pool_registry() {
+        let legacy_pool = LegacyPoolInfo {
+            address: Pubkey::new_unique(),
+            token_a: Pubkey::new_unique(),
+            token_b: Pubkey::new_unique(),
+            fee_bps: 30,
+            reserve_a: 1_000,
+            reserve_b: 2_000,
+        };
+        let other = LegacyPoolInfo {
+            address: Pubkey::new_unique(),
+            fee_bps: 5,
+            ..legacy_pool.clone()
+        };
+        let legacy = LegacyPoolRegistry {
+            discriminator: LEGACY_DISCRIMINATOR,
+            authority: Pubkey::new_unique(),
+            pools: vec![legacy_pool.clone(), other.clone()],
+        };
+
+        let mut data = legacy.try_to_vec().unwrap();
+        assert_eq!(data.len(), LegacyPoolRegistry::HEADER_LEN + 2 * LegacyPoolInfo::LEN);
+        data.resize(LegacyPoolRegistry::HEADER_LEN + 3 * LegacyPoolInfo::LEN, 0);
+        assert_eq!(LegacyPoolRegistry::capacity(data.len()), 3);
+        assert_eq!(AccountType::of_legacy(data.len()), AccountType::PoolRegistry);
+        let decoded = LegacyPoolRegistry::unpack(&data).unwrap();
+        assert_eq!(decoded.pools, legacy.pools);
+
+        // Earlier pools are active and constant-product
+        data.resize(PoolRegistry::space(3), u8::MAX);
+        decoded.upgrade(&mut data).unwrap();
+        let (registry, pools) = PoolRegistry::unpack(&data).unwrap();
+        assert_eq!(registry.account_type, AccountType::PoolRegistry as u8);
+        assert_eq!(registry.version, POOL_REGISTRY_VERSION);
+        assert_eq!(registry.authority, legacy.authority);
+        assert_eq!(pools.len(), 2);
+        for (pool, legacy_pool) in pools.iter().zip(&legacy.pools) {
+            assert_eq!(pool.address, legacy_pool.address);
+            assert_eq!((pool.token_a, pool.token_b), (legacy_pool.token_a, legacy_pool.token_b));
+            assert_eq!({ pool.fee_bps }, legacy_pool.fee_bps);
+            assert_eq!(({ pool.reserve_a }, { pool.reserve_b }), (1_000, 2_000));
+            assert!(pool.is_active());
+            assert_eq!(pool.curve_type, CURVE_CONSTANT_PRODUCT);
+        }
+
+        // The current layout must hold every pool
+        let mut small = vec![0; PoolRegistry::space(1)];
+        assert!(decoded.upgrade(&mut small).is_err());
+    }
+
+    #[test]
+    fn test_maximize_profit_finds_peak() {
+        // Profit rising to 1_000 at 5_000 and falling after, flat at the peak
+        let output = |amount_in: u64| {
+            Some(amount_in.saturating_add(1_000).saturating_sub(amount_in.abs_diff(5_000) / 3))
+        };
+        assert_eq!(PoolInfo::maximize_profit(1, u64::MAX, output), Some((4_998, 1_000)));
+        let output = |amount_in: u64| {
+            Some(amount_in.saturating_add(3_000).saturating_sub(amount_in.abs_diff(5_000)))
+        };
+        assert_eq!(PoolInfo::maximize_profit(1, u64::MAX, output), Some((5_000, 3_000)));
+        assert_eq!(PoolInfo::maximize_profit(1, u64::MAX, Some), None);
+    }
+
+    #[test]
+    fn test_maximize_profit_matches_scan_across_plateaus() {
+        let scan = |output: &dyn Fn(u64) -> Option<u64>| {
+            let profit = |amount_in: u64| {
+                output(amount_in).map_or(0, |amount_out| amount_out.saturating_sub(amount_in))
+            };
+            let amount_in = (1..=100_000u64).max_by_key(|&amount_in| (profit(amount_in), Reverse(amount_in)))?;
+            let best = profit(amount_in);
+            (best > 0).then_some((amount_in, best))
+        };
+
+        // Constant-product curve paying `rate` times the input at first,
+        // rounded down, and nothing below `first`
+        let cycle = |rate: (u64, u64), reserve: u64, first: u64| {
+            move |amount_in: u64| {
+                let amount_out = amount_in as u128 * rate.0 as u128 * reserve as u128
+                    / (rate.1 as u128 * (reserve + amount_in) as u128);
+                (amount_in >= first).then_some(amount_out as u64)
+            }
+        };
+        for output in [
+            // Rounding leaves zero profit on small inputs as on large ones
+            cycle((1_001, 1_000), 10_000_000, 1),
+            cycle((1_002, 1_000), 20_000_000, 1),
+            cycle((3, 2), 1_000, 1),
+            // Nothing paid out until close to the peak
+            cycle((2, 1), 100, 40),
+            cycle((1_001, 1_000), 10_000_000, 4_900),
+            // Never profitable
+            cycle((999, 1_000), 10_000_000, 1),
+            cycle((1_001, 1_000), 10_000_000, 20_000),
+        ] {
+            assert_eq!(PoolInfo::maximize_profit(1, 100_000, output), scan(&output));
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use solana_program::{
+    account_info::AccountInfo,
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::WayfinderError,
+    state::{CURVE_CONSTANT_PRODUCT, CURVE_STABLE_SWAP},
+};
+
+/// SPL Token-Swap program
+pub mod spl_token_swap {
+    solana_program::declare_id!("SwapsVeCiPHMUAtzQWZw7RjsKjgCjhwU55QGu4U1Szw");
+}
+
+/// Instruction tag of `SwapInstruction::Swap` in the token-swap program
+const SWAP_TAG: u8 = 1;
+
+/// Size of a `SwapVersion::SwapV1` token-swap account
+pub const TOKEN_SWAP_LEN: usize = 324;
+
+/// Fees are in basis points of the input
+const BPS: u64 = 10_000;
+
+/// Token-swap `CurveType::ConstantProduct`
+pub const SWAP_CURVE_CONSTANT_PRODUCT: u8 = 0;
+/// Token-swap `CurveType::Stable`, whose calculator starts with the
+/// amplification coefficient
+pub const SWAP_CURVE_STABLE: u8 = 2;
+
+/// Fields of a token-swap pool account needed to validate it for routing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenSwapState {
+    pub token_program_id: Pubkey,
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub pool_mint: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub pool_fee_account: Pubkey,
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub owner_trade_fee_numerator: u64,
+    pub owner_trade_fee_denominator: u64,
+    pub curve_type: u8,
+    pub curve_calculator: [u8; 32],
+}
+
+impl TokenSwapState {
+    pub fn unpack(account: &AccountInfo) -> Result<Self, ProgramError> {
+        if account.owner != &spl_token_swap::id() {
+            return Err(WayfinderError::InvalidPoolAccount.into());
+        }
+
+        Self::unpack_from_slice(&account.data.borrow())
+    }
+
+    pub fn unpack_from_slice(data: &[u8]) -> Result<Self, ProgramError> {
+        // Version byte followed by `is_initialized`
+        if data.len() < TOKEN_SWAP_LEN || data[0] != 1 || data[1] != 1 {
+            return Err(WayfinderError::InvalidPoolState.into());
+        }
+
+        let read_pubkey = |offset: usize| {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(&data[offset..offset + 32]);
+            Pubkey::new_from_array(bytes)
+        };
+        let read_u64 = |offset: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[offset..offset + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        let mut curve_calculator = [0u8; 32];
+        curve_calculator.copy_from_slice(&data[292..324]);
+
+        Ok(Self {
+            token_program_id: read_pubkey(3),
+            token_a: read_pubkey(35),
+            token_b: read_pubkey(67),
+            pool_mint: read_pubkey(99),
+            token_a_mint: read_pubkey(131),
+            token_b_mint: read_pubkey(163),
+            pool_fee_account: read_pubkey(195),
+            trade_fee_numerator: read_u64(227),
+            trade_fee_denominator: read_u64(235),
+            owner_trade_fee_numerator: read_u64(243),
+            owner_trade_fee_denominator: read_u64(251),
+            curve_type: data[291],
+            curve_calculator,
+        })
+    }
+
+    /// Total trade fee of the pool, trade and owner fee together, in basis
+    /// points. Fees that are not a whole number of basis points cannot be
+    /// priced by the router and are rejected.
+    pub fn fee_bps(&self) -> Result<u16, ProgramError> {
+        let (trade_fee, owner_fee) = self.fees_bps()?;
+        Ok(trade_fee + owner_fee)
+    }
+
+    /// Trade and owner trade fees in basis points
+    fn fees_bps(&self) -> Result<(u16, u16), ProgramError> {
+        let trade_fee = fraction_bps(self.trade_fee_numerator, self.trade_fee_denominator);
+        let owner_fee =
+            fraction_bps(self.owner_trade_fee_numerator, self.owner_trade_fee_denominator);
+
+        trade_fee
+            .zip(owner_fee)
+            .filter(|(trade_fee, owner_fee)| trade_fee + owner_fee < BPS)
+            .map(|(trade_fee, owner_fee)| (trade_fee as u16, owner_fee as u16))
+            .ok_or_else(|| WayfinderError::InvalidPoolAccount.into())
+    }
+
+    /// Registry curve type and parameters of the pool's swap curve. Only
+    /// curves the router can price are accepted.
+    pub fn curve(&self) -> Result<(u8, [u64; 2]), ProgramError> {
+        match self.curve_type {
+            SWAP_CURVE_CONSTANT_PRODUCT => {
+                // Constant product pools round the owner fee apart from the
+                // trade fee
+                let (_, owner_fee) = self.fees_bps()?;
+                Ok((CURVE_CONSTANT_PRODUCT, [owner_fee as u64, 0]))
+            }
+            SWAP_CURVE_STABLE => {
+                let mut amp = [0u8; 8];
+                amp.copy_from_slice(&self.curve_calculator[..8]);
+                Ok((CURVE_STABLE_SWAP, [u64::from_le_bytes(amp), 0]))
+            }
+            _ => Err(WayfinderError::InvalidPoolAccount.into()),
+        }
+    }
+}
+
+/// Fee fraction `numerator / denominator` in basis points, if it is a whole
+/// number of them. Token-swap charges nothing for a zero numerator, whatever
+/// the denominator.
+fn fraction_bps(numerator: u64, denominator: u64) -> Option<u64> {
+    if numerator == 0 {
+        return Some(0);
+    }
+
+    let scaled = numerator.checked_mul(BPS)?;
+    if denominator == 0 || scaled % denominator != 0 {
+        return None;
+    }
+    Some(scaled / denominator)
+}
+
+/// Number of accounts the caller supplies for every hop of an executed route
+pub const SWAP_HOP_ACCOUNTS: usize = 10;
+
+/// Accounts for a single token-swap hop, in the order they are passed to
+/// `ExecuteRoute`. The source token account is not part of the group: it is
+/// the user's input account for the first hop and the previous hop's
+/// destination for every following one.
+pub struct SwapHopAccounts<'a, 'b> {
+    pub swap_program: &'a AccountInfo<'b>,
+    pub pool: &'a AccountInfo<'b>,
+    pub swap_authority: &'a AccountInfo<'b>,
+    pub swap_source: &'a AccountInfo<'b>,
+    pub swap_destination: &'a AccountInfo<'b>,
+    pub pool_mint: &'a AccountInfo<'b>,
+    pub pool_fee: &'a AccountInfo<'b>,
+    pub source_mint: &'a AccountInfo<'b>,
+    pub destination_mint: &'a AccountInfo<'b>,
+    pub destination: &'a AccountInfo<'b>,
+}
+
+impl<'a, 'b> SwapHopAccounts<'a, 'b> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        if accounts.len() != SWAP_HOP_ACCOUNTS {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        Ok(Self {
+            swap_program: &accounts[0],
+            pool: &accounts[1],
+            swap_authority: &accounts[2],
+            swap_source: &accounts[3],
+            swap_destination: &accounts[4],
+            pool_mint: &accounts[5],
+            pool_fee: &accounts[6],
+            source_mint: &accounts[7],
+            destination_mint: &accounts[8],
+            destination: &accounts[9],
+        })
+    }
+}
+
+/// Build a token-swap `Swap` instruction. The swap program moves both tokens
+/// and mints pool fee tokens through `token_program_id`, the token program
+/// the pool was created with.
+#[allow(clippy::too_many_arguments)]
+pub fn swap(
+    swap_program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    pool: &Pubkey,
+    swap_authority: &Pubkey,
+    user_transfer_authority: &Pubkey,
+    source: &Pubkey,
+    swap_source: &Pubkey,
+    swap_destination: &Pubkey,
+    destination: &Pubkey,
+    pool_mint: &Pubkey,
+    pool_fee: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    let mut data = Vec::with_capacity(17);
+    data.push(SWAP_TAG);
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+
+    Instruction {
+        program_id: *swap_program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new_readonly(*swap_authority, false),
+            AccountMeta::new_readonly(*user_transfer_authority, true),
+            AccountMeta::new(*source, false),
+            AccountMeta::new(*swap_source, false),
+            AccountMeta::new(*swap_destination, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new(*pool_mint, false),
+            AccountMeta::new(*pool_fee, false),
+            AccountMeta::new_readonly(*token_program_id, false),
+        ],
+        data,
+    }
+}
+
+/// Token program out of `token_programs` that owns `mint`
+fn mint_token_program<'b, 'a>(
+    mint: &AccountInfo<'a>,
+    token_programs: [&'b AccountInfo<'a>; 2],
+) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    token_programs
+        .into_iter()
+        .find(|program| program.key == mint.owner)
+        .ok_or_else(|| WayfinderError::IncorrectProgramId.into())
+}
+
+/// Swap `amount_in` from `source` through a single token-swap pool, through
+/// whichever of the SPL Token and Token-2022 programs owns the pool's mints
+pub fn invoke_swap<'a>(
+    hop: &SwapHopAccounts<'_, 'a>,
+    source: &AccountInfo<'a>,
+    user_transfer_authority: &AccountInfo<'a>,
+    token_programs: [&AccountInfo<'a>; 2],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<(), ProgramError> {
+    // A pool moves every token through the one token program it was
+    // created with
+    let token_program = mint_token_program(hop.pool_mint, token_programs)?;
+    if hop.source_mint.owner != token_program.key
+        || hop.destination_mint.owner != token_program.key
+    {
+        return Err(WayfinderError::IncorrectProgramId.into());
+    }
+
+    let instruction = swap(
+        hop.swap_program.key,
+        token_program.key,
+        hop.pool.key,
+        hop.swap_authority.key,
+        user_transfer_authority.key,
+        source.key,
+        hop.swap_source.key,
+        hop.swap_destination.key,
+        hop.destination.key,
+        hop.pool_mint.key,
+        hop.pool_fee.key,
+        amount_in,
+        minimum_amount_out,
+    );
+
+    invoke(
+        &instruction,
+        &[
+            hop.pool.clone(),
+            hop.swap_authority.clone(),
+            user_transfer_authority.clone(),
+            source.clone(),
+            hop.swap_source.clone(),
+            hop.swap_destination.clone(),
+            hop.destination.clone(),
+            hop.pool_mint.clone(),
+            hop.pool_fee.clone(),
+            token_program.clone(),
+            hop.swap_program.clone(),
+        ],
+    )
+}
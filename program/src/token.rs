@@ -0,0 +1,277 @@
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::error::WayfinderError;
+
+/// SPL Token program
+pub mod spl_token {
+    solana_program::declare_id!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+}
+
+/// SPL Token-2022 program
+pub mod spl_token_2022 {
+    solana_program::declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+}
+
+/// Whether `program_id` is one of the token programs a route can move
+/// tokens through
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    program_id == &spl_token::id() || program_id == &spl_token_2022::id()
+}
+
+/// Size of an SPL token account
+pub const TOKEN_ACCOUNT_LEN: usize = 165;
+
+/// Size of an SPL mint without extensions
+pub const MINT_LEN: usize = 82;
+
+/// Token-2022 stores the account type right after the base account, padded
+/// to the size of a token account, followed by the extension TLV entries
+const ACCOUNT_TYPE_OFFSET: usize = TOKEN_ACCOUNT_LEN;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const EXTENSION_UNINITIALIZED: u16 = 0;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+/// Two authorities and the withheld amount precede the two fees
+const TRANSFER_FEE_OFFSET: usize = 72;
+const TRANSFER_FEE_LEN: usize = 18;
+
+const MAX_FEE_BASIS_POINTS: u16 = 10_000;
+
+const MINT_OFFSET: usize = 0;
+const OWNER_OFFSET: usize = 32;
+const AMOUNT_OFFSET: usize = 64;
+
+/// Minimal read-only view over the fields of an SPL token account that the
+/// router needs, without pulling in the full token program crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+impl TokenAccount {
+    pub fn unpack(account: &AccountInfo) -> Result<Self, ProgramError> {
+        if !is_token_program(account.owner) {
+            return Err(WayfinderError::IncorrectProgramId.into());
+        }
+
+        Self::unpack_from_slice(&account.data.borrow())
+    }
+
+    pub fn unpack_from_slice(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < TOKEN_ACCOUNT_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let read_pubkey = |offset: usize| {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(&data[offset..offset + 32]);
+            Pubkey::new_from_array(bytes)
+        };
+
+        let mut amount = [0u8; 8];
+        amount.copy_from_slice(&data[AMOUNT_OFFSET..AMOUNT_OFFSET + 8]);
+
+        Ok(Self {
+            mint: read_pubkey(MINT_OFFSET),
+            owner: read_pubkey(OWNER_OFFSET),
+            amount: u64::from_le_bytes(amount),
+        })
+    }
+}
+
+/// Token-2022 transfer fee in effect from `epoch` onwards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferFee {
+    pub epoch: u64,
+    pub maximum_fee: u64,
+    pub transfer_fee_basis_points: u16,
+}
+
+impl TransferFee {
+    /// Fee withheld from a transfer of `amount`, rounded up and capped at
+    /// `maximum_fee` like the token program does
+    pub fn calculate_fee(&self, amount: u64) -> Option<u64> {
+        let bps = u128::from(self.transfer_fee_basis_points);
+        if bps == 0 || amount == 0 {
+            return Some(0);
+        }
+
+        let fee = (u128::from(amount) * bps).div_ceil(u128::from(MAX_FEE_BASIS_POINTS));
+
+        Some(u64::try_from(fee).ok()?.min(self.maximum_fee))
+    }
+
+    /// Amount that arrives when `amount` is transferred
+    pub fn amount_after_fee(&self, amount: u64) -> Option<u64> {
+        amount.checked_sub(self.calculate_fee(amount)?)
+    }
+
+    /// Smallest amount to transfer for at least `post_fee_amount` to arrive
+    pub fn calculate_pre_fee_amount(&self, post_fee_amount: u64) -> Option<u64> {
+        let bps = self.transfer_fee_basis_points;
+        if bps == 0 || post_fee_amount == 0 {
+            return Some(post_fee_amount);
+        }
+        if bps >= MAX_FEE_BASIS_POINTS {
+            return post_fee_amount.checked_add(self.maximum_fee);
+        }
+
+        let numerator = u128::from(post_fee_amount) * u128::from(MAX_FEE_BASIS_POINTS);
+        let denominator = u128::from(MAX_FEE_BASIS_POINTS - bps);
+        let pre_fee_amount = numerator.div_ceil(denominator);
+
+        if pre_fee_amount - u128::from(post_fee_amount) >= u128::from(self.maximum_fee) {
+            post_fee_amount.checked_add(self.maximum_fee)
+        } else {
+            u64::try_from(pre_fee_amount).ok()
+        }
+    }
+}
+
+/// Transfer fee configuration of a Token-2022 mint. A fee change is staged
+/// as the newer fee and only takes effect from its epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferFeeConfig {
+    pub older_transfer_fee: TransferFee,
+    pub newer_transfer_fee: TransferFee,
+}
+
+impl TransferFeeConfig {
+    /// Fee charged on transfers during `epoch`
+    pub fn get_epoch_fee(&self, epoch: u64) -> &TransferFee {
+        if epoch >= self.newer_transfer_fee.epoch {
+            &self.newer_transfer_fee
+        } else {
+            &self.older_transfer_fee
+        }
+    }
+
+    /// Transfer fee configuration of a mint account's data, or `None` when
+    /// the mint has no transfer fee extension
+    pub fn unpack_from_mint(data: &[u8]) -> Result<Option<Self>, ProgramError> {
+        if data.len() < MINT_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if data.len() <= ACCOUNT_TYPE_OFFSET {
+            return Ok(None);
+        }
+        if data[ACCOUNT_TYPE_OFFSET] != ACCOUNT_TYPE_MINT {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let read_u64 = |offset: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[offset..offset + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        let read_fee = |offset: usize| TransferFee {
+            epoch: read_u64(offset),
+            maximum_fee: read_u64(offset + 8),
+            transfer_fee_basis_points: read_u16(offset + 16),
+        };
+
+        let mut offset = ACCOUNT_TYPE_OFFSET + 1;
+        while offset + 4 <= data.len() {
+            let extension_type = read_u16(offset);
+            let length = usize::from(read_u16(offset + 2));
+            let value = offset + 4;
+            if extension_type == EXTENSION_UNINITIALIZED {
+                break;
+            }
+            if value + length > data.len() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            if extension_type == EXTENSION_TRANSFER_FEE_CONFIG {
+                if length < TRANSFER_FEE_OFFSET + 2 * TRANSFER_FEE_LEN {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+
+                let fees = value + TRANSFER_FEE_OFFSET;
+                return Ok(Some(Self {
+                    older_transfer_fee: read_fee(fees),
+                    newer_transfer_fee: read_fee(fees + TRANSFER_FEE_LEN),
+                }));
+            }
+
+            offset = value + length;
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee(transfer_fee_basis_points: u16, maximum_fee: u64) -> TransferFee {
+        TransferFee {
+            epoch: 0,
+            maximum_fee,
+            transfer_fee_basis_points,
+        }
+    }
+
+    #[test]
+    fn test_transfer_fee_rounds_up_and_caps() {
+        let fee = fee(150, 5_000);
+        assert_eq!(fee.calculate_fee(0), Some(0));
+        assert_eq!(fee.calculate_fee(1), Some(1));
+        assert_eq!(fee.calculate_fee(10_000), Some(150));
+        assert_eq!(fee.calculate_fee(10_001), Some(151));
+        assert_eq!(fee.calculate_fee(1_000_000), Some(5_000));
+        assert_eq!(fee.amount_after_fee(10_000), Some(9_850));
+    }
+
+    #[test]
+    fn test_pre_fee_amount_covers_fee() {
+        for fee in [fee(0, 0), fee(1, u64::MAX), fee(150, 5_000), fee(9_999, 100), fee(10_000, 7)] {
+            for post_fee_amount in [0, 1, 99, 10_000, 123_457, 1_000_000_000] {
+                let pre_fee_amount = fee.calculate_pre_fee_amount(post_fee_amount).unwrap();
+                assert!(fee.amount_after_fee(pre_fee_amount).unwrap() >= post_fee_amount);
+                if pre_fee_amount > 0 {
+                    assert!(fee.amount_after_fee(pre_fee_amount - 1).unwrap() < post_fee_amount);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpack_transfer_fee_config_from_mint() {
+        let older = TransferFee {
+            epoch: 10,
+            maximum_fee: 1_000,
+            transfer_fee_basis_points: 25,
+        };
+        let newer = TransferFee {
+            epoch: 20,
+            maximum_fee: 2_000,
+            transfer_fee_basis_points: 50,
+        };
+
+        let mut data = vec![0u8; ACCOUNT_TYPE_OFFSET];
+        data.push(ACCOUNT_TYPE_MINT);
+        // An unrelated extension ahead of the fee config
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(&32u16.to_le_bytes());
+        data.extend_from_slice(&[7u8; 32]);
+        data.extend_from_slice(&EXTENSION_TRANSFER_FEE_CONFIG.to_le_bytes());
+        data.extend_from_slice(&108u16.to_le_bytes());
+        data.extend_from_slice(&[0u8; TRANSFER_FEE_OFFSET]);
+        for fee in [older, newer] {
+            data.extend_from_slice(&fee.epoch.to_le_bytes());
+            data.extend_from_slice(&fee.maximum_fee.to_le_bytes());
+            data.extend_from_slice(&fee.transfer_fee_basis_points.to_le_bytes());
+        }
+
+        let config = TransferFeeConfig::unpack_from_mint(&data).unwrap().unwrap();
+        assert_eq!(config.get_epoch_fee(15), &older);
+        assert_eq!(config.get_epoch_fee(20), &newer);
+
+        assert_eq!(TransferFeeConfig::unpack_from_mint(&[0u8; MINT_LEN]), Ok(None));
+        assert!(TransferFeeConfig::unpack_from_mint(&data[..data.len() - 1]).is_err());
+    }
+}
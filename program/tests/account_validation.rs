@@ -0,0 +1,697 @@
+//! Regression tests rejecting spoofed accounts: data that would deserialize
+//! correctly, but owned by another program or holding another account type.
+
+mod common;
+
+use borsh::BorshSerialize;
+use bytemuck::Zeroable;
+use common::{add_registry, add_route_state, process_instruction, TestPool};
+use solana_program_test::ProgramTest;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::TransactionError,
+};
+use wayfinder::{
+    error::WayfinderError,
+    instruction::WayfinderInstruction,
+    state::{
+        AccountType, PoolInfo, PoolRegistry, RoutePath, RouteState, CURVE_CONSTANT_PRODUCT,
+        MAX_SPLIT_PATHS, POOL_STATUS_ACTIVE, ROUTE_MODE_EXACT_IN, ROUTE_STATE_VERSION,
+    },
+    token::spl_token_2022,
+};
+
+fn custom_error(error: WayfinderError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+fn program_error(error: ProgramError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::from(u64::from(error)))
+}
+
+fn route_state(authority: Pubkey, status: u8) -> RouteState {
+    let mut route_state = RouteState {
+        account_type: AccountType::RouteState as u8,
+        version: ROUTE_STATE_VERSION,
+        input_mint: Pubkey::new_unique(),
+        output_mint: Pubkey::new_unique(),
+        amount_in: 1_000,
+        min_amount_out: 0,
+        max_hops: 3,
+        path_count: 0,
+        paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
+        status,
+        authority,
+        registry: Pubkey::new_unique(),
+        mode: ROUTE_MODE_EXACT_IN,
+        possibly_suboptimal: 0,
+    };
+    route_state.set_route(&[Pubkey::new_unique()]).unwrap();
+    route_state
+}
+
+async fn run(
+    program_test: ProgramTest,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> TransactionError {
+    let mut context = program_test.start_with_context().await;
+    process_instruction(&mut context, instruction, signers)
+        .await
+        .unwrap_err()
+}
+
+fn initialize_route(
+    program_id: Pubkey,
+    route_state: Pubkey,
+    authority: Pubkey,
+    registry: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state, false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(registry, false),
+        ],
+        data: WayfinderInstruction::InitializeRoute {
+            input_mint: Pubkey::new_unique().to_bytes(),
+            output_mint: Pubkey::new_unique().to_bytes(),
+            amount_in: 1_000,
+            min_amount_out: 0,
+            max_hops: 3,
+            nonce: 0,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn test_initialize_route_rejects_non_pda_route_state() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account::new(1_000_000_000, 0, &system_program::id()),
+    );
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        program_id,
+        Pubkey::new_unique(),
+        &[],
+        1,
+    );
+
+    let instruction = initialize_route(
+        program_id,
+        Pubkey::new_unique(),
+        authority.pubkey(),
+        registry_address,
+    );
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        program_error(ProgramError::InvalidSeeds)
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_route_rejects_foreign_registry() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account::new(1_000_000_000, 0, &system_program::id()),
+    );
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        &[],
+        1,
+    );
+
+    let (route_state_address, _) =
+        wayfinder::find_route_state_address(&program_id, &authority.pubkey(), 0);
+    let instruction = initialize_route(
+        program_id,
+        route_state_address,
+        authority.pubkey(),
+        registry_address,
+    );
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+fn find_optimal_route(
+    program_id: Pubkey,
+    route_state: Pubkey,
+    authority: Pubkey,
+    registry: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state, false),
+            AccountMeta::new_readonly(authority, true),
+            AccountMeta::new_readonly(registry, false),
+        ],
+        data: WayfinderInstruction::FindOptimalRoute.try_to_vec().unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn test_find_optimal_route_rejects_foreign_route_state() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        program_id,
+        Pubkey::new_unique(),
+        &[],
+        1,
+    );
+    let route_state_address = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state_address,
+        Pubkey::new_unique(),
+        &RouteState {
+            registry: registry_address,
+            ..route_state(authority.pubkey(), 1)
+        },
+    );
+
+    assert_eq!(
+        run(
+            program_test,
+            find_optimal_route(
+                program_id,
+                route_state_address,
+                authority.pubkey(),
+                registry_address
+            ),
+            &[&authority]
+        )
+        .await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+#[tokio::test]
+async fn test_find_optimal_route_rejects_foreign_registry() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let authority = Keypair::new();
+
+    // A registry claiming a perfect pool between the route's mints, which
+    // the route was initialized with
+    let registry_address = Pubkey::new_unique();
+    let route_state = RouteState {
+        registry: registry_address,
+        ..route_state(authority.pubkey(), 1)
+    };
+    let route_state_address = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state_address,
+        program_id,
+        &route_state,
+    );
+
+    add_registry(
+        &mut program_test,
+        registry_address,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        &[PoolInfo {
+            address: Pubkey::new_unique(),
+            token_a: route_state.input_mint,
+            token_b: route_state.output_mint,
+            fee_bps: 0,
+            reserve_a: 1,
+            reserve_b: u64::MAX,
+            status: POOL_STATUS_ACTIVE,
+            curve_type: CURVE_CONSTANT_PRODUCT,
+            curve_params: [0; 2],
+        }],
+        1,
+    );
+
+    assert_eq!(
+        run(
+            program_test,
+            find_optimal_route(
+                program_id,
+                route_state_address,
+                authority.pubkey(),
+                registry_address
+            ),
+            &[&authority]
+        )
+        .await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+#[tokio::test]
+async fn test_find_optimal_route_rejects_registry_as_route_state() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        program_id,
+        Pubkey::new_unique(),
+        &[],
+        4,
+    );
+
+    assert_eq!(
+        run(
+            program_test,
+            find_optimal_route(
+                program_id,
+                registry_address,
+                authority.pubkey(),
+                registry_address
+            ),
+            &[&authority]
+        )
+        .await,
+        program_error(ProgramError::InvalidAccountData)
+    );
+}
+
+#[tokio::test]
+async fn test_find_optimal_route_rejects_other_authority() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let attacker = Keypair::new();
+
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        program_id,
+        Pubkey::new_unique(),
+        &[],
+        1,
+    );
+    let route_state_address = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state_address,
+        program_id,
+        &RouteState {
+            registry: registry_address,
+            ..route_state(authority.pubkey(), 1)
+        },
+    );
+
+    assert_eq!(
+        run(
+            program_test,
+            find_optimal_route(
+                program_id,
+                route_state_address,
+                attacker.pubkey(),
+                registry_address
+            ),
+            &[&attacker]
+        )
+        .await,
+        custom_error(WayfinderError::AccountNotSigner)
+    );
+}
+
+#[tokio::test]
+async fn test_find_optimal_route_rejects_unpinned_registry() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+
+    // A registry of this program, but not the one the route was initialized
+    // with
+    let route_state = route_state(authority.pubkey(), 1);
+    let route_state_address = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state_address,
+        program_id,
+        &route_state,
+    );
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        program_id,
+        authority.pubkey(),
+        &[],
+        1,
+    );
+
+    assert_eq!(
+        run(
+            program_test,
+            find_optimal_route(
+                program_id,
+                route_state_address,
+                authority.pubkey(),
+                registry_address
+            ),
+            &[&authority]
+        )
+        .await,
+        custom_error(WayfinderError::RegistryMismatch)
+    );
+}
+
+#[tokio::test]
+async fn test_execute_route_rejects_foreign_route_state() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+
+    let route_state_address = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state_address,
+        Pubkey::new_unique(),
+        &route_state(authority.pubkey(), 2),
+    );
+
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state_address, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_token_2022::id(), false),
+        ],
+        data: WayfinderInstruction::ExecuteRoute.try_to_vec().unwrap(),
+    };
+
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+#[tokio::test]
+async fn test_execute_route_rejects_readonly_route_state() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+
+    let route_state_address = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state_address,
+        program_id,
+        &route_state(authority.pubkey(), 2),
+    );
+
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(route_state_address, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_token_2022::id(), false),
+        ],
+        data: WayfinderInstruction::ExecuteRoute.try_to_vec().unwrap(),
+    };
+
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        custom_error(WayfinderError::AccountNotWritable)
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_registry_rejects_foreign_registry() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+
+    let registry_address = Pubkey::new_unique();
+    program_test.add_account(
+        registry_address,
+        Account {
+            lamports: 1_000_000_000,
+            data: vec![0; PoolRegistry::space(4)],
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(registry_address, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+        ],
+        data: WayfinderInstruction::InitializeRegistry
+            .try_to_vec()
+            .unwrap(),
+    };
+
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+/// Registry owned by `registry_owner` and authorised to `authority`, with one
+/// registered token-swap pool
+fn registry_with_pool(
+    program_id: Pubkey,
+    registry_owner: Pubkey,
+    authority: Pubkey,
+) -> (ProgramTest, Pubkey, TestPool) {
+    let mut program_test = common::program_test(program_id);
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    common::add_mint(&mut program_test, mint_a, Pubkey::new_unique());
+    common::add_mint(&mut program_test, mint_b, Pubkey::new_unique());
+    let pool = TestPool::add(&mut program_test, mint_a, mint_b, 1_000, 1_000);
+
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        registry_owner,
+        authority,
+        &[PoolInfo {
+            address: pool.address,
+            token_a: mint_a,
+            token_b: mint_b,
+            fee_bps: 30,
+            reserve_a: 1_000,
+            reserve_b: 1_000,
+            status: POOL_STATUS_ACTIVE,
+            curve_type: CURVE_CONSTANT_PRODUCT,
+            curve_params: [0; 2],
+        }],
+        4,
+    );
+
+    (program_test, registry_address, pool)
+}
+
+fn pool_accounts(registry: Pubkey, authority: Pubkey, pool: &TestPool) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(registry, false),
+        AccountMeta::new_readonly(authority, true),
+        AccountMeta::new_readonly(pool.address, false),
+        AccountMeta::new_readonly(pool.vault_a, false),
+        AccountMeta::new_readonly(pool.vault_b, false),
+    ]
+}
+
+fn register_pool(program_id: Pubkey, accounts: Vec<AccountMeta>, pool: &TestPool) -> Instruction {
+    Instruction {
+        program_id,
+        accounts,
+        data: WayfinderInstruction::RegisterPool {
+            token_a_mint: pool.mint_a.to_bytes(),
+            token_b_mint: pool.mint_b.to_bytes(),
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn test_register_pool_rejects_foreign_registry() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let (program_test, registry_address, pool) =
+        registry_with_pool(program_id, Pubkey::new_unique(), authority.pubkey());
+
+    // Registering the same pool again would otherwise fail as a duplicate
+    let mut accounts = pool_accounts(registry_address, authority.pubkey(), &pool);
+    accounts[2] = AccountMeta::new_readonly(Pubkey::new_unique(), false);
+
+    assert_eq!(
+        run(
+            program_test,
+            register_pool(program_id, accounts, &pool),
+            &[&authority]
+        )
+        .await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+#[tokio::test]
+async fn test_register_pool_rejects_foreign_pool_account() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let mut program_test = common::program_test(program_id);
+
+    let registry_address = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry_address,
+        program_id,
+        authority.pubkey(),
+        &[],
+        4,
+    );
+
+    // A well-formed token-swap pool, re-owned by an arbitrary program
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let pool = TestPool::add(&mut program_test, mint_a, mint_b, 1_000, 1_000);
+    let spoofed = Pubkey::new_unique();
+    let mut data = vec![0; wayfinder::swap::TOKEN_SWAP_LEN];
+    data[0] = 1;
+    data[1] = 1;
+    data[131..163].copy_from_slice(mint_a.as_ref());
+    data[163..195].copy_from_slice(mint_b.as_ref());
+    program_test.add_account(
+        spoofed,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut accounts = pool_accounts(registry_address, authority.pubkey(), &pool);
+    accounts[2] = AccountMeta::new_readonly(spoofed, false);
+
+    assert_eq!(
+        run(
+            program_test,
+            register_pool(program_id, accounts, &pool),
+            &[&authority]
+        )
+        .await,
+        custom_error(WayfinderError::InvalidPoolAccount)
+    );
+}
+
+#[tokio::test]
+async fn test_update_pool_rejects_foreign_registry() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let (program_test, registry_address, pool) =
+        registry_with_pool(program_id, Pubkey::new_unique(), authority.pubkey());
+
+    let instruction = Instruction {
+        program_id,
+        accounts: pool_accounts(registry_address, authority.pubkey(), &pool),
+        data: WayfinderInstruction::UpdatePool {
+            status: POOL_STATUS_ACTIVE,
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+#[tokio::test]
+async fn test_deregister_pool_rejects_foreign_registry() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let (program_test, registry_address, pool) =
+        registry_with_pool(program_id, Pubkey::new_unique(), authority.pubkey());
+
+    let instruction = Instruction {
+        program_id,
+        accounts: pool_accounts(registry_address, authority.pubkey(), &pool)[..3].to_vec(),
+        data: WayfinderInstruction::DeregisterPool.try_to_vec().unwrap(),
+    };
+
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        custom_error(WayfinderError::IncorrectProgramId)
+    );
+}
+
+#[tokio::test]
+async fn test_deregister_pool_rejects_route_state_as_registry() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let mut program_test = common::program_test(program_id);
+
+    let route_state_address = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state_address,
+        program_id,
+        &route_state(authority.pubkey(), 1),
+    );
+
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state_address, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),
+        ],
+        data: WayfinderInstruction::DeregisterPool.try_to_vec().unwrap(),
+    };
+
+    assert_eq!(
+        run(program_test, instruction, &[&authority]).await,
+        program_error(ProgramError::InvalidAccountData)
+    );
+}
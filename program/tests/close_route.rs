@@ -0,0 +1,115 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::{add_registry, initialize_route_instruction, process_instruction};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::TransactionError,
+};
+use wayfinder::{error::WayfinderError, find_route_state_address, instruction::WayfinderInstruction};
+
+const NONCE: u64 = 1;
+const REGISTRY: Pubkey = Pubkey::new_from_array([1; 32]);
+
+/// Initialized route state owned by a funded authority
+async fn setup() -> (ProgramTestContext, Pubkey, Keypair, Pubkey) {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account::new(1_000_000_000, 0, &system_program::id()),
+    );
+
+    add_registry(&mut program_test, REGISTRY, program_id, Pubkey::new_unique(), &[], 1);
+
+    let mut context = program_test.start_with_context().await;
+    let (route_state, _) = find_route_state_address(&program_id, &authority.pubkey(), NONCE);
+    let instruction =
+        initialize_route_instruction(program_id, route_state, authority.pubkey(), REGISTRY, 3, NONCE);
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    (context, program_id, authority, route_state)
+}
+
+fn close_route(
+    program_id: Pubkey,
+    route_state: Pubkey,
+    authority: Pubkey,
+    destination: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state, false),
+            AccountMeta::new_readonly(authority, true),
+            AccountMeta::new(destination, false),
+        ],
+        data: WayfinderInstruction::CloseRoute.try_to_vec().unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn test_close_route() {
+    let (mut context, program_id, authority, route_state) = setup().await;
+    let route_lamports = context
+        .banks_client
+        .get_account(route_state)
+        .await
+        .unwrap()
+        .unwrap()
+        .lamports;
+
+    let destination = Pubkey::new_unique();
+    let instruction = close_route(program_id, route_state, authority.pubkey(), destination);
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    assert!(context
+        .banks_client
+        .get_account(route_state)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        context.banks_client.get_balance(destination).await.unwrap(),
+        route_lamports
+    );
+
+    // The address can be initialized again once closed
+    let instruction =
+        initialize_route_instruction(program_id, route_state, authority.pubkey(), REGISTRY, 3, NONCE);
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_close_route_wrong_authority() {
+    let (mut context, program_id, _, route_state) = setup().await;
+
+    let impostor = Keypair::new();
+    let instruction = close_route(
+        program_id,
+        route_state,
+        impostor.pubkey(),
+        impostor.pubkey(),
+    );
+    assert_eq!(
+        process_instruction(&mut context, instruction, &[&impostor])
+            .await
+            .unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::AccountNotSigner as u32)
+        )
+    );
+}
@@ -0,0 +1,439 @@
+#![allow(dead_code)]
+
+use borsh::BorshSerialize;
+use solana_program_test::{processor, BanksClient, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use ::spl_token_swap::processor::Processor as TokenSwapProcessor;
+use wayfinder::{
+    instruction::WayfinderInstruction,
+    state::{AccountType, PoolInfo, PoolRegistry, RouteState, POOL_REGISTRY_VERSION},
+    swap::{spl_token_swap, SWAP_CURVE_CONSTANT_PRODUCT, TOKEN_SWAP_LEN},
+    token::{spl_token_2022, TransferFee, TOKEN_ACCOUNT_LEN},
+};
+
+/// Program test with wayfinder and the SPL token-swap program as builtins
+pub fn program_test(program_id: Pubkey) -> ProgramTest {
+    let mut program_test = ProgramTest::new(
+        "wayfinder",
+        program_id,
+        processor!(wayfinder::entrypoint::process_instruction),
+    );
+    program_test.add_program(
+        "spl_token_swap",
+        spl_token_swap::id(),
+        processor!(TokenSwapProcessor::process),
+    );
+    program_test
+}
+
+pub fn add_mint(program_test: &mut ProgramTest, mint: Pubkey, authority: Pubkey) {
+    add_packable(
+        program_test,
+        mint,
+        spl_token::id(),
+        Mint {
+            mint_authority: COption::Some(authority),
+            supply: 1_000_000_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+    );
+}
+
+/// Token-2022 mint charging `transfer_fee_basis_points` on every transfer,
+/// uncapped, in all epochs
+pub fn add_transfer_fee_mint(
+    program_test: &mut ProgramTest,
+    mint: Pubkey,
+    authority: Pubkey,
+    transfer_fee_basis_points: u16,
+) {
+    let mut data = vec![0u8; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::Some(authority),
+            supply: 1_000_000_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut data,
+    )
+    .unwrap();
+
+    // Account type, then the transfer fee config extension: two
+    // authorities, the withheld amount and the older and newer fees
+    data.resize(TOKEN_ACCOUNT_LEN, 0);
+    data.push(1);
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&108u16.to_le_bytes());
+    data.extend_from_slice(&[0u8; 72]);
+    let fee = TransferFee {
+        epoch: 0,
+        maximum_fee: u64::MAX,
+        transfer_fee_basis_points,
+    };
+    for fee in [fee, fee] {
+        data.extend_from_slice(&fee.epoch.to_le_bytes());
+        data.extend_from_slice(&fee.maximum_fee.to_le_bytes());
+        data.extend_from_slice(&fee.transfer_fee_basis_points.to_le_bytes());
+    }
+
+    program_test.add_account(
+        mint,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: spl_token_2022::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+pub fn add_token_account(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+) {
+    add_packable(
+        program_test,
+        address,
+        spl_token::id(),
+        TokenAccount {
+            mint,
+            owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        },
+    );
+}
+
+fn add_packable<T: Pack>(program_test: &mut ProgramTest, address: Pubkey, owner: Pubkey, state: T) {
+    let mut data = vec![0u8; T::LEN];
+    T::pack(state, &mut data).unwrap();
+    program_test.add_account(
+        address,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+pub fn add_route_state(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    owner: Pubkey,
+    route_state: &RouteState,
+) {
+    let data = bytemuck::bytes_of(route_state).to_vec();
+    program_test.add_account(
+        address,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Trade and owner trade fee fractions of test pools: a 25 bps trade fee and
+/// no owner fee
+pub const TEST_POOL_FEES: [u64; 4] = [25, 10_000, 0, 10_000];
+
+/// Token-swap pool written directly into the bank
+pub struct TestPool {
+    pub address: Pubkey,
+    pub authority: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    pub pool_mint: Pubkey,
+    pub pool_fee: Pubkey,
+}
+
+impl TestPool {
+    /// Constant-product pool
+    pub fn add(
+        program_test: &mut ProgramTest,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        reserve_a: u64,
+        reserve_b: u64,
+    ) -> Self {
+        Self::add_with_curve(
+            program_test,
+            mint_a,
+            mint_b,
+            reserve_a,
+            reserve_b,
+            SWAP_CURVE_CONSTANT_PRODUCT,
+            &[],
+        )
+    }
+
+    /// Pool with token-swap curve type `curve_type` and calculator state
+    /// `calculator`
+    pub fn add_with_curve(
+        program_test: &mut ProgramTest,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        reserve_a: u64,
+        reserve_b: u64,
+        curve_type: u8,
+        calculator: &[u8],
+    ) -> Self {
+        Self::add_pool(
+            program_test,
+            (mint_a, mint_b),
+            (reserve_a, reserve_b),
+            curve_type,
+            calculator,
+            TEST_POOL_FEES,
+        )
+    }
+
+    /// Constant-product pool charging trade fee `fees[0] / fees[1]` and owner
+    /// trade fee `fees[2] / fees[3]`
+    pub fn add_with_fees(
+        program_test: &mut ProgramTest,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        reserve_a: u64,
+        reserve_b: u64,
+        fees: [u64; 4],
+    ) -> Self {
+        Self::add_pool(
+            program_test,
+            (mint_a, mint_b),
+            (reserve_a, reserve_b),
+            SWAP_CURVE_CONSTANT_PRODUCT,
+            &[],
+            fees,
+        )
+    }
+
+    fn add_pool(
+        program_test: &mut ProgramTest,
+        (mint_a, mint_b): (Pubkey, Pubkey),
+        (reserve_a, reserve_b): (u64, u64),
+        curve_type: u8,
+        calculator: &[u8],
+        fees: [u64; 4],
+    ) -> Self {
+        let address = Pubkey::new_unique();
+        let (authority, bump) =
+            Pubkey::find_program_address(&[address.as_ref()], &spl_token_swap::id());
+        let pool = Self {
+            address,
+            authority,
+            mint_a,
+            mint_b,
+            vault_a: Pubkey::new_unique(),
+            vault_b: Pubkey::new_unique(),
+            pool_mint: Pubkey::new_unique(),
+            pool_fee: Pubkey::new_unique(),
+        };
+
+        add_token_account(program_test, pool.vault_a, mint_a, authority, reserve_a);
+        add_token_account(program_test, pool.vault_b, mint_b, authority, reserve_b);
+        add_mint(program_test, pool.pool_mint, authority);
+        add_token_account(program_test, pool.pool_fee, pool.pool_mint, authority, 0);
+
+        let mut data = vec![0u8; TOKEN_SWAP_LEN];
+        data[0] = 1; // SwapVersion::SwapV1
+        data[1] = 1; // is_initialized
+        data[2] = bump;
+        data[3..35].copy_from_slice(spl_token::id().as_ref());
+        data[35..67].copy_from_slice(pool.vault_a.as_ref());
+        data[67..99].copy_from_slice(pool.vault_b.as_ref());
+        data[99..131].copy_from_slice(pool.pool_mint.as_ref());
+        data[131..163].copy_from_slice(mint_a.as_ref());
+        data[163..195].copy_from_slice(mint_b.as_ref());
+        data[195..227].copy_from_slice(pool.pool_fee.as_ref());
+        // Trade and owner trade fees, then no owner withdraw or host fees
+        let fees = [fees[0], fees[1], fees[2], fees[3], 0, 10_000, 0, 100];
+        for (i, fee) in fees.iter().enumerate() {
+            let offset = 227 + i * 8;
+            data[offset..offset + 8].copy_from_slice(&fee.to_le_bytes());
+        }
+        data[291] = curve_type;
+        data[292..292 + calculator.len()].copy_from_slice(calculator);
+
+        program_test.add_account(
+            address,
+            Account {
+                lamports: 1_000_000_000,
+                data,
+                owner: spl_token_swap::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+
+        pool
+    }
+
+    /// Hop accounts for `ExecuteRoute`, swapping from `source_mint` into
+    /// `destination`
+    pub fn hop_accounts(&self, source_mint: &Pubkey, destination: Pubkey) -> Vec<Pubkey> {
+        let (swap_source, swap_destination, destination_mint) = if *source_mint == self.mint_a {
+            (self.vault_a, self.vault_b, self.mint_b)
+        } else {
+            (self.vault_b, self.vault_a, self.mint_a)
+        };
+
+        vec![
+            spl_token_swap::id(),
+            self.address,
+            self.authority,
+            swap_source,
+            swap_destination,
+            self.pool_mint,
+            self.pool_fee,
+            *source_mint,
+            destination_mint,
+            destination,
+        ]
+    }
+}
+
+pub async fn token_balance(banks_client: &mut BanksClient, address: Pubkey) -> u64 {
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    TokenAccount::unpack(&account.data).unwrap().amount
+}
+
+pub async fn route_state(banks_client: &mut BanksClient, address: Pubkey) -> RouteState {
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    *RouteState::unpack(&account.data).unwrap()
+}
+
+pub fn add_pool_info(program_test: &mut ProgramTest, owner: Pubkey, pool_info: &PoolInfo) {
+    program_test.add_account(
+        pool_info.address,
+        Account {
+            lamports: 1_000_000_000,
+            data: bytemuck::bytes_of(pool_info).to_vec(),
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Initialized registry holding `pools`, with room for `max_pools` entries
+pub fn add_registry(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    owner: Pubkey,
+    authority: Pubkey,
+    pools: &[PoolInfo],
+    max_pools: usize,
+) {
+    let registry = PoolRegistry {
+        account_type: AccountType::PoolRegistry as u8,
+        version: POOL_REGISTRY_VERSION,
+        authority,
+        pool_count: pools.len() as u32,
+    };
+    let mut data = bytemuck::bytes_of(&registry).to_vec();
+    data.extend_from_slice(bytemuck::cast_slice(pools));
+    data.resize(PoolRegistry::space(max_pools), 0);
+    program_test.add_account(
+        address,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Registry header and registered pools
+pub async fn registry(
+    banks_client: &mut BanksClient,
+    address: Pubkey,
+) -> (PoolRegistry, Vec<PoolInfo>) {
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    let (registry, pools) = PoolRegistry::unpack(&account.data).unwrap();
+    (*registry, pools.to_vec())
+}
+
+/// Send `instruction` in its own transaction paid for by the context payer,
+/// on a fresh blockhash so identical retries are not deduplicated
+pub async fn process_instruction(
+    context: &mut ProgramTestContext,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|e| e.unwrap())
+}
+
+/// `InitializeRoute` between two fresh mints
+pub fn initialize_route_instruction(
+    program_id: Pubkey,
+    route_state: Pubkey,
+    authority: Pubkey,
+    registry: Pubkey,
+    max_hops: u8,
+    nonce: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state, false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(registry, false),
+        ],
+        data: WayfinderInstruction::InitializeRoute {
+            input_mint: Pubkey::new_unique().to_bytes(),
+            output_mint: Pubkey::new_unique().to_bytes(),
+            amount_in: 1_000,
+            min_amount_out: 0,
+            max_hops,
+            nonce,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use borsh::BorshSerialize;
+use bytemuck::Zeroable;
+use common::{add_registry, add_route_state};
+use solana_program_test::ProgramTest;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use wayfinder::{
+    instruction::WayfinderInstruction,
+    pathfinding::ROUTE_EXPANSION_BUDGET,
+    state::{
+        AccountType, PoolInfo, RoutePath, RouteState, CURVE_CONSTANT_PRODUCT, MAX_SPLIT_PATHS,
+        POOL_STATUS_ACTIVE, ROUTE_MODE_EXACT_IN, ROUTE_STATE_VERSION,
+    },
+};
+
+/// Compute limit of a transaction
+const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+
+/// `pool_count` pools between every pair of `tokens` in turn, with reserves
+/// varied so that routes differ
+fn dense_pools(tokens: &[Pubkey], pool_count: usize) -> Vec<PoolInfo> {
+    let pairs: Vec<(Pubkey, Pubkey)> = tokens
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| tokens[i + 1..].iter().map(move |b| (*a, *b)))
+        .collect();
+
+    (0..pool_count)
+        .map(|i| {
+            let (token_a, token_b) = pairs[i % pairs.len()];
+            PoolInfo {
+                address: Pubkey::new_unique(),
+                token_a,
+                token_b,
+                fee_bps: 30,
+                reserve_a: 1_000_000 + (i as u64 * 7_919) % 1_000_000,
+                reserve_b: 1_000_000 + (i as u64 * 104_729) % 1_000_000,
+                status: POOL_STATUS_ACTIVE,
+                curve_type: CURVE_CONSTANT_PRODUCT,
+                curve_params: [0; 2],
+            }
+        })
+        .collect()
+}
+
+/// Compute units `instruction` consumes searching a registry of
+/// `pool_count` pools, and whether the search ran out of budget
+async fn measure_find_route(pool_count: usize, instruction: WayfinderInstruction) -> (u64, bool) {
+    let program_id = Pubkey::new_unique();
+    // Loaded from the SBF build, so that compute is metered as on-chain
+    let mut program_test = ProgramTest::new("wayfinder", program_id, None);
+
+    let tokens: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+    let pools = dense_pools(&tokens, pool_count);
+    let registry = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry,
+        program_id,
+        Pubkey::new_unique(),
+        &pools,
+        pool_count,
+    );
+
+    let authority = Keypair::new();
+    let route_state = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state,
+        program_id,
+        &RouteState {
+            account_type: AccountType::RouteState as u8,
+            version: ROUTE_STATE_VERSION,
+            input_mint: tokens[0],
+            output_mint: tokens[1],
+            amount_in: 100_000,
+            min_amount_out: 0,
+            max_hops: 3,
+            path_count: 0,
+            paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
+            status: 1,
+            authority: authority.pubkey(),
+            registry,
+            mode: ROUTE_MODE_EXACT_IN,
+            possibly_suboptimal: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+            AccountMeta::new_readonly(registry, false),
+        ],
+        data: instruction.try_to_vec().unwrap(),
+    };
+    let transaction = Transaction::new_signed_with_payer(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS),
+            instruction,
+        ],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &authority],
+        context.last_blockhash,
+    );
+
+    let outcome = context
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .unwrap();
+    outcome.result.unwrap();
+    let units = outcome.metadata.unwrap().compute_units_consumed;
+    let route_state = common::route_state(&mut context.banks_client, route_state).await;
+    (units, route_state.possibly_suboptimal == 1)
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build of wayfinder in BPF_OUT_DIR"]
+async fn test_find_route_compute_units_by_pool_count() {
+    for (name, instruction) in [
+        ("FindOptimalRoute", WayfinderInstruction::FindOptimalRoute),
+        (
+            "FindSplitRoute",
+            WayfinderInstruction::FindSplitRoute {
+                max_paths: MAX_SPLIT_PATHS as u8,
+            },
+        ),
+    ] {
+        println!("{name}, expansion budget {ROUTE_EXPANSION_BUDGET}");
+        for pool_count in [8, 16, 32, 64, 128] {
+            let (units, suboptimal) = measure_find_route(pool_count, instruction.clone()).await;
+            println!(
+                "{pool_count:>5} pools: {units:>9} CU{}",
+                if suboptimal { " (budget exhausted)" } else { "" }
+            );
+            assert!(units < MAX_COMPUTE_UNITS as u64);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+mod common;
+
+use borsh::BorshSerialize;
+use bytemuck::Zeroable;
+use common::{add_mint, add_route_state, add_token_account, token_balance, TestPool};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use wayfinder::{
+    error::WayfinderError,
+    instruction::WayfinderInstruction,
+    state::{
+        AccountType, PoolInfo, RoutePath, RouteState, CURVE_CONSTANT_PRODUCT, MAX_SPLIT_PATHS,
+        POOL_STATUS_ACTIVE, ROUTE_MODE_EXACT_IN, ROUTE_MODE_EXACT_OUT, ROUTE_STATE_VERSION,
+    },
+    token::spl_token_2022,
+};
+
+struct TwoHopRoute {
+    context: ProgramTestContext,
+    user: Keypair,
+    route_state: Pubkey,
+    user_a: Pubkey,
+    user_b: Pubkey,
+    user_c: Pubkey,
+    instruction: Instruction,
+}
+
+/// A -> B -> C through two token-swap pools, with the route already found
+/// and `path_amount_in` of the user's 10_000 A routed down it
+async fn setup_two_hop_route(mode: u8, min_amount_out: u64, path_amount_in: u64) -> TwoHopRoute {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let user = Keypair::new();
+    let mint_authority = Pubkey::new_unique();
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let mint_c = Pubkey::new_unique();
+    for mint in [mint_a, mint_b, mint_c] {
+        add_mint(&mut program_test, mint, mint_authority);
+    }
+
+    let pool_ab = TestPool::add(&mut program_test, mint_a, mint_b, 1_000_000, 2_000_000);
+    let pool_bc = TestPool::add(&mut program_test, mint_b, mint_c, 2_000_000, 1_000_000);
+
+    let user_a = Pubkey::new_unique();
+    let user_b = Pubkey::new_unique();
+    let user_c = Pubkey::new_unique();
+    add_token_account(&mut program_test, user_a, mint_a, user.pubkey(), 10_000);
+    add_token_account(&mut program_test, user_b, mint_b, user.pubkey(), 0);
+    add_token_account(&mut program_test, user_c, mint_c, user.pubkey(), 0);
+
+    let mut state = RouteState {
+        account_type: AccountType::RouteState as u8,
+        version: ROUTE_STATE_VERSION,
+        input_mint: mint_a,
+        output_mint: mint_c,
+        amount_in: 10_000,
+        min_amount_out,
+        max_hops: 3,
+        path_count: 0,
+        paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
+        status: 2,
+        authority: user.pubkey(),
+        registry: Pubkey::new_unique(),
+        mode,
+        possibly_suboptimal: 0,
+    };
+    state
+        .set_paths(&[(vec![pool_ab.address, pool_bc.address], path_amount_in)])
+        .unwrap();
+    let route_state = Pubkey::new_unique();
+    add_route_state(&mut program_test, route_state, program_id, &state);
+
+    let mut accounts = vec![
+        AccountMeta::new(route_state, false),
+        AccountMeta::new_readonly(user.pubkey(), true),
+        AccountMeta::new(user_a, false),
+        AccountMeta::new(user_c, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(spl_token_2022::id(), false),
+    ];
+    for (pool, source_mint, destination) in [(&pool_ab, mint_a, user_b), (&pool_bc, mint_b, user_c)]
+    {
+        push_hop_accounts(&mut accounts, &pool.hop_accounts(&source_mint, destination));
+    }
+
+    let instruction = Instruction {
+        program_id,
+        accounts,
+        data: WayfinderInstruction::ExecuteRoute.try_to_vec().unwrap(),
+    };
+
+    TwoHopRoute {
+        context: program_test.start_with_context().await,
+        user,
+        route_state,
+        user_a,
+        user_b,
+        user_c,
+        instruction,
+    }
+}
+
+fn push_hop_accounts(accounts: &mut Vec<AccountMeta>, hop: &[Pubkey]) {
+    accounts.push(AccountMeta::new_readonly(hop[0], false));
+    accounts.push(AccountMeta::new_readonly(hop[1], false));
+    accounts.push(AccountMeta::new_readonly(hop[2], false));
+    accounts.extend(hop[3..7].iter().map(|key| AccountMeta::new(*key, false)));
+    accounts.push(AccountMeta::new_readonly(hop[7], false));
+    accounts.push(AccountMeta::new_readonly(hop[8], false));
+    accounts.push(AccountMeta::new(hop[9], false));
+}
+
+async fn execute(route: &mut TwoHopRoute) -> Result<(), TransactionError> {
+    let transaction = Transaction::new_signed_with_payer(
+        std::slice::from_ref(&route.instruction),
+        Some(&route.context.payer.pubkey()),
+        &[&route.context.payer, &route.user],
+        route.context.last_blockhash,
+    );
+    route
+        .context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|e| e.unwrap())
+}
+
+#[tokio::test]
+async fn test_execute_two_hop_route() {
+    let mut route = setup_two_hop_route(ROUTE_MODE_EXACT_IN, 0, 10_000).await;
+    execute(&mut route).await.unwrap();
+
+    let banks_client = &mut route.context.banks_client;
+    assert_eq!(token_balance(banks_client, route.user_a).await, 0);
+    assert_eq!(token_balance(banks_client, route.user_b).await, 0);
+    assert!(token_balance(banks_client, route.user_c).await > 0);
+
+    let route_state = common::route_state(banks_client, route.route_state).await;
+    assert_eq!(route_state.status, 3);
+}
+
+#[tokio::test]
+async fn test_execute_route_slippage_exceeded() {
+    let mut route = setup_two_hop_route(ROUTE_MODE_EXACT_IN, u64::MAX, 10_000).await;
+
+    assert_eq!(
+        execute(&mut route).await.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::SlippageExceeded as u32)
+        )
+    );
+
+    let banks_client = &mut route.context.banks_client;
+    assert_eq!(token_balance(banks_client, route.user_a).await, 10_000);
+    let route_state = common::route_state(banks_client, route.route_state).await;
+    assert_eq!(route_state.status, 2);
+}
+
+#[tokio::test]
+async fn test_execute_route_rejects_mismatched_hop_mint() {
+    let mut route = setup_two_hop_route(ROUTE_MODE_EXACT_IN, 0, 10_000).await;
+    // The token program of each transfer is picked by its mint, so the first
+    // hop may not claim to spend its destination mint
+    let destination_mint = route.instruction.accounts[14].clone();
+    route.instruction.accounts[13] = destination_mint;
+
+    assert_eq!(
+        execute(&mut route).await.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::InvalidRoute as u32)
+        )
+    );
+
+    let banks_client = &mut route.context.banks_client;
+    assert_eq!(token_balance(banks_client, route.user_a).await, 10_000);
+}
+
+#[tokio::test]
+async fn test_execute_split_route() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let user = Keypair::new();
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    for mint in [mint_a, mint_b] {
+        add_mint(&mut program_test, mint, Pubkey::new_unique());
+    }
+
+    // Two parallel A/B pools, each taking part of the input
+    let pool_1 = TestPool::add(&mut program_test, mint_a, mint_b, 1_000_000, 1_000_000);
+    let pool_2 = TestPool::add(&mut program_test, mint_a, mint_b, 1_000_000, 1_000_000);
+
+    let user_a = Pubkey::new_unique();
+    let user_b = Pubkey::new_unique();
+    add_token_account(&mut program_test, user_a, mint_a, user.pubkey(), 10_000);
+    add_token_account(&mut program_test, user_b, mint_b, user.pubkey(), 0);
+
+    let mut state = RouteState {
+        account_type: AccountType::RouteState as u8,
+        version: ROUTE_STATE_VERSION,
+        input_mint: mint_a,
+        output_mint: mint_b,
+        amount_in: 10_000,
+        min_amount_out: 0,
+        max_hops: 3,
+        path_count: 0,
+        paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
+        status: 2,
+        authority: user.pubkey(),
+        registry: Pubkey::new_unique(),
+        mode: ROUTE_MODE_EXACT_IN,
+        possibly_suboptimal: 0,
+    };
+    state
+        .set_paths(&[(vec![pool_1.address], 6_000), (vec![pool_2.address], 4_000)])
+        .unwrap();
+    let route_state = Pubkey::new_unique();
+    add_route_state(&mut program_test, route_state, program_id, &state);
+
+    let mut accounts = vec![
+        AccountMeta::new(route_state, false),
+        AccountMeta::new_readonly(user.pubkey(), true),
+        AccountMeta::new(user_a, false),
+        AccountMeta::new(user_b, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(spl_token_2022::id(), false),
+    ];
+    for pool in [&pool_1, &pool_2] {
+        push_hop_accounts(&mut accounts, &pool.hop_accounts(&mint_a, user_b));
+    }
+    let instruction = Instruction {
+        program_id,
+        accounts,
+        data: WayfinderInstruction::ExecuteRoute.try_to_vec().unwrap(),
+    };
+
+    let mut context = program_test.start_with_context().await;
+    common::process_instruction(&mut context, instruction, &[&user])
+        .await
+        .unwrap();
+
+    let banks_client = &mut context.banks_client;
+    assert_eq!(token_balance(banks_client, user_a).await, 0);
+    assert_eq!(token_balance(banks_client, pool_1.vault_a).await, 1_006_000);
+    assert_eq!(token_balance(banks_client, pool_2.vault_a).await, 1_004_000);
+    assert!(token_balance(banks_client, user_b).await > 0);
+
+    let route_state = common::route_state(banks_client, route_state).await;
+    assert_eq!(route_state.status, 3);
+}
+
+#[tokio::test]
+async fn test_execute_exact_out_route() {
+    // Input the two-hop route needs to buy 2_000 C, priced as the pools
+    // were set up
+    let pool = |token_a, token_b, reserve_a, reserve_b| PoolInfo {
+        address: Pubkey::new_unique(),
+        token_a,
+        token_b,
+        fee_bps: 25,
+        reserve_a,
+        reserve_b,
+        status: POOL_STATUS_ACTIVE,
+        curve_type: CURVE_CONSTANT_PRODUCT,
+        curve_params: [0; 2],
+    };
+    let (mint_a, mint_b, mint_c) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let pool_bc = pool(mint_b, mint_c, 2_000_000, 1_000_000);
+    let pool_ab = pool(mint_a, mint_b, 1_000_000, 2_000_000);
+    let amount_b = pool_bc.get_input_amount(&mint_b, 2_000).unwrap();
+    let amount_in = pool_ab.get_input_amount(&mint_a, amount_b).unwrap();
+
+    // At most 10_000 A may be spent
+    let mut route = setup_two_hop_route(ROUTE_MODE_EXACT_OUT, 2_000, amount_in).await;
+    execute(&mut route).await.unwrap();
+
+    let banks_client = &mut route.context.banks_client;
+    assert_eq!(
+        token_balance(banks_client, route.user_a).await,
+        10_000 - amount_in
+    );
+    assert!(token_balance(banks_client, route.user_c).await >= 2_000);
+
+    let route_state = common::route_state(banks_client, route.route_state).await;
+    assert_eq!(route_state.status, 3);
+}
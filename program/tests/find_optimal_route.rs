@@ -0,0 +1,455 @@
+mod common;
+
+use borsh::BorshSerialize;
+use bytemuck::Zeroable;
+use common::{add_mint, add_registry, add_route_state, add_transfer_fee_mint};
+use solana_program_test::ProgramTest;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use wayfinder::{
+    error::WayfinderError,
+    instruction::WayfinderInstruction,
+    state::{
+        AccountType, PoolInfo, RoutePath, RouteState, CURVE_CONSTANT_PRODUCT, MAX_SPLIT_PATHS,
+        POOL_STATUS_ACTIVE, POOL_STATUS_PAUSED, ROUTE_MODE_EXACT_IN, ROUTE_MODE_EXACT_OUT,
+        ROUTE_STATE_VERSION,
+    },
+};
+
+fn pool(mint_a: Pubkey, mint_b: Pubkey, status: u8) -> PoolInfo {
+    PoolInfo {
+        address: Pubkey::new_unique(),
+        token_a: mint_a,
+        token_b: mint_b,
+        fee_bps: 30,
+        reserve_a: 1_000_000,
+        reserve_b: 1_000_000,
+        status,
+        curve_type: CURVE_CONSTANT_PRODUCT,
+        curve_params: [0; 2],
+    }
+}
+
+async fn find_route(
+    min_amount_out: u64,
+    pool_status: u8,
+) -> (Result<(), TransactionError>, RouteState) {
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    run_find_route(
+        &[pool(mint_a, mint_b, pool_status)],
+        mint_a,
+        mint_b,
+        ROUTE_MODE_EXACT_IN,
+        1_000,
+        min_amount_out,
+        WayfinderInstruction::FindOptimalRoute,
+    )
+    .await
+}
+
+/// Search `pools` for a route in `mode` from `mint_a` to `mint_b` with
+/// `instruction`
+async fn run_find_route(
+    pools: &[PoolInfo],
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+    mode: u8,
+    amount_in: u64,
+    min_amount_out: u64,
+    instruction: WayfinderInstruction,
+) -> (Result<(), TransactionError>, RouteState) {
+    let program_id = Pubkey::new_unique();
+    let program_test = common::program_test(program_id);
+    run_find_route_with_mints(
+        program_test,
+        program_id,
+        pools,
+        mint_a,
+        mint_b,
+        mode,
+        amount_in,
+        min_amount_out,
+        instruction,
+        &[],
+    )
+    .await
+}
+
+/// Like `run_find_route`, passing `mints` to the search after the registry
+#[allow(clippy::too_many_arguments)]
+async fn run_find_route_with_mints(
+    mut program_test: ProgramTest,
+    program_id: Pubkey,
+    pools: &[PoolInfo],
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+    mode: u8,
+    amount_in: u64,
+    min_amount_out: u64,
+    instruction: WayfinderInstruction,
+    mints: &[Pubkey],
+) -> (Result<(), TransactionError>, RouteState) {
+
+    let registry = Pubkey::new_unique();
+    add_registry(
+        &mut program_test,
+        registry,
+        program_id,
+        Pubkey::new_unique(),
+        pools,
+        pools.len().max(4),
+    );
+
+    let authority = Keypair::new();
+    let route_state = Pubkey::new_unique();
+    add_route_state(
+        &mut program_test,
+        route_state,
+        program_id,
+        &RouteState {
+            account_type: AccountType::RouteState as u8,
+            version: ROUTE_STATE_VERSION,
+            input_mint: mint_a,
+            output_mint: mint_b,
+            amount_in,
+            min_amount_out,
+            max_hops: 3,
+            path_count: 0,
+            paths: [RoutePath::zeroed(); MAX_SPLIT_PATHS],
+            status: 1,
+            authority: authority.pubkey(),
+            registry,
+            mode,
+            possibly_suboptimal: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+    let mut accounts = vec![
+        AccountMeta::new(route_state, false),
+        AccountMeta::new_readonly(authority.pubkey(), true),
+        AccountMeta::new_readonly(registry, false),
+    ];
+    accounts.extend(mints.iter().map(|mint| AccountMeta::new_readonly(*mint, false)));
+    let instruction = Instruction {
+        program_id,
+        accounts,
+        data: instruction.try_to_vec().unwrap(),
+    };
+    let result = common::process_instruction(&mut context, instruction, &[&authority]).await;
+
+    (
+        result,
+        common::route_state(&mut context.banks_client, route_state).await,
+    )
+}
+
+#[tokio::test]
+async fn test_find_optimal_route() {
+    let (result, route_state) = find_route(900, POOL_STATUS_ACTIVE).await;
+    result.unwrap();
+    assert_eq!(route_state.status, 2);
+    assert_eq!(route_state.possibly_suboptimal, 0);
+    assert_eq!(route_state.paths().len(), 1);
+    assert_eq!(route_state.paths()[0].route().len(), 1);
+}
+
+#[tokio::test]
+async fn test_find_optimal_route_below_min_amount_out() {
+    let (result, route_state) = find_route(1_000, POOL_STATUS_ACTIVE).await;
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::SlippageExceeded as u32)
+        )
+    );
+    assert_eq!(route_state.status, 1);
+}
+
+#[tokio::test]
+async fn test_find_optimal_route_skips_paused_pools() {
+    let (result, _) = find_route(0, POOL_STATUS_PAUSED).await;
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::NoValidPath as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_find_route_flags_exhausted_expansion_budget() {
+    // A pool between every pair of tokens but the two routed between, with
+    // reserves varied so that no route prunes the others early
+    let tokens: Vec<Pubkey> = (0..20).map(|_| Pubkey::new_unique()).collect();
+    let pools: Vec<PoolInfo> = tokens
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| tokens[i + 1..].iter().map(move |b| (*a, *b)))
+        .skip(1)
+        .enumerate()
+        .map(|(i, (mint_a, mint_b))| PoolInfo {
+            reserve_a: 1_000_000 + (i as u64 * 7_919) % 1_000_000,
+            reserve_b: 1_000_000 + (i as u64 * 104_729) % 1_000_000,
+            ..pool(mint_a, mint_b, POOL_STATUS_ACTIVE)
+        })
+        .collect();
+
+    let (result, route_state) = run_find_route(
+        &pools,
+        tokens[0],
+        tokens[1],
+        ROUTE_MODE_EXACT_IN,
+        500_000,
+        0,
+        WayfinderInstruction::FindOptimalRoute,
+    )
+    .await;
+    result.unwrap();
+    assert_eq!(route_state.status, 2);
+    assert_eq!(route_state.possibly_suboptimal, 1);
+}
+
+#[tokio::test]
+async fn test_find_split_route() {
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let pools = [
+        pool(mint_a, mint_b, POOL_STATUS_ACTIVE),
+        pool(mint_a, mint_b, POOL_STATUS_ACTIVE),
+    ];
+
+    let (result, route_state) = run_find_route(
+        &pools,
+        mint_a,
+        mint_b,
+        ROUTE_MODE_EXACT_IN,
+        500_000,
+        0,
+        WayfinderInstruction::FindSplitRoute { max_paths: 2 },
+    )
+    .await;
+    result.unwrap();
+
+    assert_eq!(route_state.status, 2);
+    let paths = route_state.paths();
+    assert_eq!(paths.len(), 2);
+    assert_ne!(paths[0].route(), paths[1].route());
+    assert_eq!({ paths[0].amount_in } + { paths[1].amount_in }, 500_000);
+}
+
+#[tokio::test]
+async fn test_find_split_route_too_many_paths() {
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+
+    let (result, _) = run_find_route(
+        &[pool(mint_a, mint_b, POOL_STATUS_ACTIVE)],
+        mint_a,
+        mint_b,
+        ROUTE_MODE_EXACT_IN,
+        1_000,
+        0,
+        WayfinderInstruction::FindSplitRoute {
+            max_paths: MAX_SPLIT_PATHS as u8 + 1,
+        },
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::MaximumPathsExceeded as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_find_exact_out_route() {
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let pool = pool(mint_a, mint_b, POOL_STATUS_ACTIVE);
+    let amount_in = pool.get_input_amount(&mint_a, 1_000).unwrap();
+
+    let (result, route_state) = run_find_route(
+        &[pool],
+        mint_a,
+        mint_b,
+        ROUTE_MODE_EXACT_OUT,
+        2_000,
+        1_000,
+        WayfinderInstruction::FindOptimalRoute,
+    )
+    .await;
+    result.unwrap();
+
+    assert_eq!(route_state.status, 2);
+    let paths = route_state.paths();
+    assert_eq!(paths.len(), 1);
+    assert_eq!(paths[0].route(), &[pool.address]);
+    assert_eq!({ paths[0].amount_in }, amount_in);
+}
+
+#[tokio::test]
+async fn test_find_exact_out_route_above_max_amount_in() {
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+
+    // Buying 1_000 out of 1_000_000 reserves takes more than 1_000 in
+    let (result, route_state) = run_find_route(
+        &[pool(mint_a, mint_b, POOL_STATUS_ACTIVE)],
+        mint_a,
+        mint_b,
+        ROUTE_MODE_EXACT_OUT,
+        1_000,
+        1_000,
+        WayfinderInstruction::FindOptimalRoute,
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::SlippageExceeded as u32)
+        )
+    );
+    assert_eq!(route_state.status, 1);
+}
+
+/// Pools from A to C through either B or the pricier D
+fn transfer_fee_pools(
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+    mint_c: Pubkey,
+    mint_d: Pubkey,
+) -> Vec<PoolInfo> {
+    let mut pools = vec![
+        pool(mint_a, mint_b, POOL_STATUS_ACTIVE),
+        pool(mint_b, mint_c, POOL_STATUS_ACTIVE),
+        pool(mint_a, mint_d, POOL_STATUS_ACTIVE),
+        pool(mint_d, mint_c, POOL_STATUS_ACTIVE),
+    ];
+    pools[2].fee_bps = 60;
+    pools[3].fee_bps = 60;
+    pools
+}
+
+#[tokio::test]
+async fn test_find_route_avoids_transfer_fee_mints() {
+    let (mint_a, mint_b, mint_c, mint_d) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let pools = transfer_fee_pools(mint_a, mint_b, mint_c, mint_d);
+
+    let (result, route_state) = run_find_route(
+        &pools,
+        mint_a,
+        mint_c,
+        ROUTE_MODE_EXACT_IN,
+        10_000,
+        0,
+        WayfinderInstruction::FindOptimalRoute,
+    )
+    .await;
+    result.unwrap();
+    assert_eq!(route_state.paths()[0].route(), &[pools[0].address, pools[1].address]);
+
+    // Token-swap cannot move B once it has the transfer fee extension, even
+    // while it charges nothing, so the route takes the pricier pools via D
+    for transfer_fee_basis_points in [0, 100] {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = common::program_test(program_id);
+        add_transfer_fee_mint(
+            &mut program_test,
+            mint_b,
+            Pubkey::new_unique(),
+            transfer_fee_basis_points,
+        );
+        let (result, route_state) = run_find_route_with_mints(
+            program_test,
+            program_id,
+            &pools,
+            mint_a,
+            mint_c,
+            ROUTE_MODE_EXACT_IN,
+            10_000,
+            0,
+            WayfinderInstruction::FindOptimalRoute,
+            &[mint_b],
+        )
+        .await;
+        result.unwrap();
+        assert_eq!(route_state.paths()[0].route(), &[pools[2].address, pools[3].address]);
+    }
+
+    // Nor can it deliver B itself
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    add_transfer_fee_mint(&mut program_test, mint_b, Pubkey::new_unique(), 100);
+    let (result, route_state) = run_find_route_with_mints(
+        program_test,
+        program_id,
+        &pools,
+        mint_a,
+        mint_b,
+        ROUTE_MODE_EXACT_IN,
+        10_000,
+        0,
+        WayfinderInstruction::FindOptimalRoute,
+        &[mint_b],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::NoValidPath as u32)
+        )
+    );
+    assert_eq!(route_state.status, 1);
+}
+
+#[tokio::test]
+async fn test_find_route_rejects_non_token_2022_mint() {
+    let (mint_a, mint_b, mint_c, mint_d) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    add_mint(&mut program_test, mint_b, Pubkey::new_unique());
+    let (result, route_state) = run_find_route_with_mints(
+        program_test,
+        program_id,
+        &transfer_fee_pools(mint_a, mint_b, mint_c, mint_d),
+        mint_a,
+        mint_c,
+        ROUTE_MODE_EXACT_IN,
+        10_000,
+        0,
+        WayfinderInstruction::FindOptimalRoute,
+        &[mint_b],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::IncorrectProgramId as u32)
+        )
+    );
+    assert_eq!(route_state.status, 1);
+}
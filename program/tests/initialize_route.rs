@@ -0,0 +1,206 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::{add_registry, initialize_route_instruction};
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::TransactionError,
+};
+use wayfinder::{
+    error::WayfinderError,
+    find_route_state_address,
+    instruction::WayfinderInstruction,
+    state::{RouteState, MAX_ROUTE_HOPS, ROUTE_MODE_EXACT_IN, ROUTE_MODE_EXACT_OUT},
+};
+
+const NONCE: u64 = 7;
+const REGISTRY: Pubkey = Pubkey::new_from_array([7; 32]);
+
+/// Program test with a funded authority and an empty registry at `REGISTRY`
+fn setup() -> (ProgramTest, Pubkey, Keypair) {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account::new(1_000_000_000, 0, &system_program::id()),
+    );
+    add_registry(&mut program_test, REGISTRY, program_id, Pubkey::new_unique(), &[], 1);
+    (program_test, program_id, authority)
+}
+
+async fn initialize_route(
+    program_test: ProgramTest,
+    program_id: Pubkey,
+    authority: &Keypair,
+    max_hops: u8,
+) -> (ProgramTestContext, Pubkey, Result<(), TransactionError>) {
+    let (route_state, _) = find_route_state_address(&program_id, &authority.pubkey(), NONCE);
+    let mut context = program_test.start_with_context().await;
+    let instruction = initialize_route_instruction(
+        program_id,
+        route_state,
+        authority.pubkey(),
+        REGISTRY,
+        max_hops,
+        NONCE,
+    );
+    let result = common::process_instruction(&mut context, instruction, &[authority]).await;
+    (context, route_state, result)
+}
+
+#[tokio::test]
+async fn test_initialize_route_creates_route_state() {
+    let (program_test, program_id, authority) = setup();
+    let (mut context, route_state, result) =
+        initialize_route(program_test, program_id, &authority, 2).await;
+    result.unwrap();
+
+    let account = context
+        .banks_client
+        .get_account(route_state)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data.len(), RouteState::LEN);
+    assert!(Rent::default().is_exempt(account.lamports, RouteState::LEN));
+
+    let route_state = common::route_state(&mut context.banks_client, route_state).await;
+    assert_eq!(route_state.authority, authority.pubkey());
+    assert_eq!(route_state.registry, REGISTRY);
+    assert_eq!(route_state.max_hops, 2);
+    assert_eq!(route_state.status, 1);
+    assert_eq!(route_state.mode, ROUTE_MODE_EXACT_IN);
+}
+
+/// Like `initialize_route`, for an exact-output route buying `amount_out`
+/// for at most `max_amount_in`
+async fn initialize_exact_out_route(
+    program_test: ProgramTest,
+    program_id: Pubkey,
+    authority: &Keypair,
+    amount_out: u64,
+    max_amount_in: u64,
+) -> (ProgramTestContext, Pubkey, Result<(), TransactionError>) {
+    let (route_state, _) = find_route_state_address(&program_id, &authority.pubkey(), NONCE);
+    let mut context = program_test.start_with_context().await;
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(route_state, false),
+            AccountMeta::new(authority.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(REGISTRY, false),
+        ],
+        data: WayfinderInstruction::InitializeExactOutRoute {
+            input_mint: Pubkey::new_unique().to_bytes(),
+            output_mint: Pubkey::new_unique().to_bytes(),
+            amount_out,
+            max_amount_in,
+            max_hops: 2,
+            nonce: NONCE,
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+    let result = common::process_instruction(&mut context, instruction, &[authority]).await;
+    (context, route_state, result)
+}
+
+#[tokio::test]
+async fn test_initialize_exact_out_route() {
+    let (program_test, program_id, authority) = setup();
+    let (mut context, route_state, result) =
+        initialize_exact_out_route(program_test, program_id, &authority, 1_000, 1_100).await;
+    result.unwrap();
+
+    // The output to buy is the minimum output, the maximum input the input
+    let route_state = common::route_state(&mut context.banks_client, route_state).await;
+    assert_eq!(route_state.mode, ROUTE_MODE_EXACT_OUT);
+    assert_eq!({ route_state.min_amount_out }, 1_000);
+    assert_eq!({ route_state.amount_in }, 1_100);
+    assert_eq!(route_state.status, 1);
+}
+
+#[tokio::test]
+async fn test_initialize_exact_out_route_rejects_zero_amounts() {
+    for (amount_out, max_amount_in) in [(0, 1_100), (1_000, 0)] {
+        let (program_test, program_id, authority) = setup();
+        let (mut context, route_state, result) = initialize_exact_out_route(
+            program_test,
+            program_id,
+            &authority,
+            amount_out,
+            max_amount_in,
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+        assert!(context
+            .banks_client
+            .get_account(route_state)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_initialize_route_prefunded_address() {
+    let (mut program_test, program_id, authority) = setup();
+    let (route_state, _) = find_route_state_address(&program_id, &authority.pubkey(), NONCE);
+    program_test.add_account(route_state, Account::new(1_000, 0, &system_program::id()));
+
+    let (mut context, route_state, result) =
+        initialize_route(program_test, program_id, &authority, 2).await;
+    result.unwrap();
+
+    let account = context
+        .banks_client
+        .get_account(route_state)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, program_id);
+    assert!(Rent::default().is_exempt(account.lamports, RouteState::LEN));
+}
+
+#[tokio::test]
+async fn test_initialize_route_max_hops_exceeded() {
+    let (program_test, program_id, authority) = setup();
+    let (_, _, result) =
+        initialize_route(program_test, program_id, &authority, MAX_ROUTE_HOPS as u8 + 1).await;
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::MaximumHopsExceeded as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_route_twice() {
+    let (program_test, program_id, authority) = setup();
+    let (mut context, route_state, result) =
+        initialize_route(program_test, program_id, &authority, 2).await;
+    result.unwrap();
+
+    let instruction =
+        initialize_route_instruction(program_id, route_state, authority.pubkey(), REGISTRY, 3, NONCE);
+    assert_eq!(
+        common::process_instruction(&mut context, instruction, &[&authority])
+            .await
+            .unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)
+    );
+}
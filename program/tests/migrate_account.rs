@@ -0,0 +1,367 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::{add_registry, process_instruction};
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    entrypoint::MAX_PERMITTED_DATA_INCREASE,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::TransactionError,
+};
+use wayfinder::{
+    error::WayfinderError,
+    instruction::WayfinderInstruction,
+    state::{
+        AccountType, LegacyPoolInfo, LegacyPoolRegistry, LegacyRouteState, PoolInfo,
+        PoolRegistry, RouteState, CURVE_CONSTANT_PRODUCT, LEGACY_DISCRIMINATOR,
+        POOL_REGISTRY_VERSION, POOL_STATUS_ACTIVE, ROUTE_MODE_EXACT_IN, ROUTE_STATE_VERSION,
+    },
+};
+
+/// Write `legacy` Borsh-encoded at the front of a zeroed account of `len`
+/// bytes, as the original program did
+fn add_legacy_account(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    owner: Pubkey,
+    legacy: &impl BorshSerialize,
+    len: usize,
+) {
+    let mut data = legacy.try_to_vec().unwrap();
+    data.resize(len, 0);
+    add_account(program_test, address, owner, data);
+}
+
+fn add_account(program_test: &mut ProgramTest, address: Pubkey, owner: Pubkey, data: Vec<u8>) {
+    program_test.add_account(
+        address,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Registry the migrated route states are pinned to
+const REGISTRY: Pubkey = Pubkey::new_from_array([3; 32]);
+
+fn funded_authority(program_test: &mut ProgramTest) -> Keypair {
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account::new(1_000_000_000, 0, &system_program::id()),
+    );
+    authority
+}
+
+/// Legacy route state with a two-hop route found
+fn legacy_route_state(authority: Pubkey) -> LegacyRouteState {
+    LegacyRouteState {
+        discriminator: LEGACY_DISCRIMINATOR,
+        input_mint: Pubkey::new_unique(),
+        output_mint: Pubkey::new_unique(),
+        amount_in: 1_000,
+        min_amount_out: 900,
+        hops: 2,
+        route: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        status: 2,
+        authority,
+    }
+}
+
+/// Check that `route_state` is `legacy` in the current layout
+fn assert_migrated_route_state(route_state: &RouteState, legacy: &LegacyRouteState) {
+    assert_eq!(route_state.account_type, AccountType::RouteState as u8);
+    assert_eq!(route_state.version, ROUTE_STATE_VERSION);
+    assert_eq!(route_state.input_mint, legacy.input_mint);
+    assert_eq!(route_state.output_mint, legacy.output_mint);
+    assert_eq!({ route_state.amount_in }, legacy.amount_in);
+    assert_eq!({ route_state.min_amount_out }, legacy.min_amount_out);
+    assert_eq!(route_state.max_hops, 3);
+    assert_eq!(route_state.paths().len(), 1);
+    assert_eq!(route_state.paths()[0].route(), &legacy.route[..]);
+    assert_eq!({ route_state.paths()[0].amount_in }, legacy.amount_in);
+    assert_eq!(route_state.status, legacy.status);
+    assert_eq!(route_state.authority, legacy.authority);
+    assert_eq!(route_state.mode, ROUTE_MODE_EXACT_IN);
+}
+
+fn migrate_account(program_id: Pubkey, account: Pubkey, authority: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(account, false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: WayfinderInstruction::MigrateAccount.try_to_vec().unwrap(),
+    }
+}
+
+/// Migrate the route state `account`, pinning it to `REGISTRY`
+fn migrate_route_state(program_id: Pubkey, account: Pubkey, authority: Pubkey) -> Instruction {
+    let mut instruction = migrate_account(program_id, account, authority);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(REGISTRY, false));
+    instruction
+}
+
+async fn account_data(context: &mut ProgramTestContext, address: Pubkey) -> Vec<u8> {
+    context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap()
+        .data
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_route_state() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = funded_authority(&mut program_test);
+    add_registry(&mut program_test, REGISTRY, program_id, authority.pubkey(), &[], 1);
+
+    let legacy = legacy_route_state(authority.pubkey());
+    let address = Pubkey::new_unique();
+    add_legacy_account(
+        &mut program_test,
+        address,
+        program_id,
+        &legacy,
+        LegacyRouteState::LEN,
+    );
+
+    let mut context = program_test.start_with_context().await;
+    let instruction = migrate_route_state(program_id, address, authority.pubkey());
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    let data = account_data(&mut context, address).await;
+    assert_eq!(data.len(), RouteState::LEN);
+    assert_migrated_route_state(RouteState::unpack(&data).unwrap(), &legacy);
+    assert_eq!(RouteState::unpack(&data).unwrap().registry, REGISTRY);
+
+    // Migrating again is a no-op
+    let instruction = migrate_route_state(program_id, address, authority.pubkey());
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+    assert_eq!(account_data(&mut context, address).await, data);
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_route_state_without_route() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = funded_authority(&mut program_test);
+    add_registry(&mut program_test, REGISTRY, program_id, authority.pubkey(), &[], 1);
+
+    // As written by the original InitializeRoute
+    let legacy = LegacyRouteState {
+        hops: 0,
+        route: Vec::new(),
+        status: 1,
+        ..legacy_route_state(authority.pubkey())
+    };
+    let address = Pubkey::new_unique();
+    add_legacy_account(
+        &mut program_test,
+        address,
+        program_id,
+        &legacy,
+        LegacyRouteState::LEN,
+    );
+
+    let mut context = program_test.start_with_context().await;
+    let instruction = migrate_route_state(program_id, address, authority.pubkey());
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    let data = account_data(&mut context, address).await;
+    let route_state = RouteState::unpack(&data).unwrap();
+    assert_eq!(route_state.version, ROUTE_STATE_VERSION);
+    assert_eq!(route_state.status, 1);
+    assert_eq!(route_state.max_hops, 3);
+    assert!(route_state.paths().is_empty());
+}
+
+/// Legacy registry of `authority` holding `pool` with room for four entries,
+/// tagged like a legacy route state
+fn legacy_pool_registry(authority: Pubkey, pool: &PoolInfo) -> (LegacyPoolRegistry, usize) {
+    let registry = LegacyPoolRegistry {
+        discriminator: LEGACY_DISCRIMINATOR,
+        authority,
+        pools: vec![LegacyPoolInfo {
+            address: pool.address,
+            token_a: pool.token_a,
+            token_b: pool.token_b,
+            fee_bps: pool.fee_bps,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+        }],
+    };
+    (registry, LegacyPoolRegistry::HEADER_LEN + 4 * LegacyPoolInfo::LEN)
+}
+
+fn registered_pool() -> PoolInfo {
+    PoolInfo {
+        address: Pubkey::new_unique(),
+        token_a: Pubkey::new_unique(),
+        token_b: Pubkey::new_unique(),
+        fee_bps: 30,
+        reserve_a: 1_000_000,
+        reserve_b: 2_000_000,
+        status: POOL_STATUS_ACTIVE,
+        curve_type: CURVE_CONSTANT_PRODUCT,
+        curve_params: [0; 2],
+    }
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_pool_registry() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = funded_authority(&mut program_test);
+
+    let pool = registered_pool();
+    let (legacy, len) = legacy_pool_registry(authority.pubkey(), &pool);
+    let address = Pubkey::new_unique();
+    add_legacy_account(&mut program_test, address, program_id, &legacy, len);
+
+    let mut context = program_test.start_with_context().await;
+    let instruction = migrate_account(program_id, address, authority.pubkey());
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    // Every entry slot is kept, widened to the current layout
+    let data = account_data(&mut context, address).await;
+    assert_eq!(data.len(), PoolRegistry::space(4));
+    let (migrated, pools) = common::registry(&mut context.banks_client, address).await;
+    assert_eq!(migrated.version, POOL_REGISTRY_VERSION);
+    assert_eq!(migrated.authority, authority.pubkey());
+    assert_eq!(pools, vec![pool]);
+}
+
+#[tokio::test]
+async fn test_migrate_pool_registry_growth_limit() {
+    // Widening every entry slot grows this registry by exactly the most one
+    // instruction may add; a byte less of legacy data needs one more
+    let capacity = 569;
+    let len = PoolRegistry::space(capacity) - MAX_PERMITTED_DATA_INCREASE;
+    assert_eq!(LegacyPoolRegistry::capacity(len), capacity);
+    assert_eq!(LegacyPoolRegistry::capacity(len - 1), capacity);
+
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = funded_authority(&mut program_test);
+
+    let pool = registered_pool();
+    let (legacy, _) = legacy_pool_registry(authority.pubkey(), &pool);
+    let (at_limit, over_limit) = (Pubkey::new_unique(), Pubkey::new_unique());
+    add_legacy_account(&mut program_test, at_limit, program_id, &legacy, len);
+    add_legacy_account(&mut program_test, over_limit, program_id, &legacy, len - 1);
+
+    let mut context = program_test.start_with_context().await;
+    let instruction = migrate_account(program_id, at_limit, authority.pubkey());
+    process_instruction(&mut context, instruction, &[&authority])
+        .await
+        .unwrap();
+    let data = account_data(&mut context, at_limit).await;
+    assert_eq!(data.len(), PoolRegistry::space(capacity));
+
+    let instruction = migrate_account(program_id, over_limit, authority.pubkey());
+    assert_eq!(
+        process_instruction(&mut context, instruction, &[&authority])
+            .await
+            .unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::MigrationTooLarge as u32)
+        )
+    );
+    assert_eq!(account_data(&mut context, over_limit).await.len(), len - 1);
+}
+
+#[tokio::test]
+async fn test_migrate_account_wrong_authority() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = funded_authority(&mut program_test);
+    let attacker = funded_authority(&mut program_test);
+
+    let legacy = legacy_route_state(authority.pubkey());
+    let address = Pubkey::new_unique();
+    add_legacy_account(
+        &mut program_test,
+        address,
+        program_id,
+        &legacy,
+        LegacyRouteState::LEN,
+    );
+
+    let mut context = program_test.start_with_context().await;
+    let instruction = migrate_route_state(program_id, address, attacker.pubkey());
+    assert_eq!(
+        process_instruction(&mut context, instruction, &[&attacker])
+            .await
+            .unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::AccountNotSigner as u32)
+        )
+    );
+    assert_eq!(
+        account_data(&mut context, address).await.len(),
+        LegacyRouteState::LEN
+    );
+}
+
+#[tokio::test]
+async fn test_legacy_route_state_requires_migration() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = funded_authority(&mut program_test);
+
+    let legacy = legacy_route_state(authority.pubkey());
+    let address = Pubkey::new_unique();
+    add_legacy_account(
+        &mut program_test,
+        address,
+        program_id,
+        &legacy,
+        LegacyRouteState::LEN,
+    );
+
+    let mut context = program_test.start_with_context().await;
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(address, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+            AccountMeta::new(Pubkey::new_unique(), false),
+        ],
+        data: WayfinderInstruction::CloseRoute.try_to_vec().unwrap(),
+    };
+    assert_eq!(
+        process_instruction(&mut context, instruction, &[&authority])
+            .await
+            .unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WayfinderError::AccountVersionMismatch as u32)
+        )
+    );
+}
@@ -0,0 +1,310 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::{add_mint, process_instruction, TestPool};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use wayfinder::{
+    error::WayfinderError,
+    instruction::WayfinderInstruction,
+    state::{
+        PoolRegistry, CURVE_CONSTANT_PRODUCT, CURVE_STABLE_SWAP, POOL_STATUS_ACTIVE,
+        POOL_STATUS_PAUSED,
+    },
+    swap::SWAP_CURVE_STABLE,
+};
+
+struct RegistryTest {
+    context: ProgramTestContext,
+    program_id: Pubkey,
+    registry: Pubkey,
+    authority: Keypair,
+    pools: Vec<TestPool>,
+}
+
+/// Empty registry with room for two pools, plus three constant-product
+/// token-swap pools, a stable one, a constant-price one, one charging an
+/// owner fee and one whose fee is not a whole number of basis points
+async fn setup() -> RegistryTest {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mint_authority = Pubkey::new_unique();
+    let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    for mint in &mints {
+        add_mint(&mut program_test, *mint, mint_authority);
+    }
+    let pools = vec![
+        TestPool::add(&mut program_test, mints[0], mints[1], 1_000_000, 2_000_000),
+        TestPool::add(&mut program_test, mints[1], mints[2], 3_000_000, 4_000_000),
+        TestPool::add(&mut program_test, mints[0], mints[2], 5_000_000, 6_000_000),
+        TestPool::add_with_curve(
+            &mut program_test,
+            mints[0],
+            mints[1],
+            1_000_000,
+            1_000_000,
+            SWAP_CURVE_STABLE,
+            &100u64.to_le_bytes(),
+        ),
+        // Token-swap `CurveType::ConstantPrice`
+        TestPool::add_with_curve(&mut program_test, mints[0], mints[1], 1_000_000, 1_000_000, 1, &[]),
+        TestPool::add_with_fees(
+            &mut program_test,
+            mints[0],
+            mints[1],
+            1_000_000,
+            1_000_000,
+            [1, 400, 5, 10_000],
+        ),
+        TestPool::add_with_fees(
+            &mut program_test,
+            mints[0],
+            mints[1],
+            1_000_000,
+            1_000_000,
+            [1, 3, 0, 0],
+        ),
+    ];
+
+    let registry = Pubkey::new_unique();
+    program_test.add_account(
+        registry,
+        Account {
+            lamports: 1_000_000_000,
+            data: vec![0; PoolRegistry::space(2)],
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut test = RegistryTest {
+        context: program_test.start_with_context().await,
+        program_id,
+        registry,
+        authority: Keypair::new(),
+        pools,
+    };
+
+    let instruction = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(registry, false),
+            AccountMeta::new_readonly(test.authority.pubkey(), true),
+        ],
+        data: WayfinderInstruction::InitializeRegistry.try_to_vec().unwrap(),
+    };
+    let authority = test.authority.insecure_clone();
+    process_instruction(&mut test.context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    test
+}
+
+fn pool_accounts(test: &RegistryTest, pool: &TestPool, authority: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(test.registry, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(pool.address, false),
+        AccountMeta::new_readonly(pool.vault_a, false),
+        AccountMeta::new_readonly(pool.vault_b, false),
+    ]
+}
+
+async fn register(
+    test: &mut RegistryTest,
+    pool_index: usize,
+    authority: &Keypair,
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+) -> Result<(), TransactionError> {
+    let instruction = Instruction {
+        program_id: test.program_id,
+        accounts: pool_accounts(test, &test.pools[pool_index], &authority.pubkey()),
+        data: WayfinderInstruction::RegisterPool {
+            token_a_mint: token_a_mint.to_bytes(),
+            token_b_mint: token_b_mint.to_bytes(),
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+    process_instruction(&mut test.context, instruction, &[authority]).await
+}
+
+async fn register_pool(test: &mut RegistryTest, pool_index: usize) -> Result<(), TransactionError> {
+    let authority = test.authority.insecure_clone();
+    let (mint_a, mint_b) = (test.pools[pool_index].mint_a, test.pools[pool_index].mint_b);
+    register(test, pool_index, &authority, mint_a, mint_b).await
+}
+
+fn custom_error(error: WayfinderError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+#[tokio::test]
+async fn test_register_pool() {
+    let mut test = setup().await;
+    register_pool(&mut test, 0).await.unwrap();
+
+    let (registry, pools) = common::registry(&mut test.context.banks_client, test.registry).await;
+    assert_eq!(registry.authority, test.authority.pubkey());
+    assert_eq!(pools.len(), 1);
+    let pool = &pools[0];
+    assert_eq!(pool.address, test.pools[0].address);
+    assert_eq!(pool.token_a, test.pools[0].mint_a);
+    assert_eq!(pool.token_b, test.pools[0].mint_b);
+    assert_eq!({ pool.fee_bps }, 25);
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_000_000, 2_000_000));
+    assert_eq!(pool.status, POOL_STATUS_ACTIVE);
+    assert_eq!(pool.curve_type, CURVE_CONSTANT_PRODUCT);
+}
+
+#[tokio::test]
+async fn test_register_stable_pool() {
+    let mut test = setup().await;
+    register_pool(&mut test, 3).await.unwrap();
+
+    let (_, pools) = common::registry(&mut test.context.banks_client, test.registry).await;
+    assert_eq!(pools[0].curve_type, CURVE_STABLE_SWAP);
+    assert_eq!({ pools[0].curve_params }, [100, 0]);
+}
+
+#[tokio::test]
+async fn test_register_pool_unsupported_curve() {
+    let mut test = setup().await;
+    assert_eq!(
+        register_pool(&mut test, 4).await.unwrap_err(),
+        custom_error(WayfinderError::InvalidPoolAccount)
+    );
+}
+
+#[tokio::test]
+async fn test_register_pool_owner_fee() {
+    let mut test = setup().await;
+    register_pool(&mut test, 5).await.unwrap();
+
+    let (_, pools) = common::registry(&mut test.context.banks_client, test.registry).await;
+    assert_eq!({ pools[0].fee_bps }, 30);
+    assert_eq!({ pools[0].curve_params }, [5, 0]);
+}
+
+#[tokio::test]
+async fn test_register_pool_fractional_fee() {
+    let mut test = setup().await;
+    assert_eq!(
+        register_pool(&mut test, 6).await.unwrap_err(),
+        custom_error(WayfinderError::InvalidPoolAccount)
+    );
+}
+
+#[tokio::test]
+async fn test_register_pool_duplicate() {
+    let mut test = setup().await;
+    register_pool(&mut test, 0).await.unwrap();
+    assert_eq!(
+        register_pool(&mut test, 0).await.unwrap_err(),
+        custom_error(WayfinderError::PoolAlreadyRegistered)
+    );
+}
+
+#[tokio::test]
+async fn test_register_pool_registry_full() {
+    let mut test = setup().await;
+    register_pool(&mut test, 0).await.unwrap();
+    register_pool(&mut test, 1).await.unwrap();
+    assert_eq!(
+        register_pool(&mut test, 2).await.unwrap_err(),
+        custom_error(WayfinderError::RegistryFull)
+    );
+}
+
+#[tokio::test]
+async fn test_register_pool_mint_mismatch() {
+    let mut test = setup().await;
+    let authority = test.authority.insecure_clone();
+    let mint_a = test.pools[0].mint_a;
+    assert_eq!(
+        register(&mut test, 0, &authority, mint_a, Pubkey::new_unique())
+            .await
+            .unwrap_err(),
+        custom_error(WayfinderError::InvalidPoolAccount)
+    );
+}
+
+#[tokio::test]
+async fn test_register_pool_wrong_authority() {
+    let mut test = setup().await;
+    let (mint_a, mint_b) = (test.pools[0].mint_a, test.pools[0].mint_b);
+    assert_eq!(
+        register(&mut test, 0, &Keypair::new(), mint_a, mint_b)
+            .await
+            .unwrap_err(),
+        custom_error(WayfinderError::AccountNotSigner)
+    );
+}
+
+#[tokio::test]
+async fn test_update_pool() {
+    let mut test = setup().await;
+    register_pool(&mut test, 0).await.unwrap();
+
+    let instruction = Instruction {
+        program_id: test.program_id,
+        accounts: pool_accounts(&test, &test.pools[0], &test.authority.pubkey()),
+        data: WayfinderInstruction::UpdatePool {
+            status: POOL_STATUS_PAUSED,
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+    let authority = test.authority.insecure_clone();
+    process_instruction(&mut test.context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    let (_, pools) = common::registry(&mut test.context.banks_client, test.registry).await;
+    assert_eq!({ pools[0].fee_bps }, 25);
+    assert_eq!(pools[0].status, POOL_STATUS_PAUSED);
+}
+
+#[tokio::test]
+async fn test_deregister_pool() {
+    let mut test = setup().await;
+    register_pool(&mut test, 0).await.unwrap();
+    register_pool(&mut test, 1).await.unwrap();
+
+    let deregister = |test: &RegistryTest| Instruction {
+        program_id: test.program_id,
+        accounts: vec![
+            AccountMeta::new(test.registry, false),
+            AccountMeta::new_readonly(test.authority.pubkey(), true),
+            AccountMeta::new_readonly(test.pools[0].address, false),
+        ],
+        data: WayfinderInstruction::DeregisterPool.try_to_vec().unwrap(),
+    };
+    let authority = test.authority.insecure_clone();
+    let instruction = deregister(&test);
+    process_instruction(&mut test.context, instruction, &[&authority])
+        .await
+        .unwrap();
+
+    let (_, pools) = common::registry(&mut test.context.banks_client, test.registry).await;
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].address, test.pools[1].address);
+
+    let instruction = deregister(&test);
+    assert_eq!(
+        process_instruction(&mut test.context, instruction, &[&authority])
+            .await
+            .unwrap_err(),
+        custom_error(WayfinderError::PoolNotRegistered)
+    );
+}
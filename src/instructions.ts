@@ -7,11 +7,26 @@ import {
 import BN from 'bn.js';
 import { serialize } from 'borsh';
 
+export const TOKEN_PROGRAM_ID = new PublicKey(
+  'TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA'
+);
+
+export const TOKEN_2022_PROGRAM_ID = new PublicKey(
+  'TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb'
+);
+
 export enum WayfinderInstructionType {
   InitializeRoute = 0,
   FindOptimalRoute = 1,
   ExecuteRoute = 2,
   RegisterPool = 3,
+  InitializeRegistry = 4,
+  UpdatePool = 5,
+  DeregisterPool = 6,
+  CloseRoute = 7,
+  MigrateAccount = 8,
+  FindSplitRoute = 9,
+  InitializeExactOutRoute = 10,
 }
 
 export class InitializeRouteInstruction {
@@ -21,38 +36,81 @@ export class InitializeRouteInstruction {
   amountIn: BN;
   minAmountOut: BN;
   maxHops: number;
+  nonce: BN;
 
   constructor(
     inputMint: PublicKey,
     outputMint: PublicKey,
     amountIn: BN,
     minAmountOut: BN,
-    maxHops: number
+    maxHops: number,
+    nonce: BN
   ) {
     this.inputMint = inputMint.toBytes();
     this.outputMint = outputMint.toBytes();
     this.amountIn = amountIn;
     this.minAmountOut = minAmountOut;
     this.maxHops = maxHops;
+    this.nonce = nonce;
   }
 }
 
+export class InitializeExactOutRouteInstruction {
+  tag: number = WayfinderInstructionType.InitializeExactOutRoute;
+  inputMint: Uint8Array;
+  outputMint: Uint8Array;
+  amountOut: BN;
+  maxAmountIn: BN;
+  maxHops: number;
+  nonce: BN;
+
+  constructor(
+    inputMint: PublicKey,
+    outputMint: PublicKey,
+    amountOut: BN,
+    maxAmountIn: BN,
+    maxHops: number,
+    nonce: BN
+  ) {
+    this.inputMint = inputMint.toBytes();
+    this.outputMint = outputMint.toBytes();
+    this.amountOut = amountOut;
+    this.maxAmountIn = maxAmountIn;
+    this.maxHops = maxHops;
+    this.nonce = nonce;
+  }
+}
+
+export function findRouteStateAddress(
+  programId: PublicKey,
+  authority: PublicKey,
+  nonce: BN
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('route'), authority.toBuffer(), nonce.toArrayLike(Buffer, 'le', 8)],
+    programId
+  );
+}
+
 export function createInitializeRouteInstruction(
   programId: PublicKey,
   routeState: PublicKey,
   authority: PublicKey,
+  poolRegistry: PublicKey,
   inputMint: PublicKey,
   outputMint: PublicKey,
   amountIn: BN,
   minAmountOut: BN,
-  maxHops: number
+  maxHops: number,
+  nonce: BN
 ): TransactionInstruction {
   const instruction = new InitializeRouteInstruction(
     inputMint,
     outputMint,
     amountIn,
     minAmountOut,
-    maxHops
+    maxHops,
+    nonce
   );
 
   const data = Buffer.from(serialize(instruction));
@@ -60,8 +118,44 @@ export function createInitializeRouteInstruction(
   return new TransactionInstruction({
     keys: [
       { pubkey: routeState, isSigner: false, isWritable: true },
-      { pubkey: authority, isSigner: true, isWritable: false },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: poolRegistry, isSigner: false, isWritable: false },
+    ],
+    programId,
+    data,
+  });
+}
+
+export function createInitializeExactOutRouteInstruction(
+  programId: PublicKey,
+  routeState: PublicKey,
+  authority: PublicKey,
+  poolRegistry: PublicKey,
+  inputMint: PublicKey,
+  outputMint: PublicKey,
+  amountOut: BN,
+  maxAmountIn: BN,
+  maxHops: number,
+  nonce: BN
+): TransactionInstruction {
+  const instruction = new InitializeExactOutRouteInstruction(
+    inputMint,
+    outputMint,
+    amountOut,
+    maxAmountIn,
+    maxHops,
+    nonce
+  );
+
+  const data = Buffer.from(serialize(instruction));
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: routeState, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: true },
       { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: poolRegistry, isSigner: false, isWritable: false },
     ],
     programId,
     data,
@@ -71,14 +165,20 @@ export function createInitializeRouteInstruction(
 export function createFindOptimalRouteInstruction(
   programId: PublicKey,
   routeState: PublicKey,
-  poolAccounts: PublicKey[]
+  authority: PublicKey,
+  poolRegistry: PublicKey,
+  transferFeeMints: PublicKey[] = []
 ): TransactionInstruction {
   const data = Buffer.from([WayfinderInstructionType.FindOptimalRoute]);
 
+  // Token-2022 mints of the route's tokens; the search avoids those with a
+  // transfer fee, which token-swap cannot move
   const keys = [
     { pubkey: routeState, isSigner: false, isWritable: true },
-    ...poolAccounts.map((pool) => ({
-      pubkey: pool,
+    { pubkey: authority, isSigner: true, isWritable: false },
+    { pubkey: poolRegistry, isSigner: false, isWritable: false },
+    ...transferFeeMints.map((mint) => ({
+      pubkey: mint,
       isSigner: false,
       isWritable: false,
     })),
@@ -106,6 +206,8 @@ export function createExecuteRouteInstruction(
     { pubkey: authority, isSigner: true, isWritable: false },
     { pubkey: userInputTokenAccount, isSigner: false, isWritable: true },
     { pubkey: userOutputTokenAccount, isSigner: false, isWritable: true },
+    { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+    { pubkey: TOKEN_2022_PROGRAM_ID, isSigner: false, isWritable: false },
     ...poolAndTokenAccounts.map((account) => ({
       pubkey: account,
       isSigner: false,
@@ -124,12 +226,10 @@ export class RegisterPoolInstruction {
   tag: number = WayfinderInstructionType.RegisterPool;
   tokenAMint: Uint8Array;
   tokenBMint: Uint8Array;
-  feeBps: number;
 
-  constructor(tokenAMint: PublicKey, tokenBMint: PublicKey, feeBps: number) {
+  constructor(tokenAMint: PublicKey, tokenBMint: PublicKey) {
     this.tokenAMint = tokenAMint.toBytes();
     this.tokenBMint = tokenBMint.toBytes();
-    this.feeBps = feeBps;
   }
 }
 
@@ -138,11 +238,12 @@ export function createRegisterPoolInstruction(
   poolRegistry: PublicKey,
   authority: PublicKey,
   poolAccount: PublicKey,
+  poolVaultA: PublicKey,
+  poolVaultB: PublicKey,
   tokenAMint: PublicKey,
-  tokenBMint: PublicKey,
-  feeBps: number
+  tokenBMint: PublicKey
 ): TransactionInstruction {
-  const instruction = new RegisterPoolInstruction(tokenAMint, tokenBMint, feeBps);
+  const instruction = new RegisterPoolInstruction(tokenAMint, tokenBMint);
   const data = Buffer.from(serialize(instruction));
 
   return new TransactionInstruction({
@@ -150,9 +251,86 @@ export function createRegisterPoolInstruction(
       { pubkey: poolRegistry, isSigner: false, isWritable: true },
       { pubkey: authority, isSigner: true, isWritable: false },
       { pubkey: poolAccount, isSigner: false, isWritable: false },
+      { pubkey: poolVaultA, isSigner: false, isWritable: false },
+      { pubkey: poolVaultB, isSigner: false, isWritable: false },
+    ],
+    programId,
+    data,
+  });
+}
+
+
+export function createFindSplitRouteInstruction(
+  programId: PublicKey,
+  routeState: PublicKey,
+  authority: PublicKey,
+  poolRegistry: PublicKey,
+  maxPaths: number,
+  transferFeeMints: PublicKey[] = []
+): TransactionInstruction {
+  const data = Buffer.from([WayfinderInstructionType.FindSplitRoute, maxPaths]);
+
+  const keys = [
+    { pubkey: routeState, isSigner: false, isWritable: true },
+    { pubkey: authority, isSigner: true, isWritable: false },
+    { pubkey: poolRegistry, isSigner: false, isWritable: false },
+    ...transferFeeMints.map((mint) => ({
+      pubkey: mint,
+      isSigner: false,
+      isWritable: false,
+    })),
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId,
+    data,
+  });
+}
+
+export function createCloseRouteInstruction(
+  programId: PublicKey,
+  routeState: PublicKey,
+  authority: PublicKey,
+  destination: PublicKey
+): TransactionInstruction {
+  const data = Buffer.from([WayfinderInstructionType.CloseRoute]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: routeState, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+      { pubkey: destination, isSigner: false, isWritable: true },
     ],
     programId,
     data,
   });
 }
 
+/**
+ * Migrate a route state or pool registry account to the current layout.
+ * Route states are pinned to `poolRegistry`, which registries leave out.
+ */
+export function createMigrateAccountInstruction(
+  programId: PublicKey,
+  account: PublicKey,
+  authority: PublicKey,
+  poolRegistry?: PublicKey
+): TransactionInstruction {
+  const data = Buffer.from([WayfinderInstructionType.MigrateAccount]);
+
+  const keys = [
+    { pubkey: account, isSigner: false, isWritable: true },
+    { pubkey: authority, isSigner: true, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+  ];
+  if (poolRegistry) {
+    keys.push({ pubkey: poolRegistry, isSigner: false, isWritable: false });
+  }
+
+  return new TransactionInstruction({
+    keys,
+    programId,
+    data,
+  });
+}
@@ -1,12 +1,61 @@
 import { PublicKey } from '@solana/web3.js';
 import BN from 'bn.js';
-import { deserialize, serialize, field, vec } from 'borsh';
+import { deserialize, serialize, field, fixedArray } from 'borsh';
 
 export const MAX_ROUTE_HOPS = 5;
+export const MAX_SPLIT_PATHS = 3;
+
+export enum AccountType {
+  RouteState = 3,
+  PoolRegistry = 4,
+}
+
+export const ROUTE_STATE_VERSION = 1;
+export const POOL_REGISTRY_VERSION = 1;
+
+export enum CurveType {
+  /** `curveParams[0]` is the basis points of the fee going to the pool owner */
+  ConstantProduct = 0,
+  /** `curveParams[0]` is the amplification coefficient */
+  StableSwap = 1,
+  /** `curveParams` are the input and output weights of token A to B */
+  Weighted = 2,
+}
+
+export enum RouteMode {
+  /** Spend exactly `amountIn`, receiving at least `minAmountOut` */
+  ExactIn = 0,
+  /** Buy at least `minAmountOut`, spending at most `amountIn` */
+  ExactOut = 1,
+}
+
+export class RoutePath {
+  @field({ type: 'u64' })
+  amountIn: BN = new BN(0);
+
+  @field({ type: 'u8' })
+  hops: number = 0;
+
+  @field({ type: fixedArray('publicKey', MAX_ROUTE_HOPS) })
+  route: PublicKey[] = new Array(MAX_ROUTE_HOPS).fill(PublicKey.default);
+
+  constructor(fields?: { amountIn?: BN; hops?: number; route?: PublicKey[] }) {
+    if (fields) {
+      Object.assign(this, fields);
+    }
+  }
+
+  getRoute(): PublicKey[] {
+    return this.route.slice(0, Math.min(this.hops, MAX_ROUTE_HOPS));
+  }
+}
 
 export class RouteState {
   @field({ type: 'u8' })
-  discriminator: number = 0;
+  accountType: number = 0;
+
+  @field({ type: 'u8' })
+  version: number = 0;
 
   @field({ type: 'publicKey' })
   inputMint: PublicKey = PublicKey.default;
@@ -21,10 +70,13 @@ export class RouteState {
   minAmountOut: BN = new BN(0);
 
   @field({ type: 'u8' })
-  hops: number = 0;
+  maxHops: number = 0;
 
-  @field({ type: vec('publicKey') })
-  route: PublicKey[] = [];
+  @field({ type: 'u8' })
+  pathCount: number = 0;
+
+  @field({ type: fixedArray(RoutePath, MAX_SPLIT_PATHS) })
+  paths: RoutePath[] = Array.from({ length: MAX_SPLIT_PATHS }, () => new RoutePath());
 
   @field({ type: 'u8' })
   status: number = 0;
@@ -32,22 +84,40 @@ export class RouteState {
   @field({ type: 'publicKey' })
   authority: PublicKey = PublicKey.default;
 
+  @field({ type: 'publicKey' })
+  registry: PublicKey = PublicKey.default;
+
+  @field({ type: 'u8' })
+  mode: number = RouteMode.ExactIn;
+
+  @field({ type: 'u8' })
+  possiblySuboptimal: number = 0;
+
   constructor(fields?: {
-    discriminator?: number;
+    accountType?: number;
+    version?: number;
     inputMint?: PublicKey;
     outputMint?: PublicKey;
     amountIn?: BN;
     minAmountOut?: BN;
-    hops?: number;
-    route?: PublicKey[];
+    maxHops?: number;
+    pathCount?: number;
+    paths?: RoutePath[];
     status?: number;
     authority?: PublicKey;
+    registry?: PublicKey;
+    mode?: number;
+    possiblySuboptimal?: number;
   }) {
     if (fields) {
       Object.assign(this, fields);
     }
   }
 
+  getPaths(): RoutePath[] {
+    return this.paths.slice(0, Math.min(this.pathCount, MAX_SPLIT_PATHS));
+  }
+
   static fromBuffer(buffer: Buffer): RouteState {
     return deserialize(RouteState, buffer);
   }
@@ -76,6 +146,15 @@ export class PoolInfoState {
   @field({ type: 'u64' })
   reserveB: BN = new BN(0);
 
+  @field({ type: 'u8' })
+  status: number = 0;
+
+  @field({ type: 'u8' })
+  curveType: number = CurveType.ConstantProduct;
+
+  @field({ type: fixedArray('u64', 2) })
+  curveParams: BN[] = [new BN(0), new BN(0)];
+
   constructor(fields?: {
     address?: PublicKey;
     tokenA?: PublicKey;
@@ -83,6 +162,9 @@ export class PoolInfoState {
     feeBps?: number;
     reserveA?: BN;
     reserveB?: BN;
+    status?: number;
+    curveType?: number;
+    curveParams?: BN[];
   }) {
     if (fields) {
       Object.assign(this, fields);
@@ -93,7 +175,8 @@ export class PoolInfoState {
     const isTokenA = inputMint.equals(this.tokenA);
     const isTokenB = inputMint.equals(this.tokenB);
 
-    if (!isTokenA && !isTokenB) {
+    // Only constant product pools are priced client-side
+    if ((!isTokenA && !isTokenB) || this.curveType !== CurveType.ConstantProduct) {
       return null;
     }
 
@@ -109,6 +192,28 @@ export class PoolInfoState {
     return numerator.div(denominator);
   }
 
+  /** Smallest input for which `getOutputAmount` returns at least `amountOut` */
+  getInputAmount(inputMint: PublicKey, amountOut: BN): BN | null {
+    const isTokenA = inputMint.equals(this.tokenA);
+    const isTokenB = inputMint.equals(this.tokenB);
+
+    // Only constant product pools are priced client-side
+    if ((!isTokenA && !isTokenB) || this.curveType !== CurveType.ConstantProduct) {
+      return null;
+    }
+
+    const reserveIn = isTokenA ? this.reserveA : this.reserveB;
+    const reserveOut = isTokenA ? this.reserveB : this.reserveA;
+    if (amountOut.gte(reserveOut) || this.feeBps >= 10000) {
+      return null;
+    }
+
+    // Round up: amountIn = amountOut * reserveIn * 10000 / ((reserveOut - amountOut) * (10000 - feeBps))
+    const numerator = amountOut.mul(reserveIn).mul(new BN(10000));
+    const denominator = reserveOut.sub(amountOut).mul(new BN(10000 - this.feeBps));
+    return numerator.add(denominator).subn(1).div(denominator);
+  }
+
   getOtherToken(token: PublicKey): PublicKey | null {
     if (token.equals(this.tokenA)) {
       return this.tokenB;
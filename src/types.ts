@@ -8,6 +8,9 @@ export interface PoolInfo {
   feeBps: number;
   reserveA: BN;
   reserveB: BN;
+  status?: number;
+  curveType?: number;
+  curveParams?: BN[];
 }
 
 export interface RouteConfig {
@@ -16,6 +19,18 @@ export interface RouteConfig {
   amountIn: BN;
   minAmountOut: BN;
   maxHops: number;
+  /** Pool registry the route is searched in */
+  poolRegistry: PublicKey;
+}
+
+export interface ExactOutRouteConfig {
+  inputMint: PublicKey;
+  outputMint: PublicKey;
+  amountOut: BN;
+  maxAmountIn: BN;
+  maxHops: number;
+  /** Pool registry the route is searched in */
+  poolRegistry: PublicKey;
 }
 
 export interface RouteResult {
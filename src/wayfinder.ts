@@ -9,12 +9,21 @@ import {
 import BN from 'bn.js';
 import {
   createInitializeRouteInstruction,
+  createInitializeExactOutRouteInstruction,
   createFindOptimalRouteInstruction,
+  createFindSplitRouteInstruction,
   createExecuteRouteInstruction,
+  findRouteStateAddress,
 } from './instructions';
 import { RouteState } from './state';
 import { AStarPathfinder } from './pathfinding';
-import { PoolInfo, RouteConfig, RouteResult, SwapQuote } from './types';
+import {
+  ExactOutRouteConfig,
+  PoolInfo,
+  RouteConfig,
+  RouteResult,
+  SwapQuote,
+} from './types';
 
 export class WayfinderClient {
   private connection: Connection;
@@ -27,19 +36,26 @@ export class WayfinderClient {
 
   async initializeRoute(
     authority: Keypair,
-    config: RouteConfig
+    config: RouteConfig,
+    nonce: BN = new BN(Date.now())
   ): Promise<PublicKey> {
-    const routeState = Keypair.generate();
+    const [routeState] = findRouteStateAddress(
+      this.programId,
+      authority.publicKey,
+      nonce
+    );
 
     const instruction = createInitializeRouteInstruction(
       this.programId,
-      routeState.publicKey,
+      routeState,
       authority.publicKey,
+      config.poolRegistry,
       config.inputMint,
       config.outputMint,
       config.amountIn,
       config.minAmountOut,
-      config.maxHops
+      config.maxHops,
+      nonce
     );
 
     const transaction = new Transaction().add(instruction);
@@ -47,23 +63,97 @@ export class WayfinderClient {
     await sendAndConfirmTransaction(
       this.connection,
       transaction,
-      [authority, routeState],
+      [authority],
       {
         commitment: 'confirmed',
       }
     );
 
-    return routeState.publicKey;
+    return routeState;
+  }
+
+  /**
+   * Initialize a route buying `config.amountOut`; finding it picks the route
+   * needing the least input, which must stay within `config.maxAmountIn`
+   */
+  async initializeExactOutRoute(
+    authority: Keypair,
+    config: ExactOutRouteConfig,
+    nonce: BN = new BN(Date.now())
+  ): Promise<PublicKey> {
+    const [routeState] = findRouteStateAddress(
+      this.programId,
+      authority.publicKey,
+      nonce
+    );
+
+    const instruction = createInitializeExactOutRouteInstruction(
+      this.programId,
+      routeState,
+      authority.publicKey,
+      config.poolRegistry,
+      config.inputMint,
+      config.outputMint,
+      config.amountOut,
+      config.maxAmountIn,
+      config.maxHops,
+      nonce
+    );
+
+    const transaction = new Transaction().add(instruction);
+
+    await sendAndConfirmTransaction(
+      this.connection,
+      transaction,
+      [authority],
+      {
+        commitment: 'confirmed',
+      }
+    );
+
+    return routeState;
   }
 
   async findOptimalRoute(
+    authority: Keypair,
     routeStateAddress: PublicKey,
-    pools: PublicKey[]
+    poolRegistry: PublicKey,
+    transferFeeMints: PublicKey[] = []
   ): Promise<void> {
     const instruction = createFindOptimalRouteInstruction(
       this.programId,
       routeStateAddress,
-      pools
+      authority.publicKey,
+      poolRegistry,
+      transferFeeMints
+    );
+
+    const transaction = new Transaction().add(instruction);
+
+    await sendAndConfirmTransaction(
+      this.connection,
+      transaction,
+      [authority],
+      {
+        commitment: 'confirmed',
+      }
+    );
+  }
+
+  async findSplitRoute(
+    authority: Keypair,
+    routeStateAddress: PublicKey,
+    poolRegistry: PublicKey,
+    maxPaths: number,
+    transferFeeMints: PublicKey[] = []
+  ): Promise<void> {
+    const instruction = createFindSplitRouteInstruction(
+      this.programId,
+      routeStateAddress,
+      authority.publicKey,
+      poolRegistry,
+      maxPaths,
+      transferFeeMints
     );
 
     const transaction = new Transaction().add(instruction);
@@ -71,7 +161,7 @@ export class WayfinderClient {
     await sendAndConfirmTransaction(
       this.connection,
       transaction,
-      [],
+      [authority],
       {
         commitment: 'confirmed',
       }